@@ -0,0 +1,115 @@
+//! Counts the heap allocations made by a call, run with `cargo bench --bench allocations`
+
+use std::{
+    alloc::{GlobalAlloc, Layout, System},
+    sync::{
+        Arc,
+        atomic::{AtomicUsize, Ordering},
+    },
+};
+
+use yaps_codecs::{JsonCodec, JsonData};
+use yaps_core::{FuncHandle, FuncProvider, local_hub::LocalHub, tokio::runtime::Builder};
+use yaps_macros::yaps_plugin;
+
+struct CountingAlloc;
+
+static ALLOCATIONS: AtomicUsize = AtomicUsize::new(0);
+
+unsafe impl GlobalAlloc for CountingAlloc {
+    unsafe fn alloc(&self, layout: Layout) -> *mut u8 {
+        ALLOCATIONS.fetch_add(1, Ordering::Relaxed);
+        unsafe { System.alloc(layout) }
+    }
+
+    unsafe fn dealloc(&self, ptr: *mut u8, layout: Layout) {
+        unsafe { System.dealloc(ptr, layout) }
+    }
+}
+
+#[global_allocator]
+static GLOBAL: CountingAlloc = CountingAlloc;
+
+#[yaps_plugin]
+mod adder {
+    #[derive(Default)]
+    pub struct Adder;
+
+    #[yaps_export]
+    impl Adder {
+        fn add(&self, a: i32, b: i32) -> i32 {
+            a + b
+        }
+
+        #[yaps_export(serial)]
+        fn serial_add(&self, a: i32, b: i32) -> i32 {
+            a + b
+        }
+    }
+}
+
+#[yaps_plugin]
+mod caller {
+    #[derive(Default)]
+    pub struct Caller;
+
+    #[yaps_extern]
+    impl Caller {
+        async fn add(&self, a: i32, b: i32) -> i32;
+    }
+}
+
+const CALLS: usize = 10_000;
+
+fn report(id: &str, before: usize) {
+    let allocations = ALLOCATIONS.load(Ordering::Relaxed) - before;
+    println!(
+        "{id}: {:.1} allocations per call",
+        allocations as f64 / CALLS as f64
+    );
+}
+
+fn main() {
+    let runtime = Builder::new_current_thread()
+        .enable_all()
+        .build()
+        .expect("the runtime should start");
+
+    runtime.block_on(async {
+        let mut hub = LocalHub::<JsonData>::new();
+        hub.add_plugin(adder::AdderWrapper::new(adder::Adder::default(), JsonCodec))
+            .await
+            .expect("the plugin should register");
+
+        let caller = Arc::new(caller::CallerWrapper::new(
+            caller::Caller::default(),
+            JsonCodec,
+        ));
+        hub.add_plugin(caller.clone())
+            .await
+            .expect("the plugin should register");
+
+        for id in ["add", "serial_add"] {
+            let handle = hub.get_func(id).await.expect("the function is exported");
+
+            let before = ALLOCATIONS.load(Ordering::Relaxed);
+            for _ in 0..CALLS {
+                let sum: i32 = handle
+                    .call_with_codec(&JsonCodec, (1, 2))
+                    .await
+                    .expect("the call should succeed");
+                assert_eq!(sum, 3);
+            }
+            report(id, before);
+        }
+
+        // Externs go through the consumer's extern trait object, then the resolved handle
+        let caller = caller.state().await;
+        let before = ALLOCATIONS.load(Ordering::Relaxed);
+        for _ in 0..CALLS {
+            let sum = caller.add(1, 2).await.expect("the call should succeed");
+            assert_eq!(sum, 3);
+        }
+        report("extern add", before);
+    });
+}
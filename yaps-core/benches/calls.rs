@@ -0,0 +1,54 @@
+use criterion::{Criterion, criterion_group, criterion_main};
+use yaps_codecs::{JsonCodec, JsonData};
+use yaps_core::{FuncHandle, FuncProvider, local_hub::LocalHub, tokio::runtime::Runtime};
+use yaps_macros::yaps_plugin;
+
+#[yaps_plugin]
+mod adder {
+    #[derive(Default)]
+    pub struct Adder;
+
+    #[yaps_export]
+    impl Adder {
+        fn add(&self, a: i32, b: i32) -> i32 {
+            a + b
+        }
+
+        #[yaps_export(serial)]
+        fn serial_add(&self, a: i32, b: i32) -> i32 {
+            a + b
+        }
+    }
+}
+
+/// Sync exports called inline compared to the same export going through its actor
+fn sync_export(c: &mut Criterion) {
+    let runtime = Runtime::new().expect("the runtime should start");
+    let (direct, serial) = runtime.block_on(async {
+        let mut hub = LocalHub::<JsonData>::new();
+        hub.add_plugin(adder::AdderWrapper::new(adder::Adder::default(), JsonCodec))
+            .await
+            .expect("the plugin should register");
+
+        let direct = hub.get_func("add").await.expect("add is exported");
+        let serial = hub
+            .get_func("serial_add")
+            .await
+            .expect("serial_add is exported");
+        (direct, serial)
+    });
+
+    let mut group = c.benchmark_group("sync_export");
+    group.bench_function("direct", |b| {
+        b.to_async(&runtime)
+            .iter(|| direct.call_with_codec::<_, _, i32>(&JsonCodec, (1, 2)))
+    });
+    group.bench_function("actor", |b| {
+        b.to_async(&runtime)
+            .iter(|| serial.call_with_codec::<_, _, i32>(&JsonCodec, (1, 2)))
+    });
+    group.finish();
+}
+
+criterion_group!(benches, sync_export);
+criterion_main!(benches);
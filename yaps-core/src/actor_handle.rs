@@ -1,37 +1,111 @@
 use crate::{
     Error, FuncHandle, Result, YapsData,
     codec::{Codec, DecodeFor, EncodeFor},
+    spawner::{self, AmbientSpawner, Spawner, Task},
 };
 
-use async_trait::async_trait;
-use std::{future::Future, pin::Pin, sync::Arc};
-use tokio::{
-    sync::{mpsc, oneshot},
-    task::JoinHandle,
+use futures::{
+    FutureExt,
+    future::{self, Either, select},
 };
+use std::{
+    future::Future,
+    pin::{Pin, pin},
+    sync::{
+        Arc,
+        atomic::{AtomicU64, Ordering},
+    },
+};
+use tokio::sync::{mpsc, oneshot};
+
+static NEXT_ACTOR_ID: AtomicU64 = AtomicU64::new(0);
+
+/// Actor waiting for a call to return
+#[derive(Debug, Clone)]
+struct CallFrame {
+    actor: u64,
+    name: Arc<str>,
+    // Address of the lock the actor takes, if any
+    lock: Option<usize>,
+}
+
+tokio::task_local! {
+    /// Actors up the call chain of the running call, outermost first
+    static CALL_CHAIN: Arc<[CallFrame]>;
+}
 
 struct ActorCall<D> {
     args: D,
+    chain: Arc<[CallFrame]>,
     tx_ret: oneshot::Sender<Result<D>>,
 }
 
+/// Handle to an actor running its function for one call at a time.
+///
+/// Calls made while handling a call carry the chain of actors waiting on them. A call coming
+/// back to an actor of that chain would wait for itself, so it fails with
+/// [`Error::Reentrancy`] instead. So does a call coming back to an actor taking the same lock as
+/// one of the chain, see [`ActorHandle::with_lock`]. Only calls awaited by the handling task are
+/// tracked, not the ones made from tasks it spawns.
+///
+/// Box it to resolve it as a `dyn` [`DynFuncHandle`](crate::DynFuncHandle).
 #[derive(Debug)]
 pub struct ActorHandle<D> {
+    id: u64,
+    name: Arc<str>,
+    lock: Option<usize>,
     tx_call: mpsc::UnboundedSender<ActorCall<D>>,
 }
 
 pub type AsyncResult<T> = Pin<Box<dyn Future<Output = Result<T>> + Send>>;
 
 impl<D: YapsData> ActorHandle<D> {
-    pub fn spawn<F>(func: F) -> Result<(Self, JoinHandle<Result<()>>)>
+    /// Spawns the actor on the ambient tokio runtime
+    pub fn spawn<F, Fut>(func: F) -> Result<(Self, Task<Result<()>>)>
     where
-        F: Fn(D) -> AsyncResult<D> + Send + Sync + 'static,
+        F: Fn(D) -> Fut + Send + Sync + 'static,
+        Fut: Future<Output = Result<D>> + Send + 'static,
     {
+        Self::spawn_on(func, &AmbientSpawner, future::pending())
+    }
+
+    /// Spawns the actor on `spawner`, it stops taking calls once `stop` completes.
+    ///
+    /// Calls queued before that are still answered, then the task exits.
+    pub fn spawn_on<F, Fut, S>(
+        func: F,
+        spawner: &dyn Spawner,
+        stop: S,
+    ) -> Result<(Self, Task<Result<()>>)>
+    where
+        F: Fn(D) -> Fut + Send + Sync + 'static,
+        Fut: Future<Output = Result<D>> + Send + 'static,
+        S: Future<Output = ()> + Send + 'static,
+    {
+        let id = NEXT_ACTOR_ID.fetch_add(1, Ordering::Relaxed);
         let (tx_call, mut rx_call) = mpsc::unbounded_channel::<ActorCall<D>>();
 
-        let join_handle = tokio::spawn(async move {
-            while let Some(call) = rx_call.recv().await {
-                let result = func(call.args).await;
+        let task = spawner::spawn(spawner, async move {
+            // Once fused, the stop signal stays pending after completing
+            let mut stop = pin!(stop.fuse());
+
+            loop {
+                let next = match select(pin!(rx_call.recv()), stop.as_mut()).await {
+                    Either::Left((call, _)) => Some(call),
+                    Either::Right(_) => None,
+                };
+
+                // Closing lets the queued calls through, then `recv` returns None
+                let Some(call) = next else {
+                    rx_call.close();
+                    continue;
+                };
+
+                let Some(call) = call else {
+                    break;
+                };
+
+                let result = CALL_CHAIN.scope(call.chain, func(call.args)).await;
 
                 if call.tx_ret.send(result).is_err() {
                     // TODO: Log return send failure
@@ -40,39 +114,100 @@ impl<D: YapsData> ActorHandle<D> {
             Ok(())
         });
 
-        Ok((Self { tx_call }, join_handle))
+        let handle = Self {
+            id,
+            name: Arc::from(format!("actor#{id}")),
+            lock: None,
+            tx_call,
+        };
+        Ok((handle, task))
+    }
+
+    /// Names the actor in [`Error::Reentrancy`] chains, usually after its function's id
+    pub fn with_name(mut self, name: impl Into<String>) -> Self {
+        self.name = Arc::from(name.into());
+        self
+    }
+
+    /// Marks the actor as taking `lock` while handling a call, e.g. the state of a plugin.
+    ///
+    /// A call awaited while holding it and coming back to an actor taking the same lock could
+    /// wait for the lock forever, so it fails with [`Error::Reentrancy`].
+    pub fn with_lock<T: ?Sized>(mut self, lock: &Arc<T>) -> Self {
+        self.lock = Some(lock_address(lock));
+        self
+    }
+
+    pub fn spawn_with_codec<C, F, Fut, A, R>(
+        func: F,
+        codec: Arc<C>,
+    ) -> Result<(Self, Task<Result<()>>)>
+    where
+        C: Codec<Data = D> + DecodeFor<C, A> + EncodeFor<C, R> + 'static,
+        F: Fn(A) -> Fut + Send + Sync + 'static,
+        Fut: Future<Output = Result<R>> + Send + 'static,
+    {
+        Self::spawn_with_codec_on(func, codec, &AmbientSpawner, future::pending())
     }
 
-    pub fn spawn_with_codec<C, F, A, R>(
+    /// Like [`ActorHandle::spawn_with_codec`], spawning like [`ActorHandle::spawn_on`]
+    pub fn spawn_with_codec_on<C, F, Fut, A, R, S>(
         func: F,
         codec: Arc<C>,
-    ) -> Result<(Self, JoinHandle<Result<()>>)>
+        spawner: &dyn Spawner,
+        stop: S,
+    ) -> Result<(Self, Task<Result<()>>)>
     where
         C: Codec<Data = D> + DecodeFor<C, A> + EncodeFor<C, R> + 'static,
-        F: Fn(A) -> AsyncResult<R> + Send + Sync + 'static,
+        F: Fn(A) -> Fut + Send + Sync + 'static,
+        Fut: Future<Output = Result<R>> + Send + 'static,
+        S: Future<Output = ()> + Send + 'static,
     {
         let func = Arc::new(func);
 
-        let codec_func = move |args| -> AsyncResult<D> {
+        let codec_func = move |args| {
             let codec = codec.clone();
             let func = func.clone();
 
-            Box::pin(async move {
+            async move {
                 let args = codec.decode(args)?;
                 let result = func(args).await?;
                 codec.encode(result)
-            })
+            }
         };
 
-        Self::spawn(codec_func)
+        Self::spawn_on(codec_func, spawner, stop)
     }
 }
 
-#[async_trait]
 impl<D: YapsData> FuncHandle<D> for ActorHandle<D> {
     async fn call(&self, args: D) -> Result<D> {
+        let chain = CALL_CHAIN.try_with(Arc::clone).unwrap_or_default();
+        let reentrant = chain
+            .iter()
+            .any(|frame| frame.actor == self.id || self.lock.is_some() && frame.lock == self.lock);
+        let chain: Arc<[CallFrame]> = chain
+            .iter()
+            .cloned()
+            .chain([CallFrame {
+                actor: self.id,
+                name: self.name.clone(),
+                lock: self.lock,
+            }])
+            .collect();
+
+        if reentrant {
+            return Err(Error::Reentrancy {
+                chain: chain.iter().map(|frame| frame.name.to_string()).collect(),
+            });
+        }
+
         let (tx, rx) = oneshot::channel();
-        let call = ActorCall { args, tx_ret: tx };
+        let call = ActorCall {
+            args,
+            chain,
+            tx_ret: tx,
+        };
         self.tx_call
             .send(call)
             .map_err(|e| Error::ChannelSend(e.to_string()))?;
@@ -80,3 +215,26 @@ impl<D: YapsData> FuncHandle<D> for ActorHandle<D> {
         rx.await.map_err(|_| Error::HandlerInvalidated)?
     }
 }
+
+pub(crate) fn lock_address<T: ?Sized>(lock: &Arc<T>) -> usize {
+    Arc::as_ptr(lock) as *const () as usize
+}
+
+/// Fails with [`Error::Reentrancy`] if an actor up the running call chain takes the lock at
+/// address `lock`, calling the function named `name` would wait for it
+pub(crate) fn check_lock(lock: usize, name: &str) -> Result<()> {
+    let lock = Some(lock);
+    let chain = CALL_CHAIN.try_with(Arc::clone).unwrap_or_default();
+
+    if !chain.iter().any(|frame| frame.lock == lock) {
+        return Ok(());
+    }
+
+    Err(Error::Reentrancy {
+        chain: chain
+            .iter()
+            .map(|frame| frame.name.to_string())
+            .chain([name.to_string()])
+            .collect(),
+    })
+}
@@ -0,0 +1,194 @@
+use crate::{
+    Error, FuncHandle, Result, YapsData, shared_handle::SharedHandle, stream_handle::DataStream,
+};
+
+use arc_swap::ArcSwap;
+use futures::StreamExt;
+use std::sync::{
+    Arc,
+    atomic::{AtomicUsize, Ordering},
+};
+
+/// How a [`BalancedHandle`] picks the provider for a call
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum BalanceStrategy {
+    /// Each provider in turn
+    #[default]
+    RoundRobin,
+    /// The provider with the fewest calls in progress
+    LeastOutstanding,
+    /// A provider picked at random
+    Random,
+}
+
+struct Slot<D> {
+    handle: SharedHandle<D>,
+    outstanding: AtomicUsize,
+}
+
+/// Keeps a call counted as outstanding until it completes or gets cancelled
+struct Outstanding<D>(Arc<Slot<D>>);
+
+impl<D> Outstanding<D> {
+    fn start(slot: &Arc<Slot<D>>) -> Self {
+        slot.outstanding.fetch_add(1, Ordering::SeqCst);
+        Self(slot.clone())
+    }
+}
+
+impl<D> Drop for Outstanding<D> {
+    fn drop(&mut self) {
+        self.0.outstanding.fetch_sub(1, Ordering::SeqCst);
+    }
+}
+
+/// Keeps the stream counted as outstanding for as long as it's alive
+fn outstanding_stream<D: YapsData>(
+    stream: DataStream<D>,
+    outstanding: Outstanding<D>,
+) -> DataStream<D> {
+    stream
+        .map(move |item| {
+            let _ = &outstanding;
+            item
+        })
+        .boxed()
+}
+
+/// Handle spreading calls over several providers of the same function.
+///
+/// A provider returning [`Error::HandlerInvalidated`] is taken out of rotation, the call that hit
+/// it still fails with that error. Calls aren't retried, they may have partially run already.
+///
+/// Streams and channels go to a single provider, they're outstanding until they're dropped.
+pub struct BalancedHandle<D: YapsData> {
+    id: String,
+    strategy: BalanceStrategy,
+    slots: ArcSwap<Vec<Arc<Slot<D>>>>,
+    next: AtomicUsize,
+}
+
+impl<D: YapsData> std::fmt::Debug for BalancedHandle<D> {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("BalancedHandle")
+            .field("id", &self.id)
+            .field("strategy", &self.strategy)
+            .field("providers", &self.len())
+            .finish()
+    }
+}
+
+impl<D: YapsData> BalancedHandle<D> {
+    pub fn new(
+        id: impl Into<String>,
+        strategy: BalanceStrategy,
+        handles: impl IntoIterator<Item = impl Into<SharedHandle<D>>>,
+    ) -> Self {
+        let slots = handles
+            .into_iter()
+            .map(|handle| {
+                Arc::new(Slot {
+                    handle: handle.into(),
+                    outstanding: AtomicUsize::new(0),
+                })
+            })
+            .collect();
+
+        Self {
+            id: id.into(),
+            strategy,
+            slots: ArcSwap::from_pointee(slots),
+            next: AtomicUsize::new(0),
+        }
+    }
+
+    pub fn id(&self) -> &str {
+        &self.id
+    }
+
+    pub fn strategy(&self) -> BalanceStrategy {
+        self.strategy
+    }
+
+    /// Number of providers still in rotation
+    pub fn len(&self) -> usize {
+        self.slots.load().len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.slots.load().is_empty()
+    }
+
+    /// Picks the provider of the next call
+    fn next_slot(&self) -> Result<Arc<Slot<D>>> {
+        let slots = self.slots.load();
+
+        if slots.is_empty() {
+            return Err(Error::FunctionNotFound(self.id.clone()));
+        }
+
+        Ok(slots[self.pick(&slots)].clone())
+    }
+
+    fn pick(&self, slots: &[Arc<Slot<D>>]) -> usize {
+        let start = self.next.fetch_add(1, Ordering::Relaxed);
+
+        match self.strategy {
+            BalanceStrategy::RoundRobin => start % slots.len(),
+            // Ties go to the provider round-robin would have picked
+            BalanceStrategy::LeastOutstanding => (0..slots.len())
+                .map(|i| (start + i) % slots.len())
+                .min_by_key(|&i| slots[i].outstanding.load(Ordering::SeqCst))
+                .unwrap_or_default(),
+            BalanceStrategy::Random => fastrand::usize(..slots.len()),
+        }
+    }
+
+    fn remove(&self, slot: &Arc<Slot<D>>) {
+        self.slots.rcu(|slots| {
+            slots
+                .iter()
+                .filter(|s| !Arc::ptr_eq(s, slot))
+                .cloned()
+                .collect::<Vec<_>>()
+        });
+    }
+
+    /// Takes the provider out of rotation if `result` shows it was invalidated
+    fn check<T>(&self, slot: &Arc<Slot<D>>, result: Result<T>) -> Result<T> {
+        if let Err(Error::HandlerInvalidated) = result {
+            self.remove(slot);
+        }
+
+        result
+    }
+}
+
+impl<D: YapsData> FuncHandle<D> for BalancedHandle<D> {
+    async fn call(&self, args: D) -> Result<D> {
+        let slot = self.next_slot()?;
+
+        let result = {
+            let _outstanding = Outstanding::start(&slot);
+            slot.handle.call(args).await
+        };
+
+        self.check(&slot, result)
+    }
+
+    async fn call_stream(&self, args: D) -> Result<DataStream<D>> {
+        let slot = self.next_slot()?;
+
+        let outstanding = Outstanding::start(&slot);
+        let stream = self.check(&slot, slot.handle.call_stream(args).await)?;
+        Ok(outstanding_stream(stream, outstanding))
+    }
+
+    async fn open_channel(&self, inputs: DataStream<D>) -> Result<DataStream<D>> {
+        let slot = self.next_slot()?;
+
+        let outstanding = Outstanding::start(&slot);
+        let stream = self.check(&slot, slot.handle.open_channel(inputs).await)?;
+        Ok(outstanding_stream(stream, outstanding))
+    }
+}
@@ -0,0 +1,130 @@
+use crate::{
+    Error, FuncHandle, Result, YapsData,
+    actor_handle::{check_lock, lock_address},
+    spawner::Spawner,
+};
+
+use std::sync::Arc;
+use tokio::sync::{Semaphore, oneshot};
+
+/// Default number of blocking exports of a plugin running at the same time
+pub const DEFAULT_BLOCKING_THREADS: usize = 4;
+
+/// Runs a plugin's blocking exports on the blocking threads of its spawner, keeping them off the
+/// workers.
+///
+/// At most `threads` of them run at the same time, the other calls wait for their turn.
+#[derive(Debug)]
+pub struct BlockingPool {
+    threads: usize,
+    permits: Arc<Semaphore>,
+}
+
+impl Default for BlockingPool {
+    fn default() -> Self {
+        Self::new(DEFAULT_BLOCKING_THREADS)
+    }
+}
+
+impl BlockingPool {
+    /// Creates a pool running up to `threads` calls at the same time (at least one)
+    pub fn new(threads: usize) -> Self {
+        let threads = threads.max(1);
+
+        Self {
+            threads,
+            permits: Arc::new(Semaphore::new(threads)),
+        }
+    }
+
+    pub fn threads(&self) -> usize {
+        self.threads
+    }
+
+    /// Runs `func` on a blocking thread of `spawner` once one of the pool's slots is free
+    pub async fn run<T, F>(&self, id: &str, spawner: &dyn Spawner, func: F) -> Result<T>
+    where
+        T: Send + 'static,
+        F: FnOnce() -> T + Send + 'static,
+    {
+        let permit = self
+            .permits
+            .clone()
+            .acquire_owned()
+            .await
+            .map_err(|_| Error::HandlerInvalidated)?;
+
+        let (tx, rx) = oneshot::channel();
+        spawner.spawn_blocking_boxed(Box::new(move || {
+            let _permit = permit;
+            // A panic drops the sender, reported as a failed task
+            let _ = tx.send(func());
+        }));
+
+        rx.await.map_err(|_| Error::TaskFailed(id.to_string()))
+    }
+}
+
+type BlockingFn<D> = Arc<dyn Fn(D) -> Result<D> + Send + Sync>;
+
+/// Handle running a blocking function on a [`BlockingPool`].
+///
+/// Calls aren't serialized: they run concurrently on the caller's task, up to the pool's number
+/// of threads. Box it to resolve it as a `dyn` [`DynFuncHandle`](crate::DynFuncHandle).
+pub struct PooledHandle<D> {
+    id: String,
+    pool: Arc<BlockingPool>,
+    spawner: Arc<dyn Spawner>,
+    lock: Option<usize>,
+    func: BlockingFn<D>,
+}
+
+impl<D> std::fmt::Debug for PooledHandle<D> {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("PooledHandle")
+            .field("id", &self.id)
+            .field("pool", &self.pool)
+            .field("spawner", &self.spawner)
+            .finish_non_exhaustive()
+    }
+}
+
+impl<D: YapsData> PooledHandle<D> {
+    pub fn new<F>(
+        id: impl Into<String>,
+        pool: Arc<BlockingPool>,
+        spawner: Arc<dyn Spawner>,
+        func: F,
+    ) -> Self
+    where
+        F: Fn(D) -> Result<D> + Send + Sync + 'static,
+    {
+        Self {
+            id: id.into(),
+            pool,
+            spawner,
+            lock: None,
+            func: Arc::new(func),
+        }
+    }
+
+    /// Marks the function as taking `lock`, see
+    /// [`ActorHandle::with_lock`](crate::actor_handle::ActorHandle::with_lock)
+    pub fn with_lock<T: ?Sized>(mut self, lock: &Arc<T>) -> Self {
+        self.lock = Some(lock_address(lock));
+        self
+    }
+}
+
+impl<D: YapsData> FuncHandle<D> for PooledHandle<D> {
+    async fn call(&self, args: D) -> Result<D> {
+        if let Some(lock) = self.lock {
+            check_lock(lock, &self.id)?;
+        }
+
+        let func = self.func.clone();
+        self.pool
+            .run(&self.id, self.spawner.as_ref(), move || func(args))
+            .await?
+    }
+}
@@ -0,0 +1,98 @@
+use crate::{
+    Error, FuncHandle, Result, YapsData,
+    codec::{Codec, DecodeFor, EncodeFor},
+    shared_handle::SharedHandle,
+    spawner::{RuntimeConfig, RuntimeSpawner},
+};
+
+use std::sync::{Arc, Mutex};
+use tokio::runtime::Handle;
+
+/// Fails if called from inside a runtime, where blocking on a call could deadlock it
+pub(crate) fn ensure_outside_runtime() -> Result<()> {
+    match Handle::try_current() {
+        Ok(_) => Err(Error::InsideRuntime),
+        Err(_) => Ok(()),
+    }
+}
+
+/// Runtime owned by a hub to drive blocking calls, only started once synchronous code needs it
+#[derive(Debug, Default)]
+pub(crate) struct HubRuntime {
+    runtime: Mutex<Option<Arc<RuntimeSpawner>>>,
+}
+
+impl HubRuntime {
+    pub(crate) fn get(&self) -> Result<Arc<RuntimeSpawner>> {
+        let mut runtime = self.runtime.lock().expect("hub runtime poisoned");
+
+        match runtime.as_ref() {
+            Some(runtime) => Ok(runtime.clone()),
+            None => {
+                let spawner = RuntimeSpawner::new(RuntimeConfig::new("yaps-hub"))?;
+                Ok(runtime.insert(Arc::new(spawner)).clone())
+            }
+        }
+    }
+
+    /// Resolves a function on the runtime into a [`BlockingHandle`]
+    pub(crate) fn get_func<D: YapsData>(
+        &self,
+        get_func: impl Future<Output = Result<SharedHandle<D>>>,
+    ) -> Result<BlockingHandle<D>> {
+        ensure_outside_runtime()?;
+
+        let runtime = self.get()?;
+        let func = runtime.handle().block_on(get_func)?;
+        Ok(BlockingHandle::new(func, runtime))
+    }
+}
+
+/// Handle callable from synchronous code, driving its calls on the hub's runtime.
+///
+/// Calls made from inside a runtime (including its blocking threads) are refused with
+/// [`Error::InsideRuntime`], async code should use the wrapped [`FuncHandle`] instead.
+/// Clones share the same handle, so one can be given to each thread.
+pub struct BlockingHandle<D> {
+    handle: SharedHandle<D>,
+    runtime: Arc<RuntimeSpawner>,
+}
+
+impl<D> Clone for BlockingHandle<D> {
+    fn clone(&self) -> Self {
+        Self {
+            handle: self.handle.clone(),
+            runtime: self.runtime.clone(),
+        }
+    }
+}
+
+impl<D> std::fmt::Debug for BlockingHandle<D> {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("BlockingHandle")
+            .field("runtime", &self.runtime)
+            .finish()
+    }
+}
+
+impl<D: YapsData> BlockingHandle<D> {
+    pub(crate) fn new(handle: SharedHandle<D>, runtime: Arc<RuntimeSpawner>) -> Self {
+        Self { handle, runtime }
+    }
+
+    pub fn call_blocking(&self, args: D) -> Result<D> {
+        ensure_outside_runtime()?;
+        self.runtime.handle().block_on(self.handle.call(args))
+    }
+
+    pub fn call_blocking_with_codec<C, A, R>(&self, codec: &C, args: A) -> Result<R>
+    where
+        A: Send,
+        C: Codec<Data = D> + EncodeFor<C, A> + DecodeFor<C, R>,
+    {
+        ensure_outside_runtime()?;
+        self.runtime
+            .handle()
+            .block_on(self.handle.call_with_codec(codec, args))
+    }
+}
@@ -0,0 +1,169 @@
+use crate::{
+    FuncHandle, Result, YapsData,
+    codec::{Codec, DecodeFor, EncodeFor},
+    shared_handle::SharedHandle,
+    stream_handle::DataStream,
+};
+
+use arc_swap::ArcSwap;
+use futures::{StreamExt, future::join_all, stream::BoxStream};
+use std::sync::Arc;
+
+/// Handle calling every provider of a function at once, e.g. for hook-style functions
+/// exported by several plugins.
+///
+/// Handles can be added while the broadcast handle is in use, calls already in progress keep
+/// the providers they started with.
+///
+/// Streaming functions and channels are broadcast with [`BroadcastHandle::call_stream_all_with`]
+/// and [`BroadcastHandle::open_channel_all_with`].
+pub struct BroadcastHandle<D: YapsData> {
+    id: String,
+    handles: ArcSwap<Vec<SharedHandle<D>>>,
+}
+
+impl<D: YapsData> std::fmt::Debug for BroadcastHandle<D> {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("BroadcastHandle")
+            .field("id", &self.id)
+            .field("providers", &self.len())
+            .finish()
+    }
+}
+
+impl<D: YapsData> BroadcastHandle<D> {
+    pub fn new(id: impl Into<String>) -> Self {
+        Self {
+            id: id.into(),
+            handles: ArcSwap::from_pointee(Vec::new()),
+        }
+    }
+
+    pub fn id(&self) -> &str {
+        &self.id
+    }
+
+    pub fn len(&self) -> usize {
+        self.handles.load().len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.handles.load().is_empty()
+    }
+
+    pub fn extend(&self, handles: impl IntoIterator<Item = impl Into<SharedHandle<D>>>) {
+        let handles: Vec<SharedHandle<D>> = handles.into_iter().map(Into::into).collect();
+
+        if handles.is_empty() {
+            return;
+        }
+
+        self.handles.rcu(|current| {
+            let mut current = Vec::clone(current);
+            current.extend(handles.iter().cloned());
+            current
+        });
+    }
+
+    /// Calls every provider concurrently with the arguments made by `args`, the results are in
+    /// the order the providers were added.
+    ///
+    /// Fails as a whole only if the arguments for a provider can't be made.
+    pub async fn call_all_with(
+        &self,
+        mut args: impl FnMut() -> Result<D>,
+    ) -> Result<Vec<Result<D>>> {
+        let handles = self.handles.load_full();
+
+        let calls = handles
+            .iter()
+            .map(|handle| Ok(handle.call(args()?)))
+            .collect::<Result<Vec<_>>>()?;
+
+        Ok(join_all(calls).await)
+    }
+
+    /// Like [`BroadcastHandle::call_all_with`], encoding the arguments for every provider.
+    ///
+    /// Fails as a whole only if the arguments can't be encoded.
+    pub async fn call_all_with_codec<C, A, R>(&self, codec: &C, args: A) -> Result<Vec<Result<R>>>
+    where
+        C: Codec<Data = D> + EncodeFor<C, A> + DecodeFor<C, R>,
+        A: Clone,
+    {
+        let results = self.call_all_with(|| codec.encode(args.clone())).await?;
+
+        Ok(results
+            .into_iter()
+            .map(|result| codec.decode(result?))
+            .collect())
+    }
+
+    /// Like [`BroadcastHandle::call_all_with`], calling every provider with
+    /// [`FuncHandle::call_stream`]
+    pub async fn call_stream_all_with(
+        &self,
+        mut args: impl FnMut() -> Result<D>,
+    ) -> Result<Vec<Result<DataStream<D>>>> {
+        let handles = self.handles.load_full();
+
+        let calls = handles
+            .iter()
+            .map(|handle| Ok(handle.call_stream(args()?)))
+            .collect::<Result<Vec<_>>>()?;
+
+        Ok(join_all(calls).await)
+    }
+
+    /// Like [`BroadcastHandle::call_stream_all_with`], encoding the arguments for every provider
+    /// and decoding each item as it arrives
+    pub async fn call_stream_all_with_codec<C, A, R>(
+        &self,
+        codec: Arc<C>,
+        args: A,
+    ) -> Result<Vec<Result<BoxStream<'static, Result<R>>>>>
+    where
+        C: Codec<Data = D> + EncodeFor<C, A> + DecodeFor<C, R> + 'static,
+        A: Clone,
+        R: Send + 'static,
+    {
+        let streams = self
+            .call_stream_all_with(|| codec.encode(args.clone()))
+            .await?;
+
+        Ok(streams
+            .into_iter()
+            .map(|stream| {
+                let codec = codec.clone();
+                Ok(stream?.map(move |item| codec.decode(item?)).boxed())
+            })
+            .collect())
+    }
+
+    /// Opens a channel with every provider concurrently, each getting the inputs made for it by
+    /// `inputs`. The outputs are in the order the providers were added.
+    ///
+    /// Fails as a whole only if the inputs for a provider can't be made.
+    pub async fn open_channel_all_with(
+        &self,
+        mut inputs: impl FnMut() -> Result<DataStream<D>>,
+    ) -> Result<Vec<Result<DataStream<D>>>> {
+        let handles = self.handles.load_full();
+
+        let channels = handles
+            .iter()
+            .map(|handle| Ok(handle.open_channel(inputs()?)))
+            .collect::<Result<Vec<_>>>()?;
+
+        Ok(join_all(channels).await)
+    }
+}
+
+impl<D: YapsData + Clone> BroadcastHandle<D> {
+    /// Calls every provider concurrently, the results are in the order the providers were added
+    pub async fn call_all(&self, args: D) -> Vec<Result<D>> {
+        self.call_all_with(|| Ok(args.clone()))
+            .await
+            .unwrap_or_default()
+    }
+}
@@ -0,0 +1,467 @@
+use crate::{
+    Error, Result,
+    actor_handle::AsyncResult,
+    shutdown::TaskTracker,
+    spawner::{self, BlockingTask, Spawner},
+};
+
+use futures::{
+    FutureExt,
+    future::{BoxFuture, Either, select},
+};
+use serde::{Deserialize, Deserializer, Serialize, Serializer, de};
+use std::{
+    any::Any,
+    collections::HashMap,
+    future::Future,
+    hash::{BuildHasher, RandomState},
+    pin::pin,
+    sync::{
+        Arc, Mutex, Weak,
+        atomic::{AtomicU64, Ordering},
+    },
+};
+use tokio::sync::{mpsc, oneshot};
+
+type Erased = dyn Any + Send + Sync;
+
+tokio::task_local! {
+    /// Scope of the plugin running the current task
+    static SCOPE: CallbackScope;
+}
+
+/// Reference a callback is serialized as.
+///
+/// The token is derived from randomly keyed hashes, so references can't be made up.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, Serialize, Deserialize)]
+#[serde(rename = "Callback")]
+struct CallbackId {
+    seq: u64,
+    token: u64,
+}
+
+struct Entry {
+    actor: Weak<Erased>,
+    // Received copies still alive, the callback ends once the last one is dropped
+    links: usize,
+}
+
+/// Callbacks created by the plugins of a hub, which the plugins of the hub can receive.
+///
+/// Hubs nested in each other link their tables, so callbacks can be passed between them.
+#[derive(Default)]
+pub struct CallbackTable {
+    keys: RandomState,
+    next_seq: AtomicU64,
+    entries: Mutex<HashMap<CallbackId, Entry>>,
+    linked: Mutex<Vec<Weak<CallbackTable>>>,
+}
+
+impl std::fmt::Debug for CallbackTable {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("CallbackTable")
+            .field("callbacks", &self.len())
+            .finish()
+    }
+}
+
+impl CallbackTable {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Number of callbacks that can currently be received
+    pub fn len(&self) -> usize {
+        self.entries().len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.entries().is_empty()
+    }
+
+    /// Lets the plugins attached to either table receive the callbacks of the other one
+    pub fn link(self: &Arc<Self>, other: &Arc<Self>) {
+        if Arc::ptr_eq(self, other) {
+            return;
+        }
+
+        for (table, linked) in [(self, other), (other, self)] {
+            let mut tables = table.linked.lock().expect("callback table lock poisoned");
+            tables.retain(|t| t.strong_count() > 0);
+
+            if !tables.iter().any(|t| t.as_ptr() == Arc::as_ptr(linked)) {
+                tables.push(Arc::downgrade(linked));
+            }
+        }
+    }
+
+    fn entries(&self) -> std::sync::MutexGuard<'_, HashMap<CallbackId, Entry>> {
+        self.entries.lock().expect("callback table lock poisoned")
+    }
+
+    fn next_id(&self) -> CallbackId {
+        let seq = self.next_seq.fetch_add(1, Ordering::Relaxed);
+        CallbackId {
+            seq,
+            token: self.keys.hash_one(seq),
+        }
+    }
+
+    fn register(&self, id: CallbackId, actor: Weak<Erased>) {
+        self.entries().insert(id, Entry { actor, links: 0 });
+    }
+
+    fn remove(&self, id: &CallbackId) {
+        self.entries().remove(id);
+    }
+
+    fn is_registered(&self, id: &CallbackId) -> bool {
+        self.entries()
+            .get(id)
+            .is_some_and(|entry| entry.actor.strong_count() > 0)
+    }
+
+    /// Counts a new received copy of the callback, looking in the linked tables as well
+    fn receive(self: &Arc<Self>, id: &CallbackId) -> Option<(Arc<Self>, Arc<Erased>)> {
+        let mut visited = Vec::new();
+        let mut pending = vec![self.clone()];
+
+        while let Some(table) = pending.pop() {
+            if visited.iter().any(|t| Arc::ptr_eq(t, &table)) {
+                continue;
+            }
+
+            // The table must be unlocked before the upgraded actor may get dropped
+            let actor = table.entries().get_mut(id).and_then(|entry| {
+                let actor = entry.actor.upgrade()?;
+                entry.links += 1;
+                Some(actor)
+            });
+
+            if let Some(actor) = actor {
+                return Some((table, actor));
+            }
+
+            let linked = table.linked.lock().expect("callback table lock poisoned");
+            pending.extend(linked.iter().filter_map(Weak::upgrade));
+            drop(linked);
+            visited.push(table);
+        }
+
+        None
+    }
+
+    /// Drops a received copy, ending the callback if it was the last one
+    fn unlink(&self, id: &CallbackId) {
+        let mut entries = self.entries();
+
+        if let Some(entry) = entries.get_mut(id) {
+            entry.links -= 1;
+
+            if entry.links == 0 {
+                entries.remove(id);
+            }
+        }
+    }
+}
+
+/// What the callbacks created and received by a plugin are bound to: the table of its hub,
+/// and the spawner and tracker of its tasks.
+///
+/// Plugins run their tasks in their scope, [`Callback::new`] and receiving a callback only
+/// work from inside one.
+#[derive(Debug, Clone)]
+pub struct CallbackScope {
+    table: Option<Arc<CallbackTable>>,
+    spawner: Arc<dyn Spawner>,
+    tasks: Arc<TaskTracker>,
+}
+
+impl CallbackScope {
+    /// Scope of a plugin, without a table until it's registered with a hub
+    pub fn new(
+        table: Option<Arc<CallbackTable>>,
+        spawner: Arc<dyn Spawner>,
+        tasks: Arc<TaskTracker>,
+    ) -> Self {
+        Self {
+            table,
+            spawner,
+            tasks,
+        }
+    }
+
+    /// Runs `future` in this scope
+    pub fn scope<F: Future>(self, future: F) -> impl Future<Output = F::Output> {
+        SCOPE.scope(self, future)
+    }
+
+    /// Runs `func` in this scope, e.g. on a blocking thread
+    pub fn sync_scope<R>(self, func: impl FnOnce() -> R) -> R {
+        SCOPE.sync_scope(self, func)
+    }
+
+    /// Spawner running the tasks in this scope, on the plugin's spawner
+    pub fn spawner(self) -> Arc<dyn Spawner> {
+        Arc::new(ScopedSpawner(self))
+    }
+}
+
+#[derive(Debug)]
+struct ScopedSpawner(CallbackScope);
+
+impl Spawner for ScopedSpawner {
+    fn spawn_boxed(&self, task: BoxFuture<'static, ()>) {
+        self.0
+            .spawner
+            .spawn_boxed(SCOPE.scope(self.0.clone(), task).boxed());
+    }
+
+    fn spawn_blocking_boxed(&self, task: BlockingTask) {
+        let scope = self.0.clone();
+        self.0
+            .spawner
+            .spawn_blocking_boxed(Box::new(move || scope.sync_scope(task)));
+    }
+}
+
+struct CallbackCall<A, R> {
+    args: A,
+    tx_ret: oneshot::Sender<Result<R>>,
+}
+
+/// Actor of the plugin that created the callback, running every call made to it
+struct CallbackActor<A, R> {
+    id: CallbackId,
+    table: Weak<CallbackTable>,
+    tx_call: mpsc::UnboundedSender<CallbackCall<A, R>>,
+}
+
+impl<A, R> Drop for CallbackActor<A, R> {
+    fn drop(&mut self) {
+        if let Some(table) = self.table.upgrade() {
+            table.remove(&self.id);
+        }
+    }
+}
+
+/// Received copy of a callback, shared by its clones
+struct Link {
+    id: CallbackId,
+    table: Weak<CallbackTable>,
+}
+
+impl Drop for Link {
+    fn drop(&mut self) {
+        if let Some(table) = self.table.upgrade() {
+            table.unlink(&self.id);
+        }
+    }
+}
+
+enum Target<A, R> {
+    // Held by the plugin that created the callback
+    Owned(Arc<CallbackActor<A, R>>),
+    // Deserialized by another plugin, without a link if it had already ended
+    Received {
+        actor: Weak<CallbackActor<A, R>>,
+        link: Option<Arc<Link>>,
+    },
+}
+
+/// Function passed as an argument to another plugin, e.g. to register a listener.
+///
+/// A callback is serialized as a reference registered with the hub of the plugin creating it.
+/// Calls made by the receiving plugin are run by an actor spawned on the creating plugin's
+/// spawner, which stops with the plugin when the hub shuts down. The callback's lifetime ends
+/// when either side drops it: once the creating plugin drops its copies, or once every plugin
+/// it was handed to drops theirs. Calls then fail with [`Error::HandlerInvalidated`].
+///
+/// Callbacks can only be passed between plugins of the same process, through the same hub or
+/// hubs nested in each other, and have to be received with the exact argument and return types
+/// they were created with.
+pub struct Callback<A, R> {
+    id: CallbackId,
+    target: Target<A, R>,
+}
+
+impl<A, R> Clone for Callback<A, R> {
+    fn clone(&self) -> Self {
+        let target = match &self.target {
+            Target::Owned(actor) => Target::Owned(actor.clone()),
+            Target::Received { actor, link } => Target::Received {
+                actor: actor.clone(),
+                link: link.clone(),
+            },
+        };
+
+        Self {
+            id: self.id,
+            target,
+        }
+    }
+}
+
+impl<A, R> std::fmt::Debug for Callback<A, R> {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("Callback")
+            .field("id", &self.id.seq)
+            .field("owned", &matches!(self.target, Target::Owned(_)))
+            .finish()
+    }
+}
+
+impl<A, R> Callback<A, R>
+where
+    A: Send + 'static,
+    R: Send + 'static,
+{
+    /// Creates a callback running `func` on an actor of the current plugin.
+    ///
+    /// Fails with [`Error::CallbacksUnavailable`] outside of the tasks of a plugin registered
+    /// with a hub.
+    pub fn new<F>(func: F) -> Result<Self>
+    where
+        F: Fn(A) -> AsyncResult<R> + Send + Sync + 'static,
+    {
+        let scope = SCOPE
+            .try_with(CallbackScope::clone)
+            .map_err(|_| Error::CallbacksUnavailable)?;
+        let table = scope.table.clone().ok_or(Error::CallbacksUnavailable)?;
+
+        let id = table.next_id();
+        let (tx_call, mut rx_call) = mpsc::unbounded_channel::<CallbackCall<A, R>>();
+        let stop = scope.tasks.closed();
+
+        // Ends once every owned copy is dropped, or once the plugin is shut down
+        let task = spawner::spawn(scope.clone().spawner().as_ref(), async move {
+            // Once fused, the stop signal stays pending after completing
+            let mut stop = pin!(stop.fuse());
+
+            loop {
+                let next = match select(pin!(rx_call.recv()), stop.as_mut()).await {
+                    Either::Left((call, _)) => Some(call),
+                    Either::Right(_) => None,
+                };
+
+                // Closing lets the queued calls through, then `recv` returns None
+                let Some(call) = next else {
+                    rx_call.close();
+                    continue;
+                };
+
+                let Some(call) = call else {
+                    break;
+                };
+
+                let result = func(call.args).await;
+
+                if call.tx_ret.send(result).is_err() {
+                    // TODO: Log return send failure
+                }
+            }
+            Ok(())
+        });
+        scope.tasks.track(format!("callback#{}", id.seq), task);
+
+        let actor = Arc::new(CallbackActor {
+            id,
+            table: Arc::downgrade(&table),
+            tx_call,
+        });
+        let erased: Arc<Erased> = actor.clone();
+        table.register(id, Arc::downgrade(&erased));
+
+        Ok(Self {
+            id,
+            target: Target::Owned(actor),
+        })
+    }
+
+    /// Checks whether both sides still hold the callback
+    pub fn is_alive(&self) -> bool {
+        match &self.target {
+            Target::Owned(actor) => actor
+                .table
+                .upgrade()
+                .is_some_and(|table| table.is_registered(&self.id)),
+            Target::Received { actor, link } => {
+                actor.strong_count() > 0
+                    && link
+                        .as_ref()
+                        .and_then(|link| link.table.upgrade())
+                        .is_some_and(|table| table.is_registered(&self.id))
+            }
+        }
+    }
+
+    pub async fn call(&self, args: A) -> Result<R> {
+        let actor = match &self.target {
+            Target::Owned(actor) => actor.clone(),
+            Target::Received { actor, .. } => actor.upgrade().ok_or(Error::HandlerInvalidated)?,
+        };
+
+        let (tx, rx) = oneshot::channel();
+        let call = CallbackCall { args, tx_ret: tx };
+        actor
+            .tx_call
+            .send(call)
+            .map_err(|_| Error::HandlerInvalidated)?;
+
+        rx.await.map_err(|_| Error::HandlerInvalidated)?
+    }
+}
+
+impl<A, R> Serialize for Callback<A, R> {
+    fn serialize<S: Serializer>(&self, serializer: S) -> std::result::Result<S::Ok, S::Error> {
+        self.id.serialize(serializer)
+    }
+}
+
+impl<'de, A, R> Deserialize<'de> for Callback<A, R>
+where
+    A: Send + 'static,
+    R: Send + 'static,
+{
+    fn deserialize<De: Deserializer<'de>>(
+        deserializer: De,
+    ) -> std::result::Result<Self, De::Error> {
+        let id = CallbackId::deserialize(deserializer)?;
+
+        let table = SCOPE
+            .try_with(|scope| scope.table.clone())
+            .ok()
+            .flatten()
+            .ok_or_else(|| {
+                de::Error::custom("callbacks can only be received by plugins registered with a hub")
+            })?;
+
+        // A callback that ended in the meantime, or was made up, is received as an invalidated one
+        let Some((table, actor)) = table.receive(&id) else {
+            return Ok(Self {
+                id,
+                target: Target::Received {
+                    actor: Weak::new(),
+                    link: None,
+                },
+            });
+        };
+
+        // Counted as received until now, dropping the link undoes it if the types don't match
+        let link = Arc::new(Link {
+            id,
+            table: Arc::downgrade(&table),
+        });
+        let actor = actor
+            .downcast::<CallbackActor<A, R>>()
+            .map_err(|_| de::Error::custom("callback received with different types"))?;
+
+        Ok(Self {
+            id,
+            target: Target::Received {
+                actor: Arc::downgrade(&actor),
+                link: Some(link),
+            },
+        })
+    }
+}
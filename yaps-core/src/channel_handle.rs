@@ -0,0 +1,81 @@
+use crate::{
+    Error, FuncHandle, Result, YapsData,
+    actor_handle::AsyncResult,
+    shutdown::TaskTracker,
+    spawner::Spawner,
+    stream_handle::{DEFAULT_STREAM_CAPACITY, DataStream, StreamSink, spawn_producer},
+};
+
+use std::sync::Arc;
+
+type SessionFn<D> = Box<dyn Fn(DataStream<D>, StreamSink<D>) -> AsyncResult<()> + Send + Sync>;
+
+/// Handle of a function consuming a stream of inputs and producing a stream of outputs, e.g.
+/// for uploads or interactive sessions.
+///
+/// Every [`FuncHandle::open_channel`] runs a session in its own task. The session pulls
+/// the inputs as it goes, so a caller can't get ahead of it, and can't get more than the
+/// capacity ahead of the caller with its outputs. Dropping the output stream ends the session,
+/// which in turn drops the input stream.
+pub struct ChannelHandle<D: YapsData> {
+    id: String,
+    capacity: usize,
+    spawner: Arc<dyn Spawner>,
+    tracker: Option<Arc<TaskTracker>>,
+    session: SessionFn<D>,
+}
+
+impl<D: YapsData> std::fmt::Debug for ChannelHandle<D> {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("ChannelHandle")
+            .field("id", &self.id)
+            .field("capacity", &self.capacity)
+            .field("spawner", &self.spawner)
+            .field("tracker", &self.tracker)
+            .finish()
+    }
+}
+
+impl<D: YapsData> ChannelHandle<D> {
+    /// Creates a handle spawning the sessions on `spawner`
+    pub fn new<F>(id: impl Into<String>, spawner: Arc<dyn Spawner>, session: F) -> Self
+    where
+        F: Fn(DataStream<D>, StreamSink<D>) -> AsyncResult<()> + Send + Sync + 'static,
+    {
+        Self {
+            id: id.into(),
+            capacity: DEFAULT_STREAM_CAPACITY,
+            spawner,
+            tracker: None,
+            session: Box::new(session),
+        }
+    }
+
+    /// Sets how many outputs the session can get ahead of the caller (at least one)
+    pub fn with_capacity(mut self, capacity: usize) -> Self {
+        self.capacity = capacity.max(1);
+        self
+    }
+
+    /// Tracks the sessions in `tracker`, so they're joined when the plugin is shut down
+    pub fn with_tracker(mut self, tracker: Arc<TaskTracker>) -> Self {
+        self.tracker = Some(tracker);
+        self
+    }
+}
+
+impl<D: YapsData> FuncHandle<D> for ChannelHandle<D> {
+    async fn call(&self, _args: D) -> Result<D> {
+        Err(Error::Channel(self.id.clone()))
+    }
+
+    async fn open_channel(&self, inputs: DataStream<D>) -> Result<DataStream<D>> {
+        Ok(spawn_producer(
+            &self.id,
+            self.capacity,
+            self.spawner.as_ref(),
+            self.tracker.as_deref(),
+            |sink| (self.session)(inputs, sink),
+        ))
+    }
+}
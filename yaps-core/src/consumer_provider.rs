@@ -1,49 +1,598 @@
-use crate::{FuncHandle, Result};
+use crate::{
+    DynFuncHandle, Error, Result, callback::CallbackTable, event_bus::EventBus,
+    shared_handle::SharedHandle, shutdown::TaskTracker, spawner::Spawner,
+};
 
 use async_trait::async_trait;
+use futures::future;
+use serde::{Deserialize, Serialize};
+use std::{future::Future, sync::Arc};
 
 pub trait YapsData: Send + 'static {}
 
-#[derive(Debug, Clone)]
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
 pub struct FuncMetadata {
     pub id: String,
 }
 
-#[async_trait]
+/// Resolves function ids to handles.
+///
+/// Its methods are native async ones like the ones of [`FuncHandle`](crate::FuncHandle). Hubs
+/// keep their plugins as `dyn` [`DynFuncProvider`] instead, which every provider implements.
 pub trait FuncProvider<D: YapsData>: Send + Sync {
-    async fn provided_funcs(&self) -> Result<Vec<FuncMetadata>>;
-    async fn get_func(&self, id: &str) -> Result<Box<dyn FuncHandle<D>>>;
+    /// Human readable name used when introspecting a hub
+    fn name(&self) -> String {
+        std::any::type_name::<Self>().to_string()
+    }
+
+    fn provided_funcs(&self) -> impl Future<Output = Result<Vec<FuncMetadata>>> + Send;
+    fn get_func(&self, id: &str) -> impl Future<Output = Result<Box<dyn DynFuncHandle<D>>>> + Send;
+
+    /// Resolves every handle provided for `id`, hubs return one for each plugin providing it
+    fn get_all_funcs(
+        &self,
+        id: &str,
+    ) -> impl Future<Output = Result<Vec<Box<dyn DynFuncHandle<D>>>>> + Send {
+        async move {
+            match self.get_func(id).await {
+                Ok(func) => Ok(vec![func]),
+                Err(Error::FunctionNotFound(_)) => Ok(Vec::new()),
+                Err(e) => Err(e),
+            }
+        }
+    }
+
+    /// Like [`FuncProvider::get_func`], returning a handle that can be cloned and shared
+    fn get_shared_func(&self, id: &str) -> impl Future<Output = Result<SharedHandle<D>>> + Send {
+        async move { self.get_func(id).await.map(SharedHandle::from) }
+    }
+
+    /// Like [`FuncProvider::get_all_funcs`], returning handles that can be cloned and shared
+    fn get_all_shared_funcs(
+        &self,
+        id: &str,
+    ) -> impl Future<Output = Result<Vec<SharedHandle<D>>>> + Send {
+        async move {
+            let funcs = self.get_all_funcs(id).await?;
+            Ok(funcs.into_iter().map(SharedHandle::from).collect())
+        }
+    }
+
+    /// Like [`FuncProvider::get_all_shared_funcs`], naming each handle after its provider.
+    ///
+    /// The names identify shards, so they should stay the same for the same provider. Providers
+    /// sharing a name are told apart by their position among each other, e.g. `Cache`, `Cache#1`.
+    fn get_all_named_funcs(
+        &self,
+        id: &str,
+    ) -> impl Future<Output = Result<Vec<(String, SharedHandle<D>)>>> + Send {
+        async move {
+            let name = self.name();
+            let funcs = self.get_all_shared_funcs(id).await?;
+            Ok(funcs
+                .into_iter()
+                .enumerate()
+                .map(|(i, func)| (shard_name(&name, i), func))
+                .collect())
+        }
+    }
+
+    /// Called by hubs when the provider is registered, before anything is connected to it
+    fn on_init(&self) -> impl Future<Output = Result<()>> + Send {
+        future::ok(())
+    }
+
+    /// Called by hubs once the provider is connected, after the plugins it depends on
+    fn on_ready(&self) -> impl Future<Output = Result<()>> + Send {
+        future::ok(())
+    }
+
+    /// Called by hubs when the provider is shut down, before the plugins it depends on.
+    ///
+    /// Also called if registering the provider fails after [`FuncProvider::on_init`] succeeded.
+    fn on_shutdown(&self) -> impl Future<Output = Result<()>> + Send {
+        future::ok(())
+    }
+
+    /// Tracker of the provider's calls and tasks, drained and joined when a hub shuts down
+    fn task_tracker(&self) -> Option<Arc<TaskTracker>> {
+        None
+    }
+
+    /// Hands the provider the spawner its tasks should run on.
+    ///
+    /// Called by hubs configured with a spawner, before [`FuncProvider::on_init`].
+    fn attach_spawner(&self, _spawner: &Arc<dyn Spawner>) {}
+
+    /// Whether the provider has a spawner already, hubs then don't build one for it
+    fn has_spawner(&self) -> bool {
+        false
+    }
+
+    /// Hands the provider the hub's event bus to publish and subscribe to events on.
+    ///
+    /// Called by hubs after [`FuncProvider::attach_spawner`], so providers that don't consume
+    /// anything get the bus as well.
+    fn attach_events(&self, _events: &Arc<EventBus<D>>) {}
+
+    /// Hands the provider the hub's callback table, the callbacks it creates are registered in.
+    ///
+    /// Called by hubs together with [`FuncProvider::attach_events`].
+    fn attach_callbacks(&self, _callbacks: &Arc<CallbackTable>) {}
 }
 
-#[async_trait]
+/// Gets connected to the functions it consumes, kept by hubs as `dyn` [`DynFuncConsumer`]
 pub trait FuncConsumer<D: YapsData>: Send + Sync {
-    async fn connect(&self, provider: &dyn FuncProvider<D>) -> Result<()>;
+    /// Human readable name used when introspecting a hub
+    fn name(&self) -> String {
+        std::any::type_name::<Self>().to_string()
+    }
+
+    /// Functions this consumer wants to be connected to
+    fn consumed_funcs(&self) -> impl Future<Output = Result<Vec<FuncMetadata>>> + Send {
+        future::ok(Vec::new())
+    }
+
+    /// Hands the consumer a provider it can keep to resolve functions lazily.
+    ///
+    /// Called by hubs before [`FuncConsumer::connect`], the provider keeps seeing plugins
+    /// registered later on.
+    fn attach(&self, _provider: Arc<dyn DynFuncProvider<D>>) {}
+
+    /// Like [`FuncProvider::on_init`], called for consumers registered on their own
+    fn on_init(&self) -> impl Future<Output = Result<()>> + Send {
+        future::ok(())
+    }
+
+    /// Like [`FuncProvider::on_ready`], called for consumers registered on their own
+    fn on_ready(&self) -> impl Future<Output = Result<()>> + Send {
+        future::ok(())
+    }
+
+    /// Like [`FuncProvider::on_shutdown`], called for consumers registered on their own
+    fn on_shutdown(&self) -> impl Future<Output = Result<()>> + Send {
+        future::ok(())
+    }
+
+    /// Like [`FuncProvider::task_tracker`], used for consumers registered on their own
+    fn task_tracker(&self) -> Option<Arc<TaskTracker>> {
+        None
+    }
+
+    /// Like [`FuncProvider::attach_spawner`], called for consumers registered on their own
+    fn attach_spawner(&self, _spawner: &Arc<dyn Spawner>) {}
+
+    /// Like [`FuncProvider::has_spawner`], used for consumers registered on their own
+    fn has_spawner(&self) -> bool {
+        false
+    }
+
+    /// Hands the consumer the hub's event bus to publish and subscribe to events on.
+    ///
+    /// Called by hubs together with [`FuncConsumer::attach`].
+    fn attach_events(&self, _events: &Arc<EventBus<D>>) {}
+
+    /// Hands the consumer the hub's callback table, the callbacks it creates are registered in.
+    ///
+    /// Called by hubs together with [`FuncConsumer::attach`].
+    fn attach_callbacks(&self, _callbacks: &Arc<CallbackTable>) {}
+
+    fn connect(&self, provider: &dyn DynFuncProvider<D>)
+    -> impl Future<Output = Result<()>> + Send;
 }
 
+/// Object-safe adapter of [`FuncProvider`], the type hubs keep their providers as.
+///
+/// Every provider implements it, boxing the futures of its methods. They're named apart from
+/// the ones of [`FuncProvider`] so both traits can be in scope, `dyn DynFuncProvider` is used
+/// through [`FuncProvider`] anyway.
 #[async_trait]
-impl<D, T, U> FuncProvider<D> for U
-where
-    D: YapsData,
-    T: FuncProvider<D>,
-    U: std::ops::Deref<Target = T> + Send + Sync,
-{
-    async fn provided_funcs(&self) -> Result<Vec<FuncMetadata>> {
-        self.deref().provided_funcs().await
+pub trait DynFuncProvider<D: YapsData>: Send + Sync {
+    fn dyn_name(&self) -> String;
+    async fn dyn_provided_funcs(&self) -> Result<Vec<FuncMetadata>>;
+    async fn dyn_get_func(&self, id: &str) -> Result<Box<dyn DynFuncHandle<D>>>;
+    async fn dyn_get_all_funcs(&self, id: &str) -> Result<Vec<Box<dyn DynFuncHandle<D>>>>;
+    async fn dyn_get_shared_func(&self, id: &str) -> Result<SharedHandle<D>>;
+    async fn dyn_get_all_shared_funcs(&self, id: &str) -> Result<Vec<SharedHandle<D>>>;
+    async fn dyn_get_all_named_funcs(&self, id: &str) -> Result<Vec<(String, SharedHandle<D>)>>;
+    async fn dyn_on_init(&self) -> Result<()>;
+    async fn dyn_on_ready(&self) -> Result<()>;
+    async fn dyn_on_shutdown(&self) -> Result<()>;
+    fn dyn_task_tracker(&self) -> Option<Arc<TaskTracker>>;
+    fn dyn_attach_spawner(&self, spawner: &Arc<dyn Spawner>);
+    fn dyn_has_spawner(&self) -> bool;
+    fn dyn_attach_events(&self, events: &Arc<EventBus<D>>);
+    fn dyn_attach_callbacks(&self, callbacks: &Arc<CallbackTable>);
+}
+
+#[async_trait]
+impl<D: YapsData, T: FuncProvider<D>> DynFuncProvider<D> for T {
+    fn dyn_name(&self) -> String {
+        self.name()
+    }
+
+    async fn dyn_provided_funcs(&self) -> Result<Vec<FuncMetadata>> {
+        self.provided_funcs().await
+    }
+
+    async fn dyn_get_func(&self, id: &str) -> Result<Box<dyn DynFuncHandle<D>>> {
+        self.get_func(id).await
+    }
+
+    async fn dyn_get_all_funcs(&self, id: &str) -> Result<Vec<Box<dyn DynFuncHandle<D>>>> {
+        self.get_all_funcs(id).await
+    }
+
+    async fn dyn_get_shared_func(&self, id: &str) -> Result<SharedHandle<D>> {
+        self.get_shared_func(id).await
+    }
+
+    async fn dyn_get_all_shared_funcs(&self, id: &str) -> Result<Vec<SharedHandle<D>>> {
+        self.get_all_shared_funcs(id).await
+    }
+
+    async fn dyn_get_all_named_funcs(&self, id: &str) -> Result<Vec<(String, SharedHandle<D>)>> {
+        self.get_all_named_funcs(id).await
+    }
+
+    async fn dyn_on_init(&self) -> Result<()> {
+        self.on_init().await
+    }
+
+    async fn dyn_on_ready(&self) -> Result<()> {
+        self.on_ready().await
+    }
+
+    async fn dyn_on_shutdown(&self) -> Result<()> {
+        self.on_shutdown().await
+    }
+
+    fn dyn_task_tracker(&self) -> Option<Arc<TaskTracker>> {
+        self.task_tracker()
+    }
+
+    fn dyn_attach_spawner(&self, spawner: &Arc<dyn Spawner>) {
+        self.attach_spawner(spawner)
     }
 
-    async fn get_func(&self, id: &str) -> Result<Box<dyn FuncHandle<D>>> {
-        self.deref().get_func(id).await
+    fn dyn_has_spawner(&self) -> bool {
+        self.has_spawner()
+    }
+
+    fn dyn_attach_events(&self, events: &Arc<EventBus<D>>) {
+        self.attach_events(events)
+    }
+
+    fn dyn_attach_callbacks(&self, callbacks: &Arc<CallbackTable>) {
+        self.attach_callbacks(callbacks)
     }
 }
 
+impl<D: YapsData> FuncProvider<D> for dyn DynFuncProvider<D> + '_ {
+    fn name(&self) -> String {
+        self.dyn_name()
+    }
+
+    fn provided_funcs(&self) -> impl Future<Output = Result<Vec<FuncMetadata>>> + Send {
+        self.dyn_provided_funcs()
+    }
+
+    async fn get_func(&self, id: &str) -> Result<Box<dyn DynFuncHandle<D>>> {
+        self.dyn_get_func(id).await
+    }
+
+    async fn get_all_funcs(&self, id: &str) -> Result<Vec<Box<dyn DynFuncHandle<D>>>> {
+        self.dyn_get_all_funcs(id).await
+    }
+
+    async fn get_shared_func(&self, id: &str) -> Result<SharedHandle<D>> {
+        self.dyn_get_shared_func(id).await
+    }
+
+    async fn get_all_shared_funcs(&self, id: &str) -> Result<Vec<SharedHandle<D>>> {
+        self.dyn_get_all_shared_funcs(id).await
+    }
+
+    async fn get_all_named_funcs(&self, id: &str) -> Result<Vec<(String, SharedHandle<D>)>> {
+        self.dyn_get_all_named_funcs(id).await
+    }
+
+    fn on_init(&self) -> impl Future<Output = Result<()>> + Send {
+        self.dyn_on_init()
+    }
+
+    fn on_ready(&self) -> impl Future<Output = Result<()>> + Send {
+        self.dyn_on_ready()
+    }
+
+    fn on_shutdown(&self) -> impl Future<Output = Result<()>> + Send {
+        self.dyn_on_shutdown()
+    }
+
+    fn task_tracker(&self) -> Option<Arc<TaskTracker>> {
+        self.dyn_task_tracker()
+    }
+
+    fn attach_spawner(&self, spawner: &Arc<dyn Spawner>) {
+        self.dyn_attach_spawner(spawner)
+    }
+
+    fn has_spawner(&self) -> bool {
+        self.dyn_has_spawner()
+    }
+
+    fn attach_events(&self, events: &Arc<EventBus<D>>) {
+        self.dyn_attach_events(events)
+    }
+
+    fn attach_callbacks(&self, callbacks: &Arc<CallbackTable>) {
+        self.dyn_attach_callbacks(callbacks)
+    }
+}
+
+/// Object-safe adapter of [`FuncConsumer`], like [`DynFuncProvider`]
+#[async_trait]
+pub trait DynFuncConsumer<D: YapsData>: Send + Sync {
+    fn dyn_name(&self) -> String;
+    async fn dyn_consumed_funcs(&self) -> Result<Vec<FuncMetadata>>;
+    fn dyn_attach(&self, provider: Arc<dyn DynFuncProvider<D>>);
+    async fn dyn_on_init(&self) -> Result<()>;
+    async fn dyn_on_ready(&self) -> Result<()>;
+    async fn dyn_on_shutdown(&self) -> Result<()>;
+    fn dyn_task_tracker(&self) -> Option<Arc<TaskTracker>>;
+    fn dyn_attach_spawner(&self, spawner: &Arc<dyn Spawner>);
+    fn dyn_has_spawner(&self) -> bool;
+    fn dyn_attach_events(&self, events: &Arc<EventBus<D>>);
+    fn dyn_attach_callbacks(&self, callbacks: &Arc<CallbackTable>);
+    async fn dyn_connect(&self, provider: &dyn DynFuncProvider<D>) -> Result<()>;
+}
+
 #[async_trait]
-impl<D, T, U> FuncConsumer<D> for U
-where
-    D: YapsData,
-    T: FuncConsumer<D>,
-    U: std::ops::Deref<Target = T> + Send + Sync,
-{
-    async fn connect(&self, provider: &dyn FuncProvider<D>) -> Result<()> {
-        self.deref().connect(provider).await
+impl<D: YapsData, T: FuncConsumer<D>> DynFuncConsumer<D> for T {
+    fn dyn_name(&self) -> String {
+        self.name()
+    }
+
+    async fn dyn_consumed_funcs(&self) -> Result<Vec<FuncMetadata>> {
+        self.consumed_funcs().await
+    }
+
+    fn dyn_attach(&self, provider: Arc<dyn DynFuncProvider<D>>) {
+        self.attach(provider)
+    }
+
+    async fn dyn_on_init(&self) -> Result<()> {
+        self.on_init().await
+    }
+
+    async fn dyn_on_ready(&self) -> Result<()> {
+        self.on_ready().await
+    }
+
+    async fn dyn_on_shutdown(&self) -> Result<()> {
+        self.on_shutdown().await
+    }
+
+    fn dyn_task_tracker(&self) -> Option<Arc<TaskTracker>> {
+        self.task_tracker()
+    }
+
+    fn dyn_attach_spawner(&self, spawner: &Arc<dyn Spawner>) {
+        self.attach_spawner(spawner)
+    }
+
+    fn dyn_has_spawner(&self) -> bool {
+        self.has_spawner()
+    }
+
+    fn dyn_attach_events(&self, events: &Arc<EventBus<D>>) {
+        self.attach_events(events)
+    }
+
+    fn dyn_attach_callbacks(&self, callbacks: &Arc<CallbackTable>) {
+        self.attach_callbacks(callbacks)
+    }
+
+    async fn dyn_connect(&self, provider: &dyn DynFuncProvider<D>) -> Result<()> {
+        self.connect(provider).await
     }
 }
+
+impl<D: YapsData> FuncConsumer<D> for dyn DynFuncConsumer<D> + '_ {
+    fn name(&self) -> String {
+        self.dyn_name()
+    }
+
+    fn consumed_funcs(&self) -> impl Future<Output = Result<Vec<FuncMetadata>>> + Send {
+        self.dyn_consumed_funcs()
+    }
+
+    fn attach(&self, provider: Arc<dyn DynFuncProvider<D>>) {
+        self.dyn_attach(provider)
+    }
+
+    fn on_init(&self) -> impl Future<Output = Result<()>> + Send {
+        self.dyn_on_init()
+    }
+
+    fn on_ready(&self) -> impl Future<Output = Result<()>> + Send {
+        self.dyn_on_ready()
+    }
+
+    fn on_shutdown(&self) -> impl Future<Output = Result<()>> + Send {
+        self.dyn_on_shutdown()
+    }
+
+    fn task_tracker(&self) -> Option<Arc<TaskTracker>> {
+        self.dyn_task_tracker()
+    }
+
+    fn attach_spawner(&self, spawner: &Arc<dyn Spawner>) {
+        self.dyn_attach_spawner(spawner)
+    }
+
+    fn has_spawner(&self) -> bool {
+        self.dyn_has_spawner()
+    }
+
+    fn attach_events(&self, events: &Arc<EventBus<D>>) {
+        self.dyn_attach_events(events)
+    }
+
+    fn attach_callbacks(&self, callbacks: &Arc<CallbackTable>) {
+        self.dyn_attach_callbacks(callbacks)
+    }
+
+    async fn connect(&self, provider: &dyn DynFuncProvider<D>) -> Result<()> {
+        self.dyn_connect(provider).await
+    }
+}
+
+/// Name of the `nth` provider called `name`
+pub(crate) fn shard_name(name: &str, nth: usize) -> String {
+    match nth {
+        0 => name.to_string(),
+        _ => format!("{name}#{nth}"),
+    }
+}
+
+/// Anything that both provides and consumes functions, usable as a trait object
+pub trait Plugin<D: YapsData>: DynFuncProvider<D> + DynFuncConsumer<D> {}
+
+impl<D: YapsData, T: FuncProvider<D> + FuncConsumer<D>> Plugin<D> for T {}
+
+macro_rules! forward_provider_consumer {
+    ($($ptr:ty),*) => {$(
+        impl<D: YapsData, T: FuncProvider<D> + ?Sized> FuncProvider<D> for $ptr {
+            fn name(&self) -> String {
+                (**self).name()
+            }
+
+            fn provided_funcs(&self) -> impl Future<Output = Result<Vec<FuncMetadata>>> + Send {
+                (**self).provided_funcs()
+            }
+
+            fn get_func(
+                &self,
+                id: &str,
+            ) -> impl Future<Output = Result<Box<dyn DynFuncHandle<D>>>> + Send {
+                (**self).get_func(id)
+            }
+
+            fn get_all_funcs(
+                &self,
+                id: &str,
+            ) -> impl Future<Output = Result<Vec<Box<dyn DynFuncHandle<D>>>>> + Send {
+                (**self).get_all_funcs(id)
+            }
+
+            fn get_shared_func(
+                &self,
+                id: &str,
+            ) -> impl Future<Output = Result<SharedHandle<D>>> + Send {
+                (**self).get_shared_func(id)
+            }
+
+            fn get_all_shared_funcs(
+                &self,
+                id: &str,
+            ) -> impl Future<Output = Result<Vec<SharedHandle<D>>>> + Send {
+                (**self).get_all_shared_funcs(id)
+            }
+
+            fn get_all_named_funcs(
+                &self,
+                id: &str,
+            ) -> impl Future<Output = Result<Vec<(String, SharedHandle<D>)>>> + Send {
+                (**self).get_all_named_funcs(id)
+            }
+
+            fn on_init(&self) -> impl Future<Output = Result<()>> + Send {
+                FuncProvider::on_init(&**self)
+            }
+
+            fn on_ready(&self) -> impl Future<Output = Result<()>> + Send {
+                FuncProvider::on_ready(&**self)
+            }
+
+            fn on_shutdown(&self) -> impl Future<Output = Result<()>> + Send {
+                FuncProvider::on_shutdown(&**self)
+            }
+
+            fn task_tracker(&self) -> Option<Arc<TaskTracker>> {
+                FuncProvider::task_tracker(&**self)
+            }
+
+            fn attach_spawner(&self, spawner: &Arc<dyn Spawner>) {
+                FuncProvider::attach_spawner(&**self, spawner)
+            }
+
+            fn has_spawner(&self) -> bool {
+                FuncProvider::has_spawner(&**self)
+            }
+
+            fn attach_events(&self, events: &Arc<EventBus<D>>) {
+                FuncProvider::attach_events(&**self, events)
+            }
+
+            fn attach_callbacks(&self, callbacks: &Arc<CallbackTable>) {
+                FuncProvider::attach_callbacks(&**self, callbacks)
+            }
+        }
+
+        impl<D: YapsData, T: FuncConsumer<D> + ?Sized> FuncConsumer<D> for $ptr {
+            fn name(&self) -> String {
+                (**self).name()
+            }
+
+            fn consumed_funcs(&self) -> impl Future<Output = Result<Vec<FuncMetadata>>> + Send {
+                (**self).consumed_funcs()
+            }
+
+            fn attach(&self, provider: Arc<dyn DynFuncProvider<D>>) {
+                (**self).attach(provider)
+            }
+
+            fn on_init(&self) -> impl Future<Output = Result<()>> + Send {
+                FuncConsumer::on_init(&**self)
+            }
+
+            fn on_ready(&self) -> impl Future<Output = Result<()>> + Send {
+                FuncConsumer::on_ready(&**self)
+            }
+
+            fn on_shutdown(&self) -> impl Future<Output = Result<()>> + Send {
+                FuncConsumer::on_shutdown(&**self)
+            }
+
+            fn task_tracker(&self) -> Option<Arc<TaskTracker>> {
+                FuncConsumer::task_tracker(&**self)
+            }
+
+            fn attach_spawner(&self, spawner: &Arc<dyn Spawner>) {
+                FuncConsumer::attach_spawner(&**self, spawner)
+            }
+
+            fn has_spawner(&self) -> bool {
+                FuncConsumer::has_spawner(&**self)
+            }
+
+            fn attach_events(&self, events: &Arc<EventBus<D>>) {
+                FuncConsumer::attach_events(&**self, events)
+            }
+
+            fn attach_callbacks(&self, callbacks: &Arc<CallbackTable>) {
+                FuncConsumer::attach_callbacks(&**self, callbacks)
+            }
+
+            fn connect(
+                &self,
+                provider: &dyn DynFuncProvider<D>,
+            ) -> impl Future<Output = Result<()>> + Send {
+                (**self).connect(provider)
+            }
+        }
+    )*};
+}
+
+forward_provider_consumer!(&T, Box<T>, Arc<T>);
@@ -0,0 +1,51 @@
+use crate::{
+    FuncHandle, Result, YapsData,
+    codec::{Codec, DecodeFor, EncodeFor},
+};
+
+use std::sync::Arc;
+
+type DirectFn<D> = Box<dyn Fn(D) -> Result<D> + Send + Sync>;
+
+/// Handle running a synchronous function inline on the caller's task.
+///
+/// Unlike [`ActorHandle`](crate::actor_handle::ActorHandle), calls don't go through a channel
+/// and a task of their own, and they aren't serialized: concurrent calls run concurrently.
+/// Box it to resolve it as a `dyn` [`DynFuncHandle`](crate::DynFuncHandle).
+pub struct DirectHandle<D> {
+    func: DirectFn<D>,
+}
+
+impl<D> std::fmt::Debug for DirectHandle<D> {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("DirectHandle").finish_non_exhaustive()
+    }
+}
+
+impl<D: YapsData> DirectHandle<D> {
+    pub fn new<F>(func: F) -> Self
+    where
+        F: Fn(D) -> Result<D> + Send + Sync + 'static,
+    {
+        Self {
+            func: Box::new(func),
+        }
+    }
+
+    pub fn with_codec<C, F, A, R>(func: F, codec: Arc<C>) -> Self
+    where
+        C: Codec<Data = D> + DecodeFor<C, A> + EncodeFor<C, R> + 'static,
+        F: Fn(A) -> Result<R> + Send + Sync + 'static,
+    {
+        Self::new(move |args| {
+            let result = func(codec.decode(args)?)?;
+            codec.encode(result)
+        })
+    }
+}
+
+impl<D: YapsData> FuncHandle<D> for DirectHandle<D> {
+    async fn call(&self, args: D) -> Result<D> {
+        (self.func)(args)
+    }
+}
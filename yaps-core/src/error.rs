@@ -25,6 +25,70 @@ pub enum Error {
     #[error("Channel send error: {0}")]
     ChannelSend(String),
 
+    #[error("Function {0} streams its results, it has to be called with call_stream")]
+    Streaming(String),
+
+    #[error("Function {0} is a channel, it has to be opened with open_channel")]
+    Channel(String),
+
+    #[error("Function {0} isn't a channel, it can't be opened with open_channel")]
+    NotAChannel(String),
+
     #[error("Function handler invalidated")]
     HandlerInvalidated,
+
+    #[error("Callbacks can only be used by the tasks of a plugin registered with a hub")]
+    CallbacksUnavailable,
+
+    #[error("No shard key for function: {0}")]
+    ShardKeyMissing(String),
+
+    #[error("Subscription to {topic} lagged behind, {skipped} events skipped")]
+    EventsLagged { topic: String, skipped: u64 },
+
+    #[error("Hub dropped")]
+    HubDropped,
+
+    #[error("Function {0} was shut down")]
+    ShutDown(String),
+
+    #[error("Task {0} panicked or was aborted")]
+    TaskFailed(String),
+
+    #[error("Blocking calls can't be made from inside an async runtime")]
+    InsideRuntime,
+
+    #[error("Runtime error: {0}")]
+    Runtime(String),
+
+    #[error("{0}")]
+    Custom(String),
+
+    #[error("Re-entrant call would deadlock: {}", .chain.join(" -> "))]
+    Reentrancy { chain: Vec<String> },
+
+    #[error("Plugin {plugin} failed: {error}")]
+    Plugin { plugin: String, error: Box<Error> },
+
+    #[error("Multiple errors: {0:?}")]
+    Multiple(Vec<Error>),
+}
+
+impl Error {
+    /// Collapses a list of errors into a single result, flattening nested [`Error::Multiple`]
+    pub fn aggregate(errors: impl IntoIterator<Item = Error>) -> Result<()> {
+        let mut errors: Vec<_> = errors
+            .into_iter()
+            .flat_map(|e| match e {
+                Error::Multiple(errors) => errors,
+                e => vec![e],
+            })
+            .collect();
+
+        match errors.len() {
+            0 => Ok(()),
+            1 => Err(errors.remove(0)),
+            _ => Err(Error::Multiple(errors)),
+        }
+    }
 }
@@ -0,0 +1,315 @@
+use crate::{
+    Error, Result, YapsData,
+    actor_handle::AsyncResult,
+    codec::{Codec, DecodeFor, EncodeFor},
+    spawner::{self, AmbientSpawner, Spawner, Task},
+};
+
+use futures::{
+    FutureExt,
+    future::{self, Either, select},
+};
+use std::{
+    collections::{HashMap, VecDeque},
+    future::Future,
+    pin::pin,
+    sync::{
+        Arc, Mutex, Weak,
+        atomic::{AtomicUsize, Ordering},
+    },
+};
+use tokio::sync::Notify;
+
+/// Default number of events a subscription buffers before it starts lagging
+pub const DEFAULT_EVENT_CAPACITY: usize = 64;
+
+/// Topic based events published and subscribed to by plugins, complementing function calls
+/// for notifications.
+///
+/// Delivery guarantees:
+/// - An event reaches the subscriptions that exist when it's published, at most once each.
+///   Nothing is persisted, events published on a topic without subscribers are dropped.
+/// - A subscription receives the events of a topic in the order they were published.
+/// - Every subscription buffers up to the bus capacity. A subscription falling further behind
+///   skips the oldest events, [`Subscription::recv`] then returns [`Error::EventsLagged`] once
+///   and carries on with the oldest event still buffered.
+///
+/// Payloads aren't cloned, every subscription gets one encoded for it.
+pub struct EventBus<D> {
+    capacity: AtomicUsize,
+    topics: Mutex<HashMap<String, Vec<Weak<Queue<D>>>>>,
+}
+
+impl<D: YapsData> Default for EventBus<D> {
+    fn default() -> Self {
+        Self::with_capacity(DEFAULT_EVENT_CAPACITY)
+    }
+}
+
+impl<D: YapsData> std::fmt::Debug for EventBus<D> {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("EventBus")
+            .field("capacity", &self.capacity())
+            .field("topics", &self.topics())
+            .finish()
+    }
+}
+
+impl<D: YapsData> EventBus<D> {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Creates a bus whose subscriptions buffer up to `capacity` events (at least one)
+    pub fn with_capacity(capacity: usize) -> Self {
+        Self {
+            capacity: AtomicUsize::new(capacity.max(1)),
+            topics: Mutex::new(HashMap::new()),
+        }
+    }
+
+    pub fn capacity(&self) -> usize {
+        self.capacity.load(Ordering::Relaxed)
+    }
+
+    /// Changes how many events each subscription buffers (at least one).
+    ///
+    /// Existing subscriptions keep their events, a smaller capacity only applies to them on the
+    /// next publish, skipping the oldest events then.
+    pub fn set_capacity(&self, capacity: usize) {
+        self.capacity.store(capacity.max(1), Ordering::Relaxed);
+    }
+
+    /// Topics that have been subscribed to
+    pub fn topics(&self) -> Vec<String> {
+        let topics = self.topics.lock().expect("event bus lock poisoned");
+        let mut topics: Vec<_> = topics.keys().cloned().collect();
+        topics.sort();
+        topics
+    }
+
+    /// Publishes the payloads made by `payload` on `topic`, one for each subscription.
+    ///
+    /// Returns how many subscriptions it reached, or the first error making a payload.
+    pub fn publish_with(
+        &self,
+        topic: &str,
+        mut payload: impl FnMut() -> Result<D>,
+    ) -> Result<usize> {
+        let mut topics = self.topics.lock().expect("event bus lock poisoned");
+
+        let Some(queues) = topics.get_mut(topic) else {
+            return Ok(0);
+        };
+
+        // Dropped subscriptions are only cleaned up here
+        queues.retain(|queue| queue.strong_count() > 0);
+
+        let capacity = self.capacity();
+        let mut reached = 0;
+        for queue in queues.iter().filter_map(Weak::upgrade) {
+            queue.push(payload()?, capacity);
+            reached += 1;
+        }
+
+        Ok(reached)
+    }
+
+    /// Encodes `payload` for every subscription to `topic` and publishes it
+    pub fn publish_with_codec<C, A>(&self, codec: &C, topic: &str, payload: A) -> Result<usize>
+    where
+        C: Codec<Data = D> + EncodeFor<C, A>,
+        A: Clone,
+    {
+        self.publish_with(topic, || codec.encode(payload.clone()))
+    }
+
+    /// Subscribes to the events published on `topic` from now on
+    pub fn subscribe(&self, topic: &str) -> Subscription<D> {
+        let mut topics = self.topics.lock().expect("event bus lock poisoned");
+
+        let queue = Arc::new(Queue::default());
+        topics
+            .entry(topic.to_string())
+            .or_default()
+            .push(Arc::downgrade(&queue));
+
+        Subscription {
+            topic: topic.to_string(),
+            queue,
+        }
+    }
+}
+
+impl<D: YapsData + Clone> EventBus<D> {
+    /// Publishes `payload` on `topic`, returning how many subscriptions it reached
+    pub fn publish(&self, topic: &str, payload: D) -> usize {
+        self.publish_with(topic, || Ok(payload.clone()))
+            .unwrap_or_default()
+    }
+}
+
+impl<D> Drop for EventBus<D> {
+    fn drop(&mut self) {
+        let topics = self.topics.get_mut().expect("event bus lock poisoned");
+
+        for queue in topics.values().flatten().filter_map(Weak::upgrade) {
+            queue.close();
+        }
+    }
+}
+
+/// Events buffered for a single subscription
+struct Queue<D> {
+    state: Mutex<QueueState<D>>,
+    notify: Notify,
+}
+
+struct QueueState<D> {
+    events: VecDeque<D>,
+    skipped: u64,
+    closed: bool,
+}
+
+impl<D> Default for Queue<D> {
+    fn default() -> Self {
+        Self {
+            state: Mutex::new(QueueState {
+                events: VecDeque::new(),
+                skipped: 0,
+                closed: false,
+            }),
+            notify: Notify::new(),
+        }
+    }
+}
+
+impl<D> Queue<D> {
+    fn lock(&self) -> std::sync::MutexGuard<'_, QueueState<D>> {
+        self.state.lock().expect("event queue lock poisoned")
+    }
+
+    /// Buffers `payload`, skipping the oldest event if the queue is over `capacity`
+    fn push(&self, payload: D, capacity: usize) {
+        let mut state = self.lock();
+
+        state.events.push_back(payload);
+        while state.events.len() > capacity {
+            state.events.pop_front();
+            state.skipped += 1;
+        }
+
+        drop(state);
+        self.notify.notify_one();
+    }
+
+    fn close(&self) {
+        self.lock().closed = true;
+        self.notify.notify_one();
+    }
+}
+
+/// Events of a topic received from an [`EventBus`]
+pub struct Subscription<D> {
+    topic: String,
+    queue: Arc<Queue<D>>,
+}
+
+impl<D> std::fmt::Debug for Subscription<D> {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("Subscription")
+            .field("topic", &self.topic)
+            .field("pending", &self.queue.lock().events.len())
+            .finish()
+    }
+}
+
+impl<D: YapsData> Subscription<D> {
+    pub fn topic(&self) -> &str {
+        &self.topic
+    }
+
+    /// Waits for the next event, fails with [`Error::HubDropped`] once the bus is gone
+    pub async fn recv(&mut self) -> Result<D> {
+        loop {
+            {
+                let mut state = self.queue.lock();
+
+                if state.skipped > 0 {
+                    return Err(Error::EventsLagged {
+                        topic: self.topic.clone(),
+                        skipped: std::mem::take(&mut state.skipped),
+                    });
+                }
+
+                if let Some(payload) = state.events.pop_front() {
+                    return Ok(payload);
+                }
+
+                if state.closed {
+                    return Err(Error::HubDropped);
+                }
+            }
+
+            // A push while the lock was released leaves a permit, so it isn't missed
+            self.queue.notify.notified().await;
+        }
+    }
+
+    /// Calls `handler` with every event decoded, one at a time.
+    ///
+    /// Lagging is skipped. The task ends when the bus is dropped or the handler returns
+    /// [`Error::HandlerInvalidated`]. Events failing to decode or to be handled don't end it,
+    /// the first of these errors is returned once it ends.
+    pub fn spawn_with_codec<C, F, A>(self, handler: F, codec: Arc<C>) -> Task<Result<()>>
+    where
+        C: Codec<Data = D> + DecodeFor<C, A> + 'static,
+        F: Fn(A) -> AsyncResult<()> + Send + Sync + 'static,
+    {
+        self.spawn_with_codec_on(handler, codec, &AmbientSpawner, future::pending())
+    }
+
+    /// Like [`Subscription::spawn_with_codec`], running the task on `spawner`.
+    ///
+    /// The task also ends once `stop` completes, after handling the event it's busy with.
+    pub fn spawn_with_codec_on<C, F, A, S>(
+        mut self,
+        handler: F,
+        codec: Arc<C>,
+        spawner: &dyn Spawner,
+        stop: S,
+    ) -> Task<Result<()>>
+    where
+        C: Codec<Data = D> + DecodeFor<C, A> + 'static,
+        F: Fn(A) -> AsyncResult<()> + Send + Sync + 'static,
+        S: Future<Output = ()> + Send + 'static,
+    {
+        spawner::spawn(spawner, async move {
+            let mut stop = pin!(stop.fuse());
+            let mut first_error = None;
+
+            loop {
+                let payload = match select(pin!(self.recv()), stop.as_mut()).await {
+                    Either::Left((Ok(payload), _)) => payload,
+                    Either::Left((Err(Error::EventsLagged { .. }), _)) => continue,
+                    Either::Left((Err(_), _)) | Either::Right(_) => break,
+                };
+
+                let result = match codec.decode(payload).map(&handler) {
+                    Ok(handled) => handled.await,
+                    Err(e) => Err(e),
+                };
+
+                match result {
+                    Ok(()) => {}
+                    Err(Error::HandlerInvalidated) => break,
+                    Err(e) => {
+                        first_error.get_or_insert(e);
+                    }
+                }
+            }
+
+            first_error.map_or(Ok(()), Err)
+        })
+    }
+}
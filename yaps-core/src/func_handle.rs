@@ -1,38 +1,168 @@
 use crate::{
-    Result, YapsData,
+    Error, Result, YapsData,
     codec::{Codec, DecodeFor, EncodeFor},
+    stream_handle::DataStream,
 };
 
 use async_trait::async_trait;
+use futures::{
+    FutureExt, StreamExt,
+    future::{self, Either},
+    stream,
+};
+use std::{future::Future, sync::Arc};
 
-#[async_trait]
+/// Function handle, with native async methods.
+///
+/// Their futures aren't boxed, so handles wrapping each other cost nothing per call. Providers
+/// resolve functions to a `Box<dyn` [`DynFuncHandle`]`>` instead, which boxes the future of each
+/// call once. Boxed handles implement this trait as well, so both are called the same way.
 pub trait FuncHandle<D: YapsData>: Send + Sync {
-    async fn call(&self, args: D) -> Result<D>;
+    fn call(&self, args: D) -> impl Future<Output = Result<D>> + Send;
 
-    async fn call_with_codec<C, A, R>(&self, codec: &C, args: A) -> Result<R>
+    /// Encodes `args` and decodes the result with `codec`.
+    ///
+    /// Built from the future of the call without allocating, it's `Unpin` when that one is, e.g.
+    /// for boxed handles.
+    fn call_with_codec<C, A, R>(&self, codec: &C, args: A) -> impl Future<Output = Result<R>> + Send
     where
-        Self: Sized,
         A: Send,
         C: Codec<Data = D> + EncodeFor<C, A> + DecodeFor<C, R>,
     {
-        let data_in = codec.encode(args)?;
-        let data_out = self.call(data_in).await?;
-        codec.decode(data_out)
+        let data_out = match codec.encode(args) {
+            Ok(data_in) => Either::Left(self.call(data_in)),
+            Err(e) => Either::Right(future::err(e)),
+        };
+        data_out.map(move |data_out| codec.decode(data_out?))
+    }
+
+    /// Calls a function producing its results incrementally.
+    ///
+    /// Functions that don't stream are seen as a stream of their only result.
+    fn call_stream(&self, args: D) -> impl Future<Output = Result<DataStream<D>>> + Send {
+        self.call(args)
+            .map(|data_out| Ok(stream::iter([Ok(data_out?)]).boxed()))
+    }
+
+    /// Like [`FuncHandle::call_stream`], decoding each item as it arrives
+    fn call_stream_with_codec<C, A, R>(
+        &self,
+        codec: Arc<C>,
+        args: A,
+    ) -> impl Future<Output = Result<stream::BoxStream<'static, Result<R>>>> + Send
+    where
+        A: Send,
+        C: Codec<Data = D> + EncodeFor<C, A> + DecodeFor<C, R> + 'static,
+        R: Send + 'static,
+    {
+        let data_out = match codec.encode(args) {
+            Ok(data_in) => Either::Left(self.call_stream(data_in)),
+            Err(e) => Either::Right(future::err(e)),
+        };
+        data_out.map(move |data_out| Ok(data_out?.map(move |item| codec.decode(item?)).boxed()))
+    }
+
+    /// Opens a session sending a stream of inputs to the function and receiving its outputs.
+    ///
+    /// Fails with [`Error::NotAChannel`] by default, naming the handle by its type since it
+    /// doesn't know the function's id.
+    fn open_channel(
+        &self,
+        _inputs: DataStream<D>,
+    ) -> impl Future<Output = Result<DataStream<D>>> + Send {
+        future::err(Error::NotAChannel(
+            std::any::type_name::<Self>().to_string(),
+        ))
+    }
+
+    /// Like [`FuncHandle::open_channel`], encoding each input and decoding each output
+    fn open_channel_with_codec<C, I, R>(
+        &self,
+        codec: Arc<C>,
+        inputs: stream::BoxStream<'static, I>,
+    ) -> impl Future<Output = Result<stream::BoxStream<'static, Result<R>>>> + Send
+    where
+        C: Codec<Data = D> + EncodeFor<C, I> + DecodeFor<C, R> + 'static,
+        I: Send + 'static,
+        R: Send + 'static,
+    {
+        let input_codec = codec.clone();
+        let data_in = inputs.map(move |input| input_codec.encode(input)).boxed();
+
+        self.open_channel(data_in)
+            .map(move |data_out| Ok(data_out?.map(move |item| codec.decode(item?)).boxed()))
     }
 }
 
+/// Object-safe adapter of [`FuncHandle`], the type providers resolve functions to.
+///
+/// Every handle implements it, boxing the future of each call. Its methods are named apart
+/// from the ones of [`FuncHandle`] so both traits can be in scope, they're called through
+/// [`FuncHandle`] anyway.
 #[async_trait]
-impl<D, T, U> FuncHandle<D> for U
-where
-    D: YapsData,
-    T: FuncHandle<D> + ?Sized,
-    U: std::ops::Deref<Target = T> + Send + Sync,
-{
-    async fn call(&self, args: D) -> Result<D> {
-        self.deref().call(args).await
+pub trait DynFuncHandle<D: YapsData>: Send + Sync {
+    async fn dyn_call(&self, args: D) -> Result<D>;
+
+    async fn dyn_call_stream(&self, args: D) -> Result<DataStream<D>>;
+
+    async fn dyn_open_channel(&self, inputs: DataStream<D>) -> Result<DataStream<D>>;
+}
+
+#[async_trait]
+impl<D: YapsData, H: FuncHandle<D>> DynFuncHandle<D> for H {
+    async fn dyn_call(&self, args: D) -> Result<D> {
+        self.call(args).await
+    }
+
+    async fn dyn_call_stream(&self, args: D) -> Result<DataStream<D>> {
+        self.call_stream(args).await
+    }
+
+    async fn dyn_open_channel(&self, inputs: DataStream<D>) -> Result<DataStream<D>> {
+        self.open_channel(inputs).await
     }
 }
 
+impl<D: YapsData> FuncHandle<D> for dyn DynFuncHandle<D> + '_ {
+    fn call(&self, args: D) -> impl Future<Output = Result<D>> + Send {
+        self.dyn_call(args)
+    }
+
+    fn call_stream(&self, args: D) -> impl Future<Output = Result<DataStream<D>>> + Send {
+        self.dyn_call_stream(args)
+    }
+
+    fn open_channel(
+        &self,
+        inputs: DataStream<D>,
+    ) -> impl Future<Output = Result<DataStream<D>>> + Send {
+        self.dyn_open_channel(inputs)
+    }
+}
+
+macro_rules! forward_func_handle {
+    ($($ptr:ty),*) => {$(
+        impl<D: YapsData, H: FuncHandle<D> + ?Sized> FuncHandle<D> for $ptr {
+            fn call(&self, args: D) -> impl Future<Output = Result<D>> + Send {
+                (**self).call(args)
+            }
+
+            fn call_stream(&self, args: D) -> impl Future<Output = Result<DataStream<D>>> + Send {
+                (**self).call_stream(args)
+            }
+
+            fn open_channel(
+                &self,
+                inputs: DataStream<D>,
+            ) -> impl Future<Output = Result<DataStream<D>>> + Send {
+                (**self).open_channel(inputs)
+            }
+        }
+    )*};
+}
+
+forward_func_handle!(&H, Box<H>, Arc<H>);
+
 pub struct SimpleHandle<D: YapsData, F: FnMut(D) -> Result<D> + Send + Sync> {
     _marker: std::marker::PhantomData<fn(D)>,
     func: F,
@@ -47,9 +177,8 @@ impl<D: YapsData, F: Fn(D) -> Result<D> + Send + Sync> SimpleHandle<D, F> {
     }
 }
 
-#[async_trait]
 impl<D: YapsData, F: Fn(D) -> Result<D> + Send + Sync> FuncHandle<D> for SimpleHandle<D, F> {
-    async fn call(&self, args: D) -> Result<D> {
-        (self.func)(args)
+    fn call(&self, args: D) -> impl Future<Output = Result<D>> + Send {
+        future::ready((self.func)(args))
     }
 }
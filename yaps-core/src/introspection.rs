@@ -0,0 +1,28 @@
+use crate::FuncMetadata;
+
+use serde::{Deserialize, Serialize};
+
+/// Snapshot of everything registered in a hub
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct HubInfo {
+    pub plugins: Vec<PluginInfo>,
+    pub bindings: Vec<Binding>,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct PluginInfo {
+    /// Position of the plugin in registration order, referenced by [`Binding`]
+    pub index: usize,
+    pub name: String,
+    pub provides: Vec<FuncMetadata>,
+    pub consumes: Vec<FuncMetadata>,
+}
+
+/// A single consumed function and the plugin it was resolved to
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct Binding {
+    pub consumer: usize,
+    pub func: String,
+    /// `None` if it wasn't resolved from a registered plugin, or not yet for lazy externs
+    pub provider: Option<usize>,
+}
@@ -0,0 +1,107 @@
+use crate::{
+    DynFuncProvider, Error, FuncHandle, FuncProvider, Result, YapsData,
+    shared_handle::SharedHandle, stream_handle::DataStream,
+};
+
+use std::sync::{Arc, OnceLock};
+use tokio::sync::OnceCell;
+
+/// Function handle resolved from a provider on its first call and cached afterwards.
+///
+/// If resolving fails, the error is returned from the call and resolving is retried on the next
+/// one. A handle can also be set eagerly, in which case no provider is needed.
+pub struct LazyHandle<D: YapsData> {
+    id: String,
+    provider: OnceLock<Arc<dyn DynFuncProvider<D>>>,
+    handle: OnceCell<SharedHandle<D>>,
+}
+
+impl<D: YapsData> std::fmt::Debug for LazyHandle<D> {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("LazyHandle")
+            .field("id", &self.id)
+            .field("attached", &self.is_attached())
+            .field("resolved", &self.is_resolved())
+            .finish()
+    }
+}
+
+impl<D: YapsData> LazyHandle<D> {
+    pub fn new(id: impl Into<String>) -> Self {
+        Self {
+            id: id.into(),
+            provider: OnceLock::new(),
+            handle: OnceCell::new(),
+        }
+    }
+
+    /// Sets the provider used to resolve the function, only the first one is kept
+    pub fn attach(&self, provider: Arc<dyn DynFuncProvider<D>>) {
+        let _ = self.provider.set(provider);
+    }
+
+    pub fn is_attached(&self) -> bool {
+        self.provider.get().is_some()
+    }
+
+    /// Sets an already resolved handle, only the first one is kept
+    pub fn set(&self, handle: impl Into<SharedHandle<D>>) {
+        let _ = self.handle.set(handle.into());
+    }
+
+    pub fn is_resolved(&self) -> bool {
+        self.handle.initialized()
+    }
+
+    /// Whether the function can be called, without resolving it if it wasn't already
+    pub async fn is_provided(&self) -> bool {
+        if self.is_resolved() {
+            return true;
+        }
+
+        let Some(provider) = self.provider.get() else {
+            return false;
+        };
+
+        provider
+            .provided_funcs()
+            .await
+            .is_ok_and(|funcs| funcs.iter().any(|func| func.id == self.id.as_str()))
+    }
+
+    /// Resolves the handle, returning `None` if the function isn't provided
+    pub async fn get(&self) -> Result<Option<&SharedHandle<D>>> {
+        match self.resolve().await {
+            Ok(handle) => Ok(Some(handle)),
+            Err(Error::FunctionNotFound(_) | Error::FunctionNotInitialized(_)) => Ok(None),
+            Err(e) => Err(e),
+        }
+    }
+
+    async fn resolve(&self) -> Result<&SharedHandle<D>> {
+        self.handle
+            .get_or_try_init(|| async {
+                let provider = self
+                    .provider
+                    .get()
+                    .ok_or(Error::FunctionNotInitialized(self.id.clone()))?;
+
+                provider.get_shared_func(&self.id).await
+            })
+            .await
+    }
+}
+
+impl<D: YapsData> FuncHandle<D> for LazyHandle<D> {
+    async fn call(&self, args: D) -> Result<D> {
+        self.resolve().await?.call(args).await
+    }
+
+    async fn call_stream(&self, args: D) -> Result<DataStream<D>> {
+        self.resolve().await?.call_stream(args).await
+    }
+
+    async fn open_channel(&self, inputs: DataStream<D>) -> Result<DataStream<D>> {
+        self.resolve().await?.open_channel(inputs).await
+    }
+}
@@ -4,18 +4,38 @@ pub mod error;
 pub use error::{Error, Result};
 
 mod consumer_provider;
-pub use consumer_provider::{FuncConsumer, FuncMetadata, FuncProvider, YapsData};
+pub use consumer_provider::{
+    DynFuncConsumer, DynFuncProvider, FuncConsumer, FuncMetadata, FuncProvider, Plugin, YapsData,
+};
 
 mod single_provider;
 pub use single_provider::SingleProvider;
 
 mod func_handle;
-pub use func_handle::FuncHandle;
+pub use func_handle::{DynFuncHandle, FuncHandle};
 
 pub mod actor_handle;
+pub mod balanced_handle;
+pub mod blocking;
+pub mod blocking_handle;
+pub mod broadcast_handle;
+pub mod callback;
+pub mod channel_handle;
+pub mod direct_handle;
+pub mod lazy_handle;
+pub mod sharded_handle;
+pub mod shared_handle;
+pub mod stream_handle;
 
 pub mod codec;
+pub mod event_bus;
+pub mod introspection;
 pub mod local_hub;
+mod registry;
+pub mod shared_hub;
+pub mod shutdown;
+pub mod spawner;
 
 pub use async_trait;
+pub use futures;
 pub use tokio;
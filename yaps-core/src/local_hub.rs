@@ -1,72 +1,99 @@
-use crate::{Error, Result};
-use crate::{FuncConsumer, FuncHandle, FuncMetadata, FuncProvider, YapsData};
+use crate::balanced_handle::{BalanceStrategy, BalancedHandle};
+use crate::blocking_handle::{BlockingHandle, HubRuntime};
+use crate::broadcast_handle::BroadcastHandle;
+use crate::callback::CallbackTable;
+use crate::event_bus::EventBus;
+use crate::introspection::HubInfo;
+use crate::registry::{Registry, SharedRegistry};
+use crate::sharded_handle::ShardedHandle;
+use crate::shared_handle::SharedHandle;
+use crate::shared_hub::SharedHub;
+use crate::shutdown::ShutdownReport;
+use crate::spawner::{RuntimeConfig, Spawner};
+use crate::{
+    DynFuncHandle, DynFuncProvider, FuncConsumer, FuncMetadata, FuncProvider, Plugin, Result,
+    YapsData,
+};
+
+use std::{fmt, sync::Arc, time::Duration};
+
+use arc_swap::ArcSwap;
+
+pub use crate::registry::DEFAULT_CONNECT_CONCURRENCY;
 
-use std::sync::Arc;
-
-use async_trait::async_trait;
-
-struct Provider<D> {
-    pub provider: Arc<dyn FuncProvider<D>>,
-    pub funcs: Vec<FuncMetadata>,
-}
-
-type Consumer<D> = Arc<dyn FuncConsumer<D>>;
-
-// TODO: Implement Debug
-#[allow(missing_debug_implementations)]
 pub struct LocalHub<D: YapsData> {
-    providers: Vec<Provider<D>>,
-    consumers: Vec<Consumer<D>>,
+    registry: SharedRegistry<D>,
+    runtime: HubRuntime,
 }
 
 impl<D: YapsData> Default for LocalHub<D> {
     fn default() -> Self {
         Self {
-            providers: Vec::new(),
-            consumers: Vec::new(),
+            registry: Arc::new(ArcSwap::from_pointee(Registry::default())),
+            runtime: HubRuntime::default(),
         }
     }
 }
 
-// TODO: Implementation of LocalHub should be parallelized
+impl<D: YapsData> fmt::Debug for LocalHub<D> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("LocalHub")
+            .field("registry", &self.registry.load())
+            .field("runtime", &self.runtime)
+            .finish()
+    }
+}
 
-#[async_trait]
 impl<D: YapsData> FuncProvider<D> for LocalHub<D> {
     async fn provided_funcs(&self) -> Result<Vec<FuncMetadata>> {
-        let funcs: Vec<_> = self
-            .providers
-            .iter()
-            .flat_map(|p| &p.funcs)
-            .cloned()
-            .collect();
+        Ok(self.registry.load().view().provided())
+    }
 
-        Ok(funcs)
+    async fn get_func(&self, id: &str) -> Result<Box<dyn DynFuncHandle<D>>> {
+        self.registry.load_full().view().get_func(id).await
     }
 
-    async fn get_func(&self, id: &str) -> Result<Box<dyn FuncHandle<D>>> {
-        let mut providers = self
-            .providers
-            .iter()
-            .filter(|p| p.funcs.iter().any(|f| f.id == id));
+    async fn get_all_funcs(&self, id: &str) -> Result<Vec<Box<dyn DynFuncHandle<D>>>> {
+        self.registry.load_full().view().get_all_funcs(id).await
+    }
 
-        let provider = providers
-            .next()
-            .ok_or(Error::FunctionNotFound(id.to_string()))?;
+    async fn get_shared_func(&self, id: &str) -> Result<SharedHandle<D>> {
+        self.registry.load_full().view().get_shared_func(id).await
+    }
 
-        let func = provider.provider.get_func(id).await?;
+    async fn get_all_shared_funcs(&self, id: &str) -> Result<Vec<SharedHandle<D>>> {
+        self.registry
+            .load_full()
+            .view()
+            .get_all_shared_funcs(id)
+            .await
+    }
 
-        Ok(func)
+    async fn get_all_named_funcs(&self, id: &str) -> Result<Vec<(String, SharedHandle<D>)>> {
+        self.registry
+            .load_full()
+            .view()
+            .get_all_named_funcs(id)
+            .await
+    }
+
+    // Plugins of either hub can receive the callbacks created in the other one
+    fn attach_callbacks(&self, callbacks: &Arc<CallbackTable>) {
+        self.registry.load().callbacks().link(callbacks)
     }
 }
 
-#[async_trait]
 impl<D: YapsData> FuncConsumer<D> for LocalHub<D> {
-    async fn connect(&self, provider: &dyn FuncProvider<D>) -> Result<()> {
-        for consumer in self.consumers.iter() {
-            consumer.connect(provider).await?;
-        }
+    async fn consumed_funcs(&self) -> Result<Vec<FuncMetadata>> {
+        Ok(self.registry.load().consumed_funcs())
+    }
+
+    fn attach_callbacks(&self, callbacks: &Arc<CallbackTable>) {
+        self.registry.load().callbacks().link(callbacks)
+    }
 
-        Ok(())
+    async fn connect(&self, provider: &dyn DynFuncProvider<D>) -> Result<()> {
+        self.registry.load_full().connect(provider).await
     }
 }
 
@@ -75,42 +102,152 @@ impl<D: YapsData> LocalHub<D> {
         Self::default()
     }
 
-    pub async fn add_provider(&mut self, provider: impl FuncProvider<D> + 'static) -> Result<()> {
-        self.connect(&provider).await?;
+    /// Sets how many plugins are connected at the same time (at least one)
+    pub fn with_connect_concurrency(self, limit: usize) -> Self {
+        Registry::configure(&self.registry, |registry| {
+            registry.set_connect_concurrency(limit)
+        });
+        self
+    }
+
+    /// Turns the hub into one that can be shared and extended behind an `Arc`
+    pub fn into_shared(self) -> SharedHub<D> {
+        SharedHub::from_parts(self.registry, self.runtime)
+    }
+
+    /// Sets how many events each subscription buffers, including the existing subscriptions
+    pub fn with_event_capacity(self, capacity: usize) -> Self {
+        self.registry.load().events().set_capacity(capacity);
+        self
+    }
 
-        let funcs = provider.provided_funcs().await?;
+    /// Sets where the tasks of plugins registered afterwards are spawned, unless their wrapper
+    /// was given a spawner already. They're spawned on the ambient tokio runtime by default.
+    pub fn with_spawner(self, spawner: Arc<dyn Spawner>) -> Self {
+        Registry::configure(&self.registry, |registry| registry.set_spawner(spawner));
+        self
+    }
 
-        self.providers.push(Provider {
-            provider: Arc::new(provider),
-            funcs,
+    /// Isolates the plugin named `plugin` on a runtime of its own, configured by `config`.
+    ///
+    /// Its actors, streams and subscriptions run on the runtime's threads, so a plugin blocking
+    /// them doesn't starve the others. Calls from other plugins reach it over channels like any
+    /// other call. Only affects plugins registered afterwards, and takes precedence over
+    /// [`LocalHub::with_spawner`] but not over a spawner given to the plugin's wrapper.
+    pub fn with_plugin_runtime(self, plugin: impl Into<String>, config: RuntimeConfig) -> Self {
+        Registry::configure(&self.registry, |registry| {
+            registry.set_plugin_runtime(plugin.into(), config)
         });
-        Ok(())
+        self
+    }
+
+    /// Event bus shared by the registered plugins, see [`EventBus`] for delivery guarantees
+    pub fn events(&self) -> Arc<EventBus<D>> {
+        self.registry.load().events().clone()
+    }
+
+    pub async fn add_provider(&mut self, provider: impl FuncProvider<D> + 'static) -> Result<()> {
+        let registry = self.registry.load_full().with_provider(provider).await?;
+        Registry::publish(&self.registry, registry).await
     }
 
     pub async fn add_consumer(&mut self, consumer: impl FuncConsumer<D> + 'static) -> Result<()> {
-        consumer.connect(self).await?;
+        let registry = self
+            .registry
+            .load_full()
+            .with_consumer(consumer, &self.registry)
+            .await?;
+        Registry::publish(&self.registry, registry).await
+    }
 
-        self.consumers.push(Arc::new(consumer));
-        Ok(())
+    pub async fn add_plugin(&mut self, cp: impl Plugin<D> + 'static) -> Result<()> {
+        self.add_plugins([Arc::new(cp) as Arc<dyn Plugin<D>>]).await
     }
 
-    pub async fn add_plugin(
+    /// Registers several plugins at once, connecting them concurrently.
+    ///
+    /// The plugins can consume functions provided by each other regardless of their order.
+    /// If any of them fails to connect, none of them get registered and the errors of all
+    /// failing plugins are returned together.
+    ///
+    /// The plugins registered before are only connected to them once they've all started. If
+    /// that fails, the new plugins stay registered and the errors of the plugins that couldn't
+    /// connect are returned.
+    ///
+    /// Their [`FuncProvider::on_init`] hooks run before connecting and their
+    /// [`FuncProvider::on_ready`] hooks once everything is connected, providers before the
+    /// plugins consuming their functions. A failing hook aborts the registration the same way,
+    /// shutting down the plugins already initialized.
+    pub async fn add_plugins(
         &mut self,
-        cp: impl FuncProvider<D> + FuncConsumer<D> + 'static,
+        plugins: impl IntoIterator<Item = Arc<dyn Plugin<D>>>,
     ) -> Result<()> {
-        self.connect(&cp).await?;
-        cp.connect(self).await?;
+        let plugins = plugins.into_iter().collect();
+        let registry = self
+            .registry
+            .load_full()
+            .with_plugins(plugins, &self.registry)
+            .await?;
+        Registry::publish(&self.registry, registry).await
+    }
 
-        let cp = Arc::new(cp);
+    /// Returns a handle calling every plugin currently providing `id`.
+    ///
+    /// The handle is empty if nothing provides the function, plugins registered afterwards
+    /// aren't added to it.
+    pub async fn get_broadcast(&self, id: &str) -> Result<BroadcastHandle<D>> {
+        self.registry.load_full().view().get_broadcast(id).await
+    }
+
+    /// Returns a handle spreading calls over every plugin currently providing `id`
+    pub async fn get_balanced(
+        &self,
+        id: &str,
+        strategy: BalanceStrategy,
+    ) -> Result<BalancedHandle<D>> {
+        self.registry
+            .load_full()
+            .view()
+            .get_balanced(id, strategy)
+            .await
+    }
+
+    /// Returns a handle routing calls by key over every plugin currently providing `id`
+    pub async fn get_sharded(&self, id: &str) -> Result<ShardedHandle<D>> {
+        self.registry.load_full().view().get_sharded(id).await
+    }
 
-        let funcs = cp.provided_funcs().await?;
-        let provider = Provider {
-            provider: cp.clone(),
-            funcs,
-        };
+    /// Runtime owned by the hub to drive blocking calls, started on first use.
+    ///
+    /// Synchronous code can also use it to register plugins, e.g.
+    /// `hub.runtime()?.block_on(hub.add_plugin(plugin))`.
+    pub fn runtime(&self) -> Result<tokio::runtime::Handle> {
+        Ok(self.runtime.get()?.handle().clone())
+    }
+
+    /// Returns a handle to `id` callable from synchronous code, see [`BlockingHandle`].
+    ///
+    /// Fails with [`Error::InsideRuntime`] if called from inside a runtime.
+    pub fn get_func_blocking(&self, id: &str) -> Result<BlockingHandle<D>> {
+        self.runtime.get_func(self.get_shared_func(id))
+    }
+
+    /// Shuts every plugin down, waiting at most `timeout` for them.
+    ///
+    /// Plugins are shut down one at a time, consumers before the providers they depend on. Each
+    /// of them stops accepting new calls, lets the calls in flight finish, runs its
+    /// [`FuncProvider::on_shutdown`] hook and has its actor tasks joined. Anything still running
+    /// when the timeout elapses is listed in the returned report, tasks are aborted then. So are
+    /// the plugins depending on each other, which can't be shut down in dependency order.
+    pub async fn shutdown(self, timeout: Duration) -> ShutdownReport {
+        self.registry.load_full().shutdown(timeout).await
+    }
 
-        self.providers.push(provider);
-        self.consumers.push(cp);
-        Ok(())
+    /// Describes the registered plugins and how their consumed functions were resolved.
+    ///
+    /// A consumed function is bound to the plugin it was actually resolved from when connecting,
+    /// or when a lazy extern was first called.
+    pub fn introspect(&self) -> HubInfo {
+        self.registry.load().introspect()
     }
 }
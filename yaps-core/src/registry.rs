@@ -0,0 +1,1047 @@
+use crate::balanced_handle::{BalanceStrategy, BalancedHandle};
+use crate::broadcast_handle::BroadcastHandle;
+use crate::callback::CallbackTable;
+use crate::consumer_provider::shard_name;
+use crate::event_bus::EventBus;
+use crate::introspection::{Binding, HubInfo, PluginInfo};
+use crate::sharded_handle::ShardedHandle;
+use crate::shared_handle::SharedHandle;
+use crate::shutdown::{ShutdownReport, TaskTracker, Unfinished};
+use crate::spawner::{RuntimeConfig, RuntimeSpawner, Spawner};
+use crate::{
+    DynFuncConsumer, DynFuncHandle, DynFuncProvider, FuncConsumer, FuncMetadata, FuncProvider,
+    Plugin, YapsData,
+};
+use crate::{Error, Result};
+
+use std::{
+    cmp::Reverse,
+    collections::{BinaryHeap, HashMap},
+    fmt,
+    sync::{Arc, Mutex, Weak},
+    time::Duration,
+};
+
+use arc_swap::ArcSwap;
+use futures::{FutureExt, StreamExt, future::BoxFuture, stream};
+use tokio::time::{Instant, timeout_at};
+
+/// Default number of plugins connected concurrently
+pub const DEFAULT_CONNECT_CONCURRENCY: usize = 16;
+
+pub(crate) struct PluginEntry<D> {
+    name: String,
+    provider: Option<Arc<dyn DynFuncProvider<D>>>,
+    consumer: Option<Arc<dyn DynFuncConsumer<D>>>,
+    provided: Vec<FuncMetadata>,
+    consumed: Vec<FuncMetadata>,
+    // Plugin each consumed function was resolved from, the first one wins like for externs
+    bindings: Mutex<HashMap<Arc<str>, Weak<PluginEntry<D>>>>,
+}
+
+impl<D: YapsData> PluginEntry<D> {
+    fn new(
+        name: String,
+        provider: Option<Arc<dyn DynFuncProvider<D>>>,
+        consumer: Option<Arc<dyn DynFuncConsumer<D>>>,
+        provided: Vec<FuncMetadata>,
+        consumed: Vec<FuncMetadata>,
+    ) -> Self {
+        Self {
+            name,
+            provider,
+            consumer,
+            provided,
+            consumed,
+            bindings: Mutex::default(),
+        }
+    }
+
+    fn bind(&self, id: &Arc<str>, provider: &Arc<PluginEntry<D>>) {
+        let mut bindings = self.bindings.lock().expect("plugin bindings poisoned");
+        bindings
+            .entry(id.clone())
+            .or_insert_with(|| Arc::downgrade(provider));
+    }
+
+    fn bound_to(&self, id: &str) -> Option<Arc<PluginEntry<D>>> {
+        let bindings = self.bindings.lock().expect("plugin bindings poisoned");
+        bindings.get(id).and_then(Weak::upgrade)
+    }
+
+    fn wrap_error(&self, error: Error) -> Error {
+        Error::Plugin {
+            plugin: self.name.clone(),
+            error: Box::new(error),
+        }
+    }
+
+    // Plugins registered as both providers and consumers are only called as providers, so
+    // their hooks run once
+
+    async fn run_hook(&self, hook: Hook) -> Result<()> {
+        let result = match (&self.provider, &self.consumer) {
+            (Some(provider), _) => match hook {
+                Hook::Init => provider.on_init().await,
+                Hook::Ready => provider.on_ready().await,
+                Hook::Shutdown => provider.on_shutdown().await,
+            },
+            (None, Some(consumer)) => match hook {
+                Hook::Init => consumer.on_init().await,
+                Hook::Ready => consumer.on_ready().await,
+                Hook::Shutdown => consumer.on_shutdown().await,
+            },
+            (None, None) => Ok(()),
+        };
+
+        result.map_err(|e| self.wrap_error(e))
+    }
+
+    fn task_tracker(&self) -> Option<Arc<TaskTracker>> {
+        match (&self.provider, &self.consumer) {
+            (Some(provider), _) => provider.task_tracker(),
+            (None, Some(consumer)) => consumer.task_tracker(),
+            (None, None) => None,
+        }
+    }
+
+    fn attach_spawner(&self, spawner: &Arc<dyn Spawner>) {
+        match (&self.provider, &self.consumer) {
+            (Some(provider), _) => provider.attach_spawner(spawner),
+            (None, Some(consumer)) => consumer.attach_spawner(spawner),
+            (None, None) => {}
+        }
+    }
+
+    fn has_spawner(&self) -> bool {
+        match (&self.provider, &self.consumer) {
+            (Some(provider), _) => provider.has_spawner(),
+            (None, Some(consumer)) => consumer.has_spawner(),
+            (None, None) => false,
+        }
+    }
+
+    fn attach_hub(&self, events: &Arc<EventBus<D>>, callbacks: &Arc<CallbackTable>) {
+        match (&self.provider, &self.consumer) {
+            (Some(provider), _) => {
+                provider.attach_events(events);
+                provider.attach_callbacks(callbacks);
+            }
+            (None, Some(consumer)) => {
+                consumer.attach_events(events);
+                consumer.attach_callbacks(callbacks);
+            }
+            (None, None) => {}
+        }
+    }
+}
+
+/// Lifecycle hooks of a [`FuncProvider`] or [`FuncConsumer`]
+#[derive(Debug, Clone, Copy)]
+enum Hook {
+    Init,
+    Ready,
+    Shutdown,
+}
+
+/// Orders plugins so that the providers of a function come before its consumers.
+///
+/// Plugins come in registration order as far as their dependencies allow. Plugins depending on
+/// each other, directly or not, can't be ordered: they're kept together in registration order
+/// and also returned as a cycle, named after its plugins.
+fn dependency_order<D: YapsData>(
+    plugins: &[Arc<PluginEntry<D>>],
+) -> (Vec<Arc<PluginEntry<D>>>, Vec<Vec<String>>) {
+    let mut providers: HashMap<&str, Vec<usize>> = HashMap::new();
+    for (i, plugin) in plugins.iter().enumerate() {
+        for func in &plugin.provided {
+            providers.entry(func.id.as_str()).or_default().push(i);
+        }
+    }
+
+    let deps: Vec<Vec<usize>> = plugins
+        .iter()
+        .enumerate()
+        .map(|(i, plugin)| {
+            let mut deps: Vec<usize> = plugin
+                .consumed
+                .iter()
+                .filter_map(|func| providers.get(func.id.as_str()))
+                .flatten()
+                .copied()
+                .filter(|&j| j != i)
+                .collect();
+            deps.sort_unstable();
+            deps.dedup();
+            deps
+        })
+        .collect();
+
+    let components = strongly_connected(&deps);
+    let mut component_of = vec![0; plugins.len()];
+    for (c, component) in components.iter().enumerate() {
+        for &i in component {
+            component_of[i] = c;
+        }
+    }
+
+    // Kahn's algorithm over the components, the one registered first goes first
+    let mut waiting_on = vec![0; components.len()];
+    let mut dependents = vec![Vec::new(); components.len()];
+    for (c, component) in components.iter().enumerate() {
+        let mut dep_components: Vec<usize> = component
+            .iter()
+            .flat_map(|&i| &deps[i])
+            .map(|&j| component_of[j])
+            .filter(|&d| d != c)
+            .collect();
+        dep_components.sort_unstable();
+        dep_components.dedup();
+
+        waiting_on[c] = dep_components.len();
+        for d in dep_components {
+            dependents[d].push(c);
+        }
+    }
+
+    let mut ready: BinaryHeap<_> = (0..components.len())
+        .filter(|&c| waiting_on[c] == 0)
+        .map(|c| Reverse((components[c][0], c)))
+        .collect();
+    let mut ordered = Vec::with_capacity(plugins.len());
+    let mut cycles = Vec::new();
+
+    while let Some(Reverse((_, c))) = ready.pop() {
+        let component = &components[c];
+        ordered.extend(component.iter().map(|&i| plugins[i].clone()));
+        if component.len() > 1 {
+            cycles.push(component.iter().map(|&i| plugins[i].name.clone()).collect());
+        }
+
+        for &d in &dependents[c] {
+            waiting_on[d] -= 1;
+            if waiting_on[d] == 0 {
+                ready.push(Reverse((components[d][0], d)));
+            }
+        }
+    }
+
+    (ordered, cycles)
+}
+
+/// Groups the plugins depending on each other with Tarjan's algorithm, each group sorted
+fn strongly_connected(deps: &[Vec<usize>]) -> Vec<Vec<usize>> {
+    struct Tarjan<'a> {
+        deps: &'a [Vec<usize>],
+        // Order in which each plugin was visited
+        visited: Vec<Option<usize>>,
+        next: usize,
+        low: Vec<usize>,
+        on_stack: Vec<bool>,
+        stack: Vec<usize>,
+        components: Vec<Vec<usize>>,
+    }
+
+    impl Tarjan<'_> {
+        fn visit(&mut self, i: usize) {
+            self.visited[i] = Some(self.next);
+            self.low[i] = self.next;
+            self.next += 1;
+            self.stack.push(i);
+            self.on_stack[i] = true;
+
+            for &j in &self.deps[i] {
+                match self.visited[j] {
+                    None => {
+                        self.visit(j);
+                        self.low[i] = self.low[i].min(self.low[j]);
+                    }
+                    Some(order) if self.on_stack[j] => self.low[i] = self.low[i].min(order),
+                    Some(_) => {}
+                }
+            }
+
+            if Some(self.low[i]) == self.visited[i] {
+                let start = self.stack.iter().rposition(|&j| j == i).expect("visited");
+                let mut component = self.stack.split_off(start);
+                for &j in &component {
+                    self.on_stack[j] = false;
+                }
+                component.sort_unstable();
+                self.components.push(component);
+            }
+        }
+    }
+
+    let mut tarjan = Tarjan {
+        deps,
+        visited: vec![None; deps.len()],
+        next: 0,
+        low: vec![0; deps.len()],
+        on_stack: vec![false; deps.len()],
+        stack: Vec::new(),
+        components: Vec::new(),
+    };
+
+    for i in 0..deps.len() {
+        if tarjan.visited[i].is_none() {
+            tarjan.visit(i);
+        }
+    }
+
+    tarjan.components
+}
+
+/// Plugins registered in a hub together with the logic shared by all hub flavours.
+///
+/// Registering returns a new registry instead of changing the current one, and only touches the
+/// plugins being registered. This lets [`SharedHub`](crate::shared_hub::SharedHub) keep serving
+/// lookups from the previous registry while a registration is in progress. The plugins
+/// registered before are only connected to the new ones once the new registry is published, see
+/// [`Registry::publish`].
+pub(crate) struct Registry<D> {
+    plugins: Vec<Arc<PluginEntry<D>>>,
+    // Positions of the plugins providing each function, in registration order
+    index: HashMap<Arc<str>, Vec<usize>>,
+    connect_concurrency: usize,
+    events: Arc<EventBus<D>>,
+    callbacks: Arc<CallbackTable>,
+    spawner: Option<Arc<dyn Spawner>>,
+    plugin_runtimes: HashMap<String, RuntimeConfig>,
+}
+
+impl<D> Clone for Registry<D> {
+    fn clone(&self) -> Self {
+        Self {
+            plugins: self.plugins.clone(),
+            index: self.index.clone(),
+            connect_concurrency: self.connect_concurrency,
+            events: self.events.clone(),
+            callbacks: self.callbacks.clone(),
+            spawner: self.spawner.clone(),
+            plugin_runtimes: self.plugin_runtimes.clone(),
+        }
+    }
+}
+
+impl<D: YapsData> Default for Registry<D> {
+    fn default() -> Self {
+        Self {
+            plugins: Vec::new(),
+            index: HashMap::new(),
+            connect_concurrency: DEFAULT_CONNECT_CONCURRENCY,
+            events: Arc::new(EventBus::default()),
+            callbacks: Arc::new(CallbackTable::new()),
+            spawner: None,
+            plugin_runtimes: HashMap::new(),
+        }
+    }
+}
+
+impl<D: YapsData> fmt::Debug for Registry<D> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let info = self.introspect();
+        f.debug_struct("Registry")
+            .field("plugins", &info.plugins)
+            .field("bindings", &info.bindings)
+            .field("connect_concurrency", &self.connect_concurrency)
+            .field("events", &self.events)
+            .field("callbacks", &self.callbacks)
+            .field("spawner", &self.spawner)
+            .field("plugin_runtimes", &self.plugin_runtimes)
+            .finish()
+    }
+}
+
+/// The plugins of a registry a lookup can resolve functions from.
+///
+/// Views handed to a consumer exclude the consumer itself, plugins being told apart by identity,
+/// and record which plugin each of its functions was resolved from.
+pub(crate) struct RegistryView<'a, D: YapsData> {
+    registry: &'a Registry<D>,
+    consumer: Option<&'a Arc<PluginEntry<D>>>,
+    // Plugins registered before this position are left out
+    from: usize,
+}
+
+impl<'a, D: YapsData> RegistryView<'a, D> {
+    pub(crate) fn provided(&self) -> Vec<FuncMetadata> {
+        self.registry.plugins[self.from..]
+            .iter()
+            .filter(|p| !self.is_consumer(p) && p.provider.is_some())
+            .flat_map(|p| &p.provided)
+            .cloned()
+            .collect()
+    }
+
+    fn is_consumer(&self, plugin: &Arc<PluginEntry<D>>) -> bool {
+        self.consumer
+            .is_some_and(|consumer| Arc::ptr_eq(plugin, consumer))
+    }
+
+    /// The indexed id and every plugin providing it, in registration order
+    fn providers_of(
+        &self,
+        id: &str,
+    ) -> Option<(&'a Arc<str>, impl Iterator<Item = &'a Arc<PluginEntry<D>>>)> {
+        let registry = self.registry;
+        let (id, positions) = registry.index.get_key_value(id)?;
+        let (from, consumer) = (self.from, self.consumer);
+
+        let providers = positions
+            .iter()
+            .filter(move |i| **i >= from)
+            .map(|i| &registry.plugins[*i])
+            .filter(move |p| !consumer.is_some_and(|consumer| Arc::ptr_eq(p, consumer)));
+
+        Some((id, providers))
+    }
+
+    fn first_provider(&self, id: &str) -> Result<(&'a Arc<str>, &'a Arc<PluginEntry<D>>)> {
+        self.providers_of(id)
+            .and_then(|(id, mut providers)| Some((id, providers.next()?)))
+            .ok_or(Error::FunctionNotFound(id.to_string()))
+    }
+
+    fn bind(&self, id: &Arc<str>, provider: &Arc<PluginEntry<D>>) {
+        if let Some(consumer) = self.consumer {
+            consumer.bind(id, provider);
+        }
+    }
+
+    pub(crate) async fn get_broadcast(&self, id: &str) -> Result<BroadcastHandle<D>> {
+        let broadcast = BroadcastHandle::new(id);
+        broadcast.extend(self.get_all_shared_funcs(id).await?);
+        Ok(broadcast)
+    }
+
+    pub(crate) async fn get_balanced(
+        &self,
+        id: &str,
+        strategy: BalanceStrategy,
+    ) -> Result<BalancedHandle<D>> {
+        let funcs = self.get_all_shared_funcs(id).await?;
+
+        if funcs.is_empty() {
+            return Err(Error::FunctionNotFound(id.to_string()));
+        }
+
+        Ok(BalancedHandle::new(id, strategy, funcs))
+    }
+
+    pub(crate) async fn get_sharded(&self, id: &str) -> Result<ShardedHandle<D>> {
+        let funcs = self.get_all_named_funcs(id).await?;
+
+        if funcs.is_empty() {
+            return Err(Error::FunctionNotFound(id.to_string()));
+        }
+
+        let sharded = ShardedHandle::new(id);
+        sharded.extend(funcs);
+        Ok(sharded)
+    }
+}
+
+impl<D: YapsData> FuncProvider<D> for RegistryView<'_, D> {
+    async fn provided_funcs(&self) -> Result<Vec<FuncMetadata>> {
+        Ok(self.provided())
+    }
+
+    async fn get_func(&self, id: &str) -> Result<Box<dyn DynFuncHandle<D>>> {
+        let (id, plugin) = self.first_provider(id)?;
+        let provider = plugin
+            .provider
+            .as_ref()
+            .expect("only providers are indexed");
+
+        let func = provider.get_func(id).await?;
+        self.bind(id, plugin);
+        Ok(func)
+    }
+
+    async fn get_shared_func(&self, id: &str) -> Result<SharedHandle<D>> {
+        let (id, plugin) = self.first_provider(id)?;
+        let provider = plugin
+            .provider
+            .as_ref()
+            .expect("only providers are indexed");
+
+        let func = provider.get_shared_func(id).await?;
+        self.bind(id, plugin);
+        Ok(func)
+    }
+
+    /// Resolves the function from every plugin providing it, in registration order
+    async fn get_all_funcs(&self, id: &str) -> Result<Vec<Box<dyn DynFuncHandle<D>>>> {
+        let Some((id, providers)) = self.providers_of(id) else {
+            return Ok(Vec::new());
+        };
+        let providers: Vec<_> = providers.collect();
+
+        let funcs = futures::future::try_join_all(providers.iter().map(|plugin| {
+            let provider = plugin
+                .provider
+                .as_ref()
+                .expect("only providers are indexed");
+            provider.get_func(id)
+        }))
+        .await?;
+
+        if let Some(plugin) = providers.first() {
+            self.bind(id, plugin);
+        }
+        Ok(funcs)
+    }
+
+    async fn get_all_shared_funcs(&self, id: &str) -> Result<Vec<SharedHandle<D>>> {
+        let Some((id, providers)) = self.providers_of(id) else {
+            return Ok(Vec::new());
+        };
+        let providers: Vec<_> = providers.collect();
+
+        let funcs = futures::future::try_join_all(providers.iter().map(|plugin| {
+            let provider = plugin
+                .provider
+                .as_ref()
+                .expect("only providers are indexed");
+            provider.get_shared_func(id)
+        }))
+        .await?;
+
+        if let Some(plugin) = providers.first() {
+            self.bind(id, plugin);
+        }
+        Ok(funcs)
+    }
+
+    /// Names the handles after their plugins, numbering the plugins sharing a name
+    async fn get_all_named_funcs(&self, id: &str) -> Result<Vec<(String, SharedHandle<D>)>> {
+        let Some((_, providers)) = self.providers_of(id) else {
+            return Ok(Vec::new());
+        };
+
+        let mut seen: HashMap<&str, usize> = HashMap::new();
+        let names: Vec<_> = providers
+            .map(|plugin| {
+                let nth = seen.entry(plugin.name.as_str()).or_default();
+                *nth += 1;
+                shard_name(&plugin.name, *nth - 1)
+            })
+            .collect();
+
+        let funcs = self.get_all_shared_funcs(id).await?;
+        Ok(names.into_iter().zip(funcs).collect())
+    }
+}
+
+/// Registry published by a hub, replaced as a whole on every registration
+pub(crate) type SharedRegistry<D> = Arc<ArcSwap<Registry<D>>>;
+
+/// Like [`RegistryView`], but always looking at the latest published registry.
+///
+/// Handed to consumers through [`FuncConsumer::attach`], so it only keeps weak references to
+/// avoid cycles between the hub and its plugins.
+struct LiveView<D> {
+    registry: Weak<ArcSwap<Registry<D>>>,
+    consumer: Weak<PluginEntry<D>>,
+}
+
+impl<D: YapsData> LiveView<D> {
+    fn load(&self) -> Result<Arc<Registry<D>>> {
+        let registry = self.registry.upgrade().ok_or(Error::HubDropped)?;
+        Ok(registry.load_full())
+    }
+}
+
+impl<D: YapsData> FuncProvider<D> for LiveView<D> {
+    async fn provided_funcs(&self) -> Result<Vec<FuncMetadata>> {
+        let (registry, consumer) = (self.load()?, self.consumer.upgrade());
+        Ok(registry.view_for(consumer.as_ref(), 0).provided())
+    }
+
+    async fn get_func(&self, id: &str) -> Result<Box<dyn DynFuncHandle<D>>> {
+        let (registry, consumer) = (self.load()?, self.consumer.upgrade());
+        registry.view_for(consumer.as_ref(), 0).get_func(id).await
+    }
+
+    async fn get_all_funcs(&self, id: &str) -> Result<Vec<Box<dyn DynFuncHandle<D>>>> {
+        let (registry, consumer) = (self.load()?, self.consumer.upgrade());
+        registry
+            .view_for(consumer.as_ref(), 0)
+            .get_all_funcs(id)
+            .await
+    }
+
+    async fn get_shared_func(&self, id: &str) -> Result<SharedHandle<D>> {
+        let (registry, consumer) = (self.load()?, self.consumer.upgrade());
+        registry
+            .view_for(consumer.as_ref(), 0)
+            .get_shared_func(id)
+            .await
+    }
+
+    async fn get_all_shared_funcs(&self, id: &str) -> Result<Vec<SharedHandle<D>>> {
+        let (registry, consumer) = (self.load()?, self.consumer.upgrade());
+        registry
+            .view_for(consumer.as_ref(), 0)
+            .get_all_shared_funcs(id)
+            .await
+    }
+
+    async fn get_all_named_funcs(&self, id: &str) -> Result<Vec<(String, SharedHandle<D>)>> {
+        let (registry, consumer) = (self.load()?, self.consumer.upgrade());
+        registry
+            .view_for(consumer.as_ref(), 0)
+            .get_all_named_funcs(id)
+            .await
+    }
+}
+
+impl<D: YapsData> Registry<D> {
+    /// Changes the settings of the registry published in `shared`
+    pub(crate) fn configure(shared: &SharedRegistry<D>, configure: impl FnOnce(&mut Self)) {
+        let mut registry = Registry::clone(&shared.load());
+        configure(&mut registry);
+        shared.store(Arc::new(registry));
+    }
+
+    pub(crate) fn set_connect_concurrency(&mut self, limit: usize) {
+        self.connect_concurrency = limit.max(1);
+    }
+
+    pub(crate) fn set_spawner(&mut self, spawner: Arc<dyn Spawner>) {
+        self.spawner = Some(spawner);
+    }
+
+    pub(crate) fn set_plugin_runtime(&mut self, plugin: String, config: RuntimeConfig) {
+        self.plugin_runtimes.insert(plugin, config);
+    }
+
+    /// Gives `plugins` their dedicated runtime, or the registry's spawner.
+    ///
+    /// Plugins given a spawner of their own keep it, no runtime is started for them.
+    fn attach_spawners(&self, plugins: &[Arc<PluginEntry<D>>]) -> Result<()> {
+        for plugin in plugins.iter().filter(|p| !p.has_spawner()) {
+            if let Some(config) = self.plugin_runtimes.get(&plugin.name) {
+                let runtime: Arc<dyn Spawner> = Arc::new(RuntimeSpawner::new(config.clone())?);
+                plugin.attach_spawner(&runtime);
+            } else if let Some(spawner) = &self.spawner {
+                plugin.attach_spawner(spawner);
+            }
+        }
+
+        Ok(())
+    }
+
+    pub(crate) fn events(&self) -> &Arc<EventBus<D>> {
+        &self.events
+    }
+
+    pub(crate) fn callbacks(&self) -> &Arc<CallbackTable> {
+        &self.callbacks
+    }
+
+    /// Every registered plugin, as seen from outside the registry
+    pub(crate) fn view(&self) -> RegistryView<'_, D> {
+        self.view_for(None, 0)
+    }
+
+    /// The plugins from position `from` on, as seen by `consumer`
+    fn view_for<'a>(
+        &'a self,
+        consumer: Option<&'a Arc<PluginEntry<D>>>,
+        from: usize,
+    ) -> RegistryView<'a, D> {
+        RegistryView {
+            registry: self,
+            consumer,
+            from,
+        }
+    }
+
+    pub(crate) fn consumed_funcs(&self) -> Vec<FuncMetadata> {
+        self.plugins
+            .iter()
+            .filter(|p| p.consumer.is_some())
+            .flat_map(|p| &p.consumed)
+            .cloned()
+            .collect()
+    }
+
+    fn push_plugin(&mut self, entry: Arc<PluginEntry<D>>) {
+        if entry.provider.is_some() {
+            for func in &entry.provided {
+                // The index keeps the first key, every lookup shares that id
+                let providers = self.index.entry(Arc::from(func.id.as_str())).or_default();
+                // Providers may list an id twice, they're still only asked once
+                if providers.last() != Some(&self.plugins.len()) {
+                    providers.push(self.plugins.len());
+                }
+            }
+        }
+
+        self.plugins.push(entry);
+    }
+
+    async fn run_bounded(&self, tasks: Vec<BoxFuture<'_, Result<()>>>) -> Result<()> {
+        let results: Vec<_> = stream::iter(tasks)
+            .buffer_unordered(self.connect_concurrency)
+            .collect()
+            .await;
+
+        Error::aggregate(results.into_iter().filter_map(Result::err))
+    }
+
+    /// Connects every registered consumer to `provider`
+    pub(crate) async fn connect(&self, provider: &dyn DynFuncProvider<D>) -> Result<()> {
+        let connections: Vec<_> = self
+            .plugins
+            .iter()
+            .filter_map(|p| {
+                let consumer = p.consumer.as_ref()?;
+                Some(
+                    async move {
+                        consumer
+                            .connect(provider)
+                            .await
+                            .map_err(|e| p.wrap_error(e))
+                    }
+                    .boxed(),
+                )
+            })
+            .collect();
+
+        self.run_bounded(connections).await
+    }
+
+    /// Connects the consumers registered before position `first_new` to the plugins after it
+    async fn connect_registered(&self, first_new: usize) -> Result<()> {
+        if first_new == self.plugins.len() {
+            return Ok(());
+        }
+
+        let connections: Vec<_> = self.plugins[..first_new]
+            .iter()
+            .filter_map(|p| {
+                let consumer = p.consumer.as_ref()?;
+                Some(
+                    async move {
+                        consumer
+                            .connect(&self.view_for(Some(p), first_new))
+                            .await
+                            .map_err(|e| p.wrap_error(e))
+                    }
+                    .boxed(),
+                )
+            })
+            .collect();
+
+        self.run_bounded(connections).await
+    }
+
+    pub(crate) async fn with_provider(
+        &self,
+        provider: impl FuncProvider<D> + 'static,
+    ) -> Result<Self> {
+        let provided = provider.provided_funcs().await?;
+        let provider: Arc<dyn DynFuncProvider<D>> = Arc::new(provider);
+
+        let mut registry = self.clone();
+        let first_new = registry.plugins.len();
+        registry.push_plugin(Arc::new(PluginEntry::new(
+            provider.name(),
+            Some(provider),
+            None,
+            provided,
+            Vec::new(),
+        )));
+
+        registry
+            .start(&registry.plugins[first_new..], async { Ok(()) })
+            .await?;
+
+        Ok(registry)
+    }
+
+    /// Runs the init hooks of `plugins`, then `connect`, then their ready hooks.
+    ///
+    /// The plugins are given their spawner first, see [`Registry::attach_spawners`], then the
+    /// hub's event bus and callback table. Consumers registered on their own start the same way.
+    /// Hooks run one plugin at a time in dependency order. If anything fails, the plugins that
+    /// were initialized are shut down again in reverse order and every error is returned.
+    async fn start(
+        &self,
+        plugins: &[Arc<PluginEntry<D>>],
+        connect: impl Future<Output = Result<()>>,
+    ) -> Result<()> {
+        let (ordered, _) = dependency_order(plugins);
+        let mut initialized = 0;
+
+        self.attach_spawners(plugins)?;
+        for plugin in plugins {
+            plugin.attach_hub(&self.events, &self.callbacks);
+        }
+
+        let started = async {
+            for plugin in &ordered {
+                plugin.run_hook(Hook::Init).await?;
+                initialized += 1;
+            }
+
+            connect.await?;
+
+            for plugin in &ordered {
+                plugin.run_hook(Hook::Ready).await?;
+            }
+
+            Ok(())
+        }
+        .await;
+
+        let Err(error) = started else {
+            return Ok(());
+        };
+
+        let mut errors = vec![error];
+        for plugin in ordered[..initialized].iter().rev() {
+            if let Err(e) = plugin.run_hook(Hook::Shutdown).await {
+                errors.push(e);
+            }
+        }
+
+        Error::aggregate(errors)
+    }
+
+    /// Makes `registry` the latest one, then connects the consumers registered before it to the
+    /// plugins it added.
+    ///
+    /// Registrations have to be serialized. The new plugins have started at this point, so they
+    /// stay registered if a consumer fails to connect to them and only its errors are returned.
+    pub(crate) async fn publish(shared: &SharedRegistry<D>, registry: Self) -> Result<()> {
+        let first_new = shared.load().plugins.len();
+        let registry = Arc::new(registry);
+
+        shared.store(registry.clone());
+        registry.connect_registered(first_new).await
+    }
+
+    fn live_view(shared: &SharedRegistry<D>, consumer: &Arc<PluginEntry<D>>) -> Arc<LiveView<D>> {
+        Arc::new(LiveView {
+            registry: Arc::downgrade(shared),
+            consumer: Arc::downgrade(consumer),
+        })
+    }
+
+    pub(crate) async fn with_consumer(
+        &self,
+        consumer: impl FuncConsumer<D> + 'static,
+        shared: &SharedRegistry<D>,
+    ) -> Result<Self> {
+        let consumed = consumer.consumed_funcs().await?;
+        let consumer: Arc<dyn DynFuncConsumer<D>> = Arc::new(consumer);
+        let entry = Arc::new(PluginEntry::new(
+            consumer.name(),
+            None,
+            Some(consumer.clone()),
+            Vec::new(),
+            consumed,
+        ));
+
+        let mut registry = self.clone();
+        let first_new = registry.plugins.len();
+        registry.push_plugin(entry.clone());
+
+        let connect = async {
+            consumer.attach(Self::live_view(shared, &entry));
+            consumer.connect(&registry.view_for(Some(&entry), 0)).await
+        };
+
+        self.start(&registry.plugins[first_new..], connect).await?;
+
+        Ok(registry)
+    }
+
+    /// Connects `plugins` with each other and with the registered plugins.
+    ///
+    /// The plugins can consume functions provided by each other regardless of their order.
+    /// If any of them fails to connect, the errors of all failing plugins are returned together.
+    /// Their lifecycle hooks run around the connection, see [`Registry::start`].
+    ///
+    /// The consumers registered already aren't touched, they're connected to the new plugins by
+    /// [`Registry::publish`] once those have started.
+    pub(crate) async fn with_plugins(
+        &self,
+        plugins: Vec<Arc<dyn Plugin<D>>>,
+        shared: &SharedRegistry<D>,
+    ) -> Result<Self> {
+        let metadata: Vec<_> = plugins
+            .iter()
+            .map(|cp| {
+                async move {
+                    let provider: Arc<dyn DynFuncProvider<D>> = cp.clone();
+                    let consumer: Arc<dyn DynFuncConsumer<D>> = cp.clone();
+                    let name = provider.name();
+                    let (provided, consumed) =
+                        futures::join!(provider.provided_funcs(), consumer.consumed_funcs());
+                    let wrap_error = |error| Error::Plugin {
+                        plugin: name.clone(),
+                        error: Box::new(error),
+                    };
+
+                    let provided = provided.map_err(wrap_error)?;
+                    let consumed = consumed.map_err(wrap_error)?;
+
+                    Ok::<_, Error>(PluginEntry::new(
+                        name,
+                        Some(provider),
+                        Some(consumer),
+                        provided,
+                        consumed,
+                    ))
+                }
+                .boxed()
+            })
+            .collect();
+
+        let mut new_plugins = Vec::new();
+        let mut errors = Vec::new();
+        for result in stream::iter(metadata)
+            .buffered(self.connect_concurrency)
+            .collect::<Vec<_>>()
+            .await
+        {
+            match result {
+                Ok(p) => new_plugins.push(Arc::new(p)),
+                Err(e) => errors.push(e),
+            }
+        }
+        Error::aggregate(errors)?;
+
+        let mut registry = self.clone();
+        let first_new = registry.plugins.len();
+        for entry in new_plugins {
+            registry.push_plugin(entry);
+        }
+
+        let connect = async {
+            // The new consumers can now resolve functions from every registered plugin
+            let new: Vec<_> = (first_new..registry.plugins.len())
+                .map(|index| {
+                    let registry = &registry;
+                    async move {
+                        let plugin = &registry.plugins[index];
+                        let consumer = plugin
+                            .consumer
+                            .as_ref()
+                            .expect("plugins registered together are always consumers");
+
+                        consumer.attach(Self::live_view(shared, plugin));
+                        consumer
+                            .connect(&registry.view_for(Some(plugin), 0))
+                            .await
+                            .map_err(|e| plugin.wrap_error(e))
+                    }
+                    .boxed()
+                })
+                .collect();
+            registry.run_bounded(new).await
+        };
+
+        self.start(&registry.plugins[first_new..], connect).await?;
+
+        Ok(registry)
+    }
+
+    /// Shuts the plugins down one at a time, consumers before the providers they depend on.
+    ///
+    /// Each plugin stops accepting calls, drains the ones in flight, runs its shutdown hook and
+    /// has its tasks joined: actors, subscriptions, stream producers and callbacks. The plugins
+    /// it depends on keep serving the calls it makes meanwhile. Plugins depending on each other
+    /// are shut down in reverse registration order and reported as a cycle.
+    ///
+    /// Whatever is still running when `timeout` elapses is reported, and its tasks are aborted.
+    pub(crate) async fn shutdown(&self, timeout: Duration) -> ShutdownReport {
+        let deadline = Instant::now() + timeout;
+        let mut report = ShutdownReport::default();
+
+        let (mut ordered, cycles) = dependency_order(&self.plugins);
+        ordered.reverse();
+        report.cycles = cycles;
+
+        for plugin in ordered {
+            let tracker = plugin.task_tracker();
+
+            if let Some(tracker) = &tracker {
+                tracker.close();
+
+                if timeout_at(deadline, tracker.drain()).await.is_err() {
+                    report.unfinished.push(Unfinished::Calls {
+                        plugin: plugin.name.clone(),
+                        in_flight: tracker.in_flight(),
+                    });
+                }
+            }
+
+            match timeout_at(deadline, plugin.run_hook(Hook::Shutdown)).await {
+                Ok(result) => report.errors.extend(result.err()),
+                Err(_) => report.unfinished.push(Unfinished::Hook {
+                    plugin: plugin.name.clone(),
+                }),
+            }
+
+            for (name, mut task) in tracker.iter().flat_map(|t| t.take_tasks()) {
+                match timeout_at(deadline, &mut task).await {
+                    Ok(Some(result)) => report
+                        .errors
+                        .extend(result.err().map(|e| plugin.wrap_error(e))),
+                    Ok(None) => report
+                        .errors
+                        .push(plugin.wrap_error(Error::TaskFailed(name))),
+                    Err(_) => {
+                        task.abort();
+                        report.unfinished.push(Unfinished::Task {
+                            plugin: plugin.name.clone(),
+                            task: name,
+                        });
+                    }
+                }
+            }
+        }
+
+        report
+    }
+
+    /// Describes the registered plugins and how their consumed functions were resolved.
+    ///
+    /// A consumed function is bound to the plugin it was actually resolved from when connecting,
+    /// or when a lazy extern was first called.
+    pub(crate) fn introspect(&self) -> HubInfo {
+        let plugins = self
+            .plugins
+            .iter()
+            .enumerate()
+            .map(|(index, p)| PluginInfo {
+                index,
+                name: p.name.clone(),
+                provides: p.provided.clone(),
+                consumes: p.consumed.clone(),
+            })
+            .collect();
+
+        let bindings = self
+            .plugins
+            .iter()
+            .enumerate()
+            .flat_map(|(consumer, p)| p.consumed.iter().map(move |f| (consumer, p, f)))
+            .map(|(consumer, p, func)| Binding {
+                consumer,
+                func: func.id.to_string(),
+                provider: p.bound_to(&func.id).and_then(|provider| {
+                    self.plugins.iter().position(|p| Arc::ptr_eq(p, &provider))
+                }),
+            })
+            .collect();
+
+        HubInfo { plugins, bindings }
+    }
+}
@@ -0,0 +1,300 @@
+use crate::{
+    Error, FuncHandle, Result, YapsData,
+    codec::{Codec, DecodeFor, EncodeFor},
+    shared_handle::SharedHandle,
+    stream_handle::DataStream,
+};
+
+use arc_swap::ArcSwap;
+use futures::{StreamExt, stream};
+use std::{
+    hash::{Hash, Hasher},
+    sync::Arc,
+};
+
+/// Points each shard gets on the hash ring, more points spread keys more evenly
+const VIRTUAL_NODES: u64 = 64;
+
+/// Extracts the key from the arguments, handing them back to be passed on
+type KeyFn<D> = Box<dyn Fn(D) -> Result<(ShardKey, D)> + Send + Sync>;
+
+/// Hashed key deciding which shard a call goes to
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct ShardKey(u64);
+
+impl ShardKey {
+    /// Hashes `key` with FNV-1a, giving the same key across runs and platforms
+    pub fn new(key: &(impl Hash + ?Sized)) -> Self {
+        let mut hasher = FnvHasher::default();
+        key.hash(&mut hasher);
+        Self(hasher.finish())
+    }
+}
+
+/// 64 bit FNV-1a, integers are hashed as little endian so keys don't depend on the platform.
+///
+/// FNV alone barely mixes the high bits of short keys, which the ring sorts by, so the result
+/// goes through the murmur3 finalizer.
+struct FnvHasher(u64);
+
+impl Default for FnvHasher {
+    fn default() -> Self {
+        Self(0xcbf2_9ce4_8422_2325)
+    }
+}
+
+impl Hasher for FnvHasher {
+    fn finish(&self) -> u64 {
+        let mut hash = self.0;
+        hash ^= hash >> 33;
+        hash = hash.wrapping_mul(0xff51_afd7_ed55_8ccd);
+        hash ^= hash >> 33;
+        hash = hash.wrapping_mul(0xc4ce_b9fe_1a85_ec53);
+        hash ^ (hash >> 33)
+    }
+
+    fn write(&mut self, bytes: &[u8]) {
+        for byte in bytes {
+            self.0 ^= u64::from(*byte);
+            self.0 = self.0.wrapping_mul(0x0000_0100_0000_01b3);
+        }
+    }
+
+    fn write_u16(&mut self, i: u16) {
+        self.write(&i.to_le_bytes())
+    }
+
+    fn write_u32(&mut self, i: u32) {
+        self.write(&i.to_le_bytes())
+    }
+
+    fn write_u64(&mut self, i: u64) {
+        self.write(&i.to_le_bytes())
+    }
+
+    fn write_u128(&mut self, i: u128) {
+        self.write(&i.to_le_bytes())
+    }
+
+    fn write_usize(&mut self, i: usize) {
+        self.write_u64(i as u64)
+    }
+
+    fn write_i16(&mut self, i: i16) {
+        self.write_u16(i as u16)
+    }
+
+    fn write_i32(&mut self, i: i32) {
+        self.write_u32(i as u32)
+    }
+
+    fn write_i64(&mut self, i: i64) {
+        self.write_u64(i as u64)
+    }
+
+    fn write_i128(&mut self, i: i128) {
+        self.write_u128(i as u128)
+    }
+
+    fn write_isize(&mut self, i: isize) {
+        self.write_u64(i as u64)
+    }
+}
+
+struct Ring<D> {
+    shards: Vec<(String, SharedHandle<D>)>,
+    // Sorted by hash, each point belongs to the shard at the given index
+    points: Vec<(u64, usize)>,
+}
+
+impl<D> Ring<D> {
+    fn new(shards: Vec<(String, SharedHandle<D>)>) -> Self {
+        // Points only depend on the shard names, not on the order the shards were added in
+        let mut points: Vec<_> = shards
+            .iter()
+            .enumerate()
+            .flat_map(|(i, (name, _))| {
+                (0..VIRTUAL_NODES).map(move |node| (ShardKey::new(&(name.as_str(), node)).0, i))
+            })
+            .collect();
+        points.sort_unstable_by(|(a, i), (b, j)| a.cmp(b).then(shards[*i].0.cmp(&shards[*j].0)));
+
+        Self { shards, points }
+    }
+
+    fn shard_for(&self, key: ShardKey) -> Option<&(String, SharedHandle<D>)> {
+        let i = self.points.partition_point(|(point, _)| *point < key.0);
+        let (_, shard) = self.points.get(i).or(self.points.first())?;
+        Some(&self.shards[*shard])
+    }
+}
+
+/// Handle routing each call to the provider owning its key, e.g. for partitioned caches.
+///
+/// Keys are mapped to providers by consistent hashing, so adding a provider only moves the keys
+/// it takes over. Providers are identified by their shard name, hubs use the name of the plugin,
+/// see [`FuncProvider::get_all_named_funcs`](crate::FuncProvider::get_all_named_funcs).
+pub struct ShardedHandle<D: YapsData> {
+    id: String,
+    ring: ArcSwap<Ring<D>>,
+    key: Option<KeyFn<D>>,
+}
+
+impl<D: YapsData> std::fmt::Debug for ShardedHandle<D> {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("ShardedHandle")
+            .field("id", &self.id)
+            .field("shards", &self.len())
+            .field("has_key", &self.key.is_some())
+            .finish()
+    }
+}
+
+impl<D: YapsData> ShardedHandle<D> {
+    pub fn new(id: impl Into<String>) -> Self {
+        Self {
+            id: id.into(),
+            ring: ArcSwap::from_pointee(Ring::new(Vec::new())),
+            key: None,
+        }
+    }
+
+    /// Extracts the key from the decoded arguments, letting the handle be called like any other.
+    ///
+    /// Without a key, calls through [`FuncHandle`] fail with [`Error::ShardKeyMissing`]. The arguments
+    /// are decoded to get the key, then encoded again for the shard.
+    pub fn with_key<C, A, K>(
+        mut self,
+        codec: Arc<C>,
+        key: impl Fn(&A) -> K + Send + Sync + 'static,
+    ) -> Self
+    where
+        C: Codec<Data = D> + DecodeFor<C, A> + EncodeFor<C, A> + 'static,
+        K: Hash,
+    {
+        self.key = Some(Box::new(move |args: D| {
+            let args: A = codec.decode(args)?;
+            let key = ShardKey::new(&key(&args));
+            Ok((key, codec.encode(args)?))
+        }));
+        self
+    }
+
+    pub fn id(&self) -> &str {
+        &self.id
+    }
+
+    pub fn len(&self) -> usize {
+        self.ring.load().shards.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.ring.load().shards.is_empty()
+    }
+
+    /// Adds named shards, replacing the existing shards with the same names
+    pub fn extend<N, H>(&self, handles: impl IntoIterator<Item = (N, H)>)
+    where
+        N: Into<String>,
+        H: Into<SharedHandle<D>>,
+    {
+        let handles: Vec<(String, SharedHandle<D>)> = handles
+            .into_iter()
+            .map(|(name, handle)| (name.into(), handle.into()))
+            .collect();
+
+        if handles.is_empty() {
+            return;
+        }
+
+        self.ring.rcu(|ring| {
+            let mut shards = ring.shards.clone();
+            for (name, handle) in &handles {
+                match shards.iter_mut().find(|(shard, _)| shard == name) {
+                    Some(shard) => shard.1 = handle.clone(),
+                    None => shards.push((name.clone(), handle.clone())),
+                }
+            }
+            Ring::new(shards)
+        });
+    }
+
+    /// Name of the shard owning `key`
+    pub fn shard_for(&self, key: ShardKey) -> Option<String> {
+        self.ring
+            .load()
+            .shard_for(key)
+            .map(|(name, _)| name.clone())
+    }
+
+    fn shard(&self, key: ShardKey) -> Result<SharedHandle<D>> {
+        let ring = self.ring.load();
+        let (_, shard) = ring
+            .shard_for(key)
+            .ok_or_else(|| Error::FunctionNotFound(self.id.clone()))?;
+
+        Ok(shard.clone())
+    }
+
+    /// Extracts the key with the function given to [`ShardedHandle::with_key`]
+    fn key_of(&self, args: D) -> Result<(ShardKey, D)> {
+        let key = self
+            .key
+            .as_ref()
+            .ok_or_else(|| Error::ShardKeyMissing(self.id.clone()))?;
+
+        key(args)
+    }
+
+    pub async fn call_with_key(&self, key: ShardKey, args: D) -> Result<D> {
+        self.shard(key)?.call(args).await
+    }
+
+    pub async fn call_stream_with_key(&self, key: ShardKey, args: D) -> Result<DataStream<D>> {
+        self.shard(key)?.call_stream(args).await
+    }
+
+    pub async fn open_channel_with_key(
+        &self,
+        key: ShardKey,
+        inputs: DataStream<D>,
+    ) -> Result<DataStream<D>> {
+        self.shard(key)?.open_channel(inputs).await
+    }
+
+    pub async fn call_with_key_codec<C, A, R>(&self, codec: &C, key: ShardKey, args: A) -> Result<R>
+    where
+        C: Codec<Data = D> + EncodeFor<C, A> + DecodeFor<C, R>,
+    {
+        let data_in = codec.encode(args)?;
+        let data_out = self.call_with_key(key, data_in).await?;
+        codec.decode(data_out)
+    }
+}
+
+impl<D: YapsData> FuncHandle<D> for ShardedHandle<D> {
+    async fn call(&self, args: D) -> Result<D> {
+        let (key, args) = self.key_of(args)?;
+        self.call_with_key(key, args).await
+    }
+
+    async fn call_stream(&self, args: D) -> Result<DataStream<D>> {
+        let (key, args) = self.key_of(args)?;
+        self.call_stream_with_key(key, args).await
+    }
+
+    /// Opens the channel with the shard owning the key of the first input.
+    ///
+    /// Waits for the first input to be sent, use [`ShardedHandle::open_channel_with_key`] to
+    /// open it beforehand.
+    async fn open_channel(&self, mut inputs: DataStream<D>) -> Result<DataStream<D>> {
+        let first = inputs
+            .next()
+            .await
+            .ok_or_else(|| Error::ShardKeyMissing(self.id.clone()))??;
+
+        let (key, first) = self.key_of(first)?;
+        let inputs = stream::once(async { Ok(first) }).chain(inputs).boxed();
+        self.open_channel_with_key(key, inputs).await
+    }
+}
@@ -0,0 +1,79 @@
+use crate::{DynFuncHandle, FuncHandle, Result, YapsData, stream_handle::DataStream};
+
+use std::{future::Future, ops::Deref, sync::Arc};
+
+/// Function handle that's cheap to clone, every clone calls the same underlying handle.
+///
+/// Resolved with [`FuncProvider::get_shared_func`](crate::FuncProvider::get_shared_func), or
+/// converted from a boxed handle.
+pub struct SharedHandle<D> {
+    handle: Arc<dyn DynFuncHandle<D>>,
+}
+
+impl<D> Clone for SharedHandle<D> {
+    fn clone(&self) -> Self {
+        Self {
+            handle: self.handle.clone(),
+        }
+    }
+}
+
+impl<D> std::fmt::Debug for SharedHandle<D> {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("SharedHandle")
+            .field("clones", &Arc::strong_count(&self.handle))
+            .finish()
+    }
+}
+
+impl<D: YapsData> SharedHandle<D> {
+    pub fn new(handle: impl FuncHandle<D> + 'static) -> Self {
+        Self {
+            handle: Arc::new(handle),
+        }
+    }
+
+    /// Boxes the handle for APIs taking `Box<dyn DynFuncHandle<D>>`
+    pub fn into_boxed(self) -> Box<dyn DynFuncHandle<D>> {
+        Box::new(self)
+    }
+}
+
+impl<D> From<Box<dyn DynFuncHandle<D>>> for SharedHandle<D> {
+    fn from(handle: Box<dyn DynFuncHandle<D>>) -> Self {
+        Self {
+            handle: Arc::from(handle),
+        }
+    }
+}
+
+impl<D> From<Arc<dyn DynFuncHandle<D>>> for SharedHandle<D> {
+    fn from(handle: Arc<dyn DynFuncHandle<D>>) -> Self {
+        Self { handle }
+    }
+}
+
+impl<D> Deref for SharedHandle<D> {
+    type Target = dyn DynFuncHandle<D>;
+
+    fn deref(&self) -> &Self::Target {
+        self.handle.as_ref()
+    }
+}
+
+impl<D: YapsData> FuncHandle<D> for SharedHandle<D> {
+    fn call(&self, args: D) -> impl Future<Output = Result<D>> + Send {
+        self.handle.call(args)
+    }
+
+    fn call_stream(&self, args: D) -> impl Future<Output = Result<DataStream<D>>> + Send {
+        self.handle.call_stream(args)
+    }
+
+    fn open_channel(
+        &self,
+        inputs: DataStream<D>,
+    ) -> impl Future<Output = Result<DataStream<D>>> + Send {
+        self.handle.open_channel(inputs)
+    }
+}
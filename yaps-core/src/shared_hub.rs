@@ -0,0 +1,241 @@
+use crate::balanced_handle::{BalanceStrategy, BalancedHandle};
+use crate::blocking_handle::{BlockingHandle, HubRuntime};
+use crate::broadcast_handle::BroadcastHandle;
+use crate::callback::CallbackTable;
+use crate::event_bus::EventBus;
+use crate::introspection::HubInfo;
+use crate::registry::{Registry, SharedRegistry};
+use crate::sharded_handle::ShardedHandle;
+use crate::shared_handle::SharedHandle;
+use crate::shutdown::ShutdownReport;
+use crate::spawner::{RuntimeConfig, Spawner};
+use crate::{
+    DynFuncHandle, DynFuncProvider, FuncConsumer, FuncMetadata, FuncProvider, Plugin, Result,
+    YapsData,
+};
+
+use std::{fmt, sync::Arc, time::Duration};
+
+use arc_swap::ArcSwap;
+use tokio::sync::Mutex;
+
+/// Thread-safe hub that can be extended while it's being used, e.g. behind an `Arc`.
+///
+/// Registrations are serialized with each other, but lookups always read the latest published
+/// registry without locking, so they never wait for a registration in progress.
+pub struct SharedHub<D: YapsData> {
+    registry: SharedRegistry<D>,
+    registration: Mutex<()>,
+    runtime: HubRuntime,
+}
+
+impl<D: YapsData> Default for SharedHub<D> {
+    fn default() -> Self {
+        Self::from_parts(
+            Arc::new(ArcSwap::from_pointee(Registry::default())),
+            HubRuntime::default(),
+        )
+    }
+}
+
+impl<D: YapsData> fmt::Debug for SharedHub<D> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("SharedHub")
+            .field("registry", &self.registry.load())
+            .field("runtime", &self.runtime)
+            .finish()
+    }
+}
+
+impl<D: YapsData> FuncProvider<D> for SharedHub<D> {
+    async fn provided_funcs(&self) -> Result<Vec<FuncMetadata>> {
+        Ok(self.registry.load().view().provided())
+    }
+
+    async fn get_func(&self, id: &str) -> Result<Box<dyn DynFuncHandle<D>>> {
+        self.registry.load_full().view().get_func(id).await
+    }
+
+    async fn get_all_funcs(&self, id: &str) -> Result<Vec<Box<dyn DynFuncHandle<D>>>> {
+        self.registry.load_full().view().get_all_funcs(id).await
+    }
+
+    async fn get_shared_func(&self, id: &str) -> Result<SharedHandle<D>> {
+        self.registry.load_full().view().get_shared_func(id).await
+    }
+
+    async fn get_all_shared_funcs(&self, id: &str) -> Result<Vec<SharedHandle<D>>> {
+        self.registry
+            .load_full()
+            .view()
+            .get_all_shared_funcs(id)
+            .await
+    }
+
+    async fn get_all_named_funcs(&self, id: &str) -> Result<Vec<(String, SharedHandle<D>)>> {
+        self.registry
+            .load_full()
+            .view()
+            .get_all_named_funcs(id)
+            .await
+    }
+
+    // Plugins of either hub can receive the callbacks created in the other one
+    fn attach_callbacks(&self, callbacks: &Arc<CallbackTable>) {
+        self.registry.load().callbacks().link(callbacks)
+    }
+}
+
+impl<D: YapsData> FuncConsumer<D> for SharedHub<D> {
+    async fn consumed_funcs(&self) -> Result<Vec<FuncMetadata>> {
+        Ok(self.registry.load().consumed_funcs())
+    }
+
+    fn attach_callbacks(&self, callbacks: &Arc<CallbackTable>) {
+        self.registry.load().callbacks().link(callbacks)
+    }
+
+    async fn connect(&self, provider: &dyn DynFuncProvider<D>) -> Result<()> {
+        self.registry.load_full().connect(provider).await
+    }
+}
+
+impl<D: YapsData> SharedHub<D> {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub(crate) fn from_parts(registry: SharedRegistry<D>, runtime: HubRuntime) -> Self {
+        Self {
+            registry,
+            registration: Mutex::new(()),
+            runtime,
+        }
+    }
+
+    /// Sets how many plugins are connected at the same time (at least one)
+    pub fn with_connect_concurrency(self, limit: usize) -> Self {
+        Registry::configure(&self.registry, |registry| {
+            registry.set_connect_concurrency(limit)
+        });
+        self
+    }
+
+    /// Sets how many events each subscription buffers, see
+    /// [`LocalHub::with_event_capacity`](crate::local_hub::LocalHub::with_event_capacity)
+    pub fn with_event_capacity(self, capacity: usize) -> Self {
+        self.registry.load().events().set_capacity(capacity);
+        self
+    }
+
+    /// Sets where the tasks of plugins registered afterwards are spawned, see
+    /// [`LocalHub::with_spawner`](crate::local_hub::LocalHub::with_spawner)
+    pub fn with_spawner(self, spawner: Arc<dyn Spawner>) -> Self {
+        Registry::configure(&self.registry, |registry| registry.set_spawner(spawner));
+        self
+    }
+
+    /// Isolates the plugin named `plugin` on a runtime of its own, see
+    /// [`LocalHub::with_plugin_runtime`](crate::local_hub::LocalHub::with_plugin_runtime)
+    pub fn with_plugin_runtime(self, plugin: impl Into<String>, config: RuntimeConfig) -> Self {
+        Registry::configure(&self.registry, |registry| {
+            registry.set_plugin_runtime(plugin.into(), config)
+        });
+        self
+    }
+
+    /// Event bus shared by the registered plugins
+    pub fn events(&self) -> Arc<EventBus<D>> {
+        self.registry.load().events().clone()
+    }
+
+    pub async fn add_provider(&self, provider: impl FuncProvider<D> + 'static) -> Result<()> {
+        let _registration = self.registration.lock().await;
+
+        let registry = self.registry.load_full().with_provider(provider).await?;
+        Registry::publish(&self.registry, registry).await
+    }
+
+    pub async fn add_consumer(&self, consumer: impl FuncConsumer<D> + 'static) -> Result<()> {
+        let _registration = self.registration.lock().await;
+
+        let registry = self
+            .registry
+            .load_full()
+            .with_consumer(consumer, &self.registry)
+            .await?;
+        Registry::publish(&self.registry, registry).await
+    }
+
+    pub async fn add_plugin(&self, cp: impl Plugin<D> + 'static) -> Result<()> {
+        self.add_plugins([Arc::new(cp) as Arc<dyn Plugin<D>>]).await
+    }
+
+    /// Registers several plugins at once, see [`LocalHub::add_plugins`](crate::local_hub::LocalHub::add_plugins)
+    pub async fn add_plugins(
+        &self,
+        plugins: impl IntoIterator<Item = Arc<dyn Plugin<D>>>,
+    ) -> Result<()> {
+        let plugins = plugins.into_iter().collect();
+        let _registration = self.registration.lock().await;
+
+        let registry = self
+            .registry
+            .load_full()
+            .with_plugins(plugins, &self.registry)
+            .await?;
+        Registry::publish(&self.registry, registry).await
+    }
+
+    /// Returns a handle calling every plugin currently providing `id`, see
+    /// [`LocalHub::get_broadcast`](crate::local_hub::LocalHub::get_broadcast)
+    pub async fn get_broadcast(&self, id: &str) -> Result<BroadcastHandle<D>> {
+        self.registry.load_full().view().get_broadcast(id).await
+    }
+
+    /// Returns a handle spreading calls over every plugin currently providing `id`, see
+    /// [`LocalHub::get_balanced`](crate::local_hub::LocalHub::get_balanced)
+    pub async fn get_balanced(
+        &self,
+        id: &str,
+        strategy: BalanceStrategy,
+    ) -> Result<BalancedHandle<D>> {
+        self.registry
+            .load_full()
+            .view()
+            .get_balanced(id, strategy)
+            .await
+    }
+
+    /// Returns a handle routing calls by key over every plugin currently providing `id`, see
+    /// [`LocalHub::get_sharded`](crate::local_hub::LocalHub::get_sharded)
+    pub async fn get_sharded(&self, id: &str) -> Result<ShardedHandle<D>> {
+        self.registry.load_full().view().get_sharded(id).await
+    }
+
+    /// Runtime owned by the hub to drive blocking calls, see
+    /// [`LocalHub::runtime`](crate::local_hub::LocalHub::runtime)
+    pub fn runtime(&self) -> Result<tokio::runtime::Handle> {
+        Ok(self.runtime.get()?.handle().clone())
+    }
+
+    /// Returns a handle to `id` callable from synchronous code, see
+    /// [`LocalHub::get_func_blocking`](crate::local_hub::LocalHub::get_func_blocking)
+    pub fn get_func_blocking(&self, id: &str) -> Result<BlockingHandle<D>> {
+        self.runtime.get_func(self.get_shared_func(id))
+    }
+
+    /// Shuts every plugin down, waiting at most `timeout` for them, see
+    /// [`LocalHub::shutdown`](crate::local_hub::LocalHub::shutdown).
+    ///
+    /// Waits for a registration in progress first. Plugins registered afterwards aren't shut down.
+    pub async fn shutdown(&self, timeout: Duration) -> ShutdownReport {
+        let _registration = self.registration.lock().await;
+        self.registry.load_full().shutdown(timeout).await
+    }
+
+    /// Describes the registered plugins, see [`LocalHub::introspect`](crate::local_hub::LocalHub::introspect)
+    pub fn introspect(&self) -> HubInfo {
+        self.registry.load().introspect()
+    }
+}
@@ -0,0 +1,198 @@
+use crate::{Error, FuncHandle, Result, YapsData, spawner::Task, stream_handle::DataStream};
+
+use futures::StreamExt;
+use std::{
+    future::Future,
+    sync::{Arc, Mutex},
+};
+use tokio::sync::watch;
+
+/// Keeps track of a plugin's calls and tasks so a hub can shut it down gracefully.
+///
+/// Once closed, the handles created through [`TrackedHandle`] refuse new calls while the ones
+/// in flight keep running, and actors spawned with [`TaskTracker::closed`] as their stop signal
+/// finish their queued calls before exiting.
+#[derive(Debug)]
+pub struct TaskTracker {
+    closed: watch::Sender<bool>,
+    in_flight: watch::Sender<usize>,
+    tasks: Mutex<Vec<(String, Task<Result<()>>)>>,
+}
+
+impl Default for TaskTracker {
+    fn default() -> Self {
+        Self {
+            closed: watch::Sender::new(false),
+            in_flight: watch::Sender::new(0),
+            tasks: Mutex::new(Vec::new()),
+        }
+    }
+}
+
+impl TaskTracker {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn is_closed(&self) -> bool {
+        *self.closed.borrow()
+    }
+
+    /// Stops accepting new calls
+    pub fn close(&self) {
+        self.closed.send_replace(true);
+    }
+
+    /// Completes once the tracker is closed, never if it's dropped without being closed
+    pub fn closed(&self) -> impl Future<Output = ()> + Send + 'static {
+        let mut closed = self.closed.subscribe();
+
+        async move {
+            if closed.wait_for(|closed| *closed).await.is_err() {
+                futures::future::pending::<()>().await;
+            }
+        }
+    }
+
+    /// Number of calls currently running through the tracked handles
+    pub fn in_flight(&self) -> usize {
+        *self.in_flight.borrow()
+    }
+
+    /// Waits until no call is running anymore
+    pub async fn drain(&self) {
+        let mut in_flight = self.in_flight.subscribe();
+        let _ = in_flight.wait_for(|count| *count == 0).await;
+    }
+
+    /// Keeps a task so it can be joined when shutting down
+    pub fn track(&self, name: impl Into<String>, task: Task<Result<()>>) {
+        let mut tasks = self.tasks.lock().expect("task list poisoned");
+
+        // Tasks are added every time a function is resolved, the finished ones aren't needed
+        tasks.retain(|(_, task)| !task.is_finished());
+        tasks.push((name.into(), task));
+    }
+
+    /// Hands over the tracked tasks to join them
+    pub fn take_tasks(&self) -> Vec<(String, Task<Result<()>>)> {
+        std::mem::take(&mut *self.tasks.lock().expect("task list poisoned"))
+    }
+
+    fn start_call(self: &Arc<Self>) -> Option<CallGuard> {
+        // Counted before checking, so a drain started after the check waits for this call
+        self.in_flight.send_modify(|count| *count += 1);
+        let guard = CallGuard {
+            tracker: self.clone(),
+        };
+
+        (!self.is_closed()).then_some(guard)
+    }
+}
+
+/// Marks a call as in flight until dropped
+struct CallGuard {
+    tracker: Arc<TaskTracker>,
+}
+
+impl Drop for CallGuard {
+    fn drop(&mut self) {
+        self.tracker.in_flight.send_modify(|count| *count -= 1);
+    }
+}
+
+/// Handle counting its calls in a [`TaskTracker`], refusing them once it's closed.
+///
+/// Streams and channels are in flight until they're dropped.
+pub struct TrackedHandle<H> {
+    id: String,
+    tracker: Arc<TaskTracker>,
+    handle: H,
+}
+
+impl<H> std::fmt::Debug for TrackedHandle<H> {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("TrackedHandle")
+            .field("id", &self.id)
+            .field("tracker", &self.tracker)
+            .finish()
+    }
+}
+
+impl<H> TrackedHandle<H> {
+    pub fn new(id: impl Into<String>, tracker: Arc<TaskTracker>, handle: H) -> Self {
+        Self {
+            id: id.into(),
+            tracker,
+            handle,
+        }
+    }
+
+    fn start_call(&self) -> Result<CallGuard> {
+        self.tracker
+            .start_call()
+            .ok_or_else(|| Error::ShutDown(self.id.clone()))
+    }
+}
+
+/// Keeps the call in flight for as long as the stream is alive
+fn guard_stream<D: YapsData>(stream: DataStream<D>, guard: CallGuard) -> DataStream<D> {
+    stream
+        .map(move |item| {
+            let _ = &guard;
+            item
+        })
+        .boxed()
+}
+
+impl<D: YapsData, H: FuncHandle<D>> FuncHandle<D> for TrackedHandle<H> {
+    async fn call(&self, args: D) -> Result<D> {
+        let _guard = self.start_call()?;
+        self.handle.call(args).await
+    }
+
+    async fn call_stream(&self, args: D) -> Result<DataStream<D>> {
+        let guard = self.start_call()?;
+        let stream = self.handle.call_stream(args).await?;
+        Ok(guard_stream(stream, guard))
+    }
+
+    async fn open_channel(&self, inputs: DataStream<D>) -> Result<DataStream<D>> {
+        let guard = self.start_call()?;
+        let outputs = match self.handle.open_channel(inputs).await {
+            // The wrapped handle doesn't know the id
+            Err(Error::NotAChannel(_)) => return Err(Error::NotAChannel(self.id.clone())),
+            outputs => outputs?,
+        };
+        Ok(guard_stream(outputs, guard))
+    }
+}
+
+/// Something a hub shutdown didn't wait for because the timeout elapsed
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Unfinished {
+    /// Calls still running in a plugin
+    Calls { plugin: String, in_flight: usize },
+    /// A plugin's shutdown hook
+    Hook { plugin: String },
+    /// A task spawned by a plugin, aborted after the timeout
+    Task { plugin: String, task: String },
+}
+
+/// Outcome of shutting down a hub
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct ShutdownReport {
+    /// What didn't finish before the timeout, in the order it was waited for
+    pub unfinished: Vec<Unfinished>,
+    /// Errors returned by the shutdown hooks and the joined tasks
+    pub errors: Vec<Error>,
+    /// Plugins depending on each other, shut down in reverse registration order
+    pub cycles: Vec<Vec<String>>,
+}
+
+impl ShutdownReport {
+    /// Whether everything finished in time without errors
+    pub fn is_clean(&self) -> bool {
+        self.unfinished.is_empty() && self.errors.is_empty()
+    }
+}
@@ -1,6 +1,7 @@
-use crate::{FuncHandle, FuncMetadata, FuncProvider, Result, YapsData, func_handle::SimpleHandle};
+use crate::{
+    DynFuncHandle, FuncMetadata, FuncProvider, Result, YapsData, func_handle::SimpleHandle,
+};
 
-use async_trait::async_trait;
 use std::sync::Arc;
 
 #[derive(Debug)]
@@ -20,7 +21,6 @@ impl<D: YapsData, F: Fn(D) -> Result<D> + Send + Sync + 'static> SingleProvider<
     }
 }
 
-#[async_trait]
 impl<D: YapsData, F: Fn(D) -> Result<D> + Send + Sync + 'static> FuncProvider<D>
     for SingleProvider<D, F>
 {
@@ -30,7 +30,7 @@ impl<D: YapsData, F: Fn(D) -> Result<D> + Send + Sync + 'static> FuncProvider<D>
         }])
     }
 
-    async fn get_func(&self, func: &str) -> Result<Box<dyn FuncHandle<D>>> {
+    async fn get_func(&self, func: &str) -> Result<Box<dyn DynFuncHandle<D>>> {
         if func == self.id {
             let func_clone = self.func.clone();
             Ok(Box::new(SimpleHandle::new(move |args| func_clone(args))))
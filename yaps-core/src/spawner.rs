@@ -0,0 +1,263 @@
+use crate::{Error, Result};
+
+use futures::{
+    FutureExt,
+    future::{AbortHandle, Abortable, BoxFuture},
+};
+use std::{
+    fmt,
+    future::Future,
+    panic::AssertUnwindSafe,
+    pin::Pin,
+    sync::{
+        Arc, LazyLock, OnceLock,
+        atomic::{AtomicBool, Ordering},
+    },
+    task::{Context, Poll},
+};
+use tokio::{
+    runtime::{Builder, Handle, Runtime},
+    sync::oneshot,
+};
+
+/// Boxed function run by [`Spawner::spawn_blocking_boxed`]
+pub type BlockingTask = Box<dyn FnOnce() + Send>;
+
+/// Executor running the tasks of plugins: their actors, streams, subscriptions and callbacks,
+/// and the calls of their blocking exports, see [`BlockingPool`](crate::blocking::BlockingPool).
+pub trait Spawner: Send + Sync + fmt::Debug {
+    /// Runs `task` in the background until it completes
+    fn spawn_boxed(&self, task: BoxFuture<'static, ()>);
+
+    /// Runs `task` on a thread where it may block.
+    ///
+    /// Uses the blocking threads of the ambient tokio runtime by default, or a thread of its own
+    /// outside of one.
+    fn spawn_blocking_boxed(&self, task: BlockingTask) {
+        match Handle::try_current() {
+            Ok(handle) => drop(handle.spawn_blocking(task)),
+            Err(_) => drop(std::thread::spawn(task)),
+        }
+    }
+}
+
+/// Spawns `future` on `spawner`, returning a handle to await or abort it
+pub fn spawn<T, F>(spawner: &dyn Spawner, future: F) -> Task<T>
+where
+    T: Send + 'static,
+    F: Future<Output = T> + Send + 'static,
+{
+    let (abort, registration) = AbortHandle::new_pair();
+    let (tx, rx) = oneshot::channel();
+    let finished = Arc::new(AtomicBool::new(false));
+
+    let done = finished.clone();
+    spawner.spawn_boxed(
+        async move {
+            // Panics are reported through the task handle instead of unwinding the executor
+            let output = AssertUnwindSafe(Abortable::new(future, registration))
+                .catch_unwind()
+                .await;
+            done.store(true, Ordering::Release);
+
+            if let Ok(Ok(output)) = output {
+                let _ = tx.send(output);
+            }
+        }
+        .boxed(),
+    );
+
+    Task {
+        rx,
+        abort,
+        finished,
+    }
+}
+
+/// Handle of a task spawned with [`spawn`].
+///
+/// Resolves to the task's output, or `None` if it panicked, was aborted or was dropped by its
+/// executor. Dropping the handle detaches the task.
+pub struct Task<T> {
+    rx: oneshot::Receiver<T>,
+    abort: AbortHandle,
+    finished: Arc<AtomicBool>,
+}
+
+impl<T> fmt::Debug for Task<T> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("Task")
+            .field("finished", &self.is_finished())
+            .finish()
+    }
+}
+
+impl<T> Task<T> {
+    /// Stops the task at its next await point
+    pub fn abort(&self) {
+        self.abort.abort();
+    }
+
+    pub fn is_finished(&self) -> bool {
+        self.finished.load(Ordering::Acquire)
+    }
+}
+
+impl<T> Future for Task<T> {
+    type Output = Option<T>;
+
+    fn poll(mut self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Self::Output> {
+        Pin::new(&mut self.rx).poll(cx).map(|output| output.ok())
+    }
+}
+
+/// Spawns on the tokio runtime of the calling code, like `tokio::spawn`
+#[derive(Debug, Default, Clone, Copy)]
+pub struct AmbientSpawner;
+
+impl Spawner for AmbientSpawner {
+    fn spawn_boxed(&self, task: BoxFuture<'static, ()>) {
+        tokio::spawn(task);
+    }
+}
+
+impl Spawner for Handle {
+    fn spawn_boxed(&self, task: BoxFuture<'static, ()>) {
+        self.spawn(task);
+    }
+
+    fn spawn_blocking_boxed(&self, task: BlockingTask) {
+        self.spawn_blocking(task);
+    }
+}
+
+/// Spawns on an `async-executor`, which has to be driven by the application
+#[cfg(feature = "async-executor")]
+impl Spawner for Arc<async_executor::Executor<'static>> {
+    fn spawn_boxed(&self, task: BoxFuture<'static, ()>) {
+        self.spawn(task).detach();
+    }
+}
+
+/// How a dedicated runtime is set up
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct RuntimeConfig {
+    /// Number of worker threads, one per core if unset
+    pub worker_threads: Option<usize>,
+    pub thread_name: String,
+}
+
+impl Default for RuntimeConfig {
+    fn default() -> Self {
+        Self::new("yaps-runtime")
+    }
+}
+
+impl RuntimeConfig {
+    pub fn new(thread_name: impl Into<String>) -> Self {
+        Self {
+            worker_threads: None,
+            thread_name: thread_name.into(),
+        }
+    }
+
+    /// Sets the number of worker threads (at least one)
+    pub fn with_worker_threads(mut self, threads: usize) -> Self {
+        self.worker_threads = Some(threads.max(1));
+        self
+    }
+}
+
+/// Spawns on a multi-threaded tokio runtime of its own
+pub struct RuntimeSpawner {
+    config: RuntimeConfig,
+    runtime: Option<Runtime>,
+}
+
+impl fmt::Debug for RuntimeSpawner {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("RuntimeSpawner")
+            .field("config", &self.config)
+            .finish()
+    }
+}
+
+impl RuntimeSpawner {
+    pub fn new(config: RuntimeConfig) -> Result<Self> {
+        let mut builder = Builder::new_multi_thread();
+        if let Some(threads) = config.worker_threads {
+            builder.worker_threads(threads);
+        }
+
+        let runtime = builder
+            .thread_name(config.thread_name.clone())
+            .enable_all()
+            .build()
+            .map_err(|e| Error::Runtime(e.to_string()))?;
+
+        Ok(Self {
+            config,
+            runtime: Some(runtime),
+        })
+    }
+
+    pub fn config(&self) -> &RuntimeConfig {
+        &self.config
+    }
+
+    pub fn handle(&self) -> &Handle {
+        self.runtime
+            .as_ref()
+            .expect("the runtime is only taken when dropped")
+            .handle()
+    }
+}
+
+impl Drop for RuntimeSpawner {
+    fn drop(&mut self) {
+        // The last reference can be dropped from async code, where waiting for the workers panics
+        if let Some(runtime) = self.runtime.take() {
+            runtime.shutdown_background();
+        }
+    }
+}
+
+impl Spawner for RuntimeSpawner {
+    fn spawn_boxed(&self, task: BoxFuture<'static, ()>) {
+        self.handle().spawn(task);
+    }
+
+    fn spawn_blocking_boxed(&self, task: BlockingTask) {
+        self.handle().spawn_blocking(task);
+    }
+}
+
+static AMBIENT: LazyLock<Arc<dyn Spawner>> = LazyLock::new(|| Arc::new(AmbientSpawner));
+
+/// Spawner used by a plugin, tasks go to the ambient runtime until one is set.
+///
+/// Only the first spawner set is kept, so one given to a wrapper wins over the hub's.
+#[derive(Debug, Default)]
+pub struct SpawnerCell {
+    spawner: OnceLock<Arc<dyn Spawner>>,
+}
+
+impl SpawnerCell {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Returns `false` if a spawner was already set
+    pub fn set(&self, spawner: Arc<dyn Spawner>) -> bool {
+        self.spawner.set(spawner).is_ok()
+    }
+
+    /// Whether a spawner was set, tasks go to the ambient runtime otherwise
+    pub fn is_set(&self) -> bool {
+        self.spawner.get().is_some()
+    }
+
+    pub fn get(&self) -> Arc<dyn Spawner> {
+        self.spawner.get().unwrap_or(&AMBIENT).clone()
+    }
+}
@@ -0,0 +1,153 @@
+use crate::{
+    Error, FuncHandle, Result, YapsData,
+    actor_handle::AsyncResult,
+    codec::{Codec, EncodeFor},
+    shutdown::TaskTracker,
+    spawner::{self, Spawner},
+};
+
+use futures::{
+    FutureExt, Stream, StreamExt,
+    future::{Either, select},
+    stream::{self, BoxStream},
+};
+use std::sync::Arc;
+use tokio::sync::mpsc;
+
+/// Default number of items a streaming function can produce ahead of its consumer
+pub const DEFAULT_STREAM_CAPACITY: usize = 16;
+
+/// Stream of encoded items returned by [`FuncHandle::call_stream`](crate::FuncHandle::call_stream)
+pub type DataStream<D> = BoxStream<'static, Result<D>>;
+
+/// Where a streaming function sends its items to
+#[derive(Debug)]
+pub struct StreamSink<D> {
+    tx: mpsc::Sender<Result<D>>,
+}
+
+impl<D: YapsData> StreamSink<D> {
+    /// Sends an item, waiting while the consumer is too far behind.
+    ///
+    /// Returns `false` once the consumer dropped the stream, the producer should stop then.
+    pub async fn send(&self, item: Result<D>) -> bool {
+        self.tx.send(item).await.is_ok()
+    }
+
+    /// Encodes and sends every item of `stream` until it ends or the consumer drops the stream
+    pub async fn forward_with_codec<C, S, T>(&self, codec: &C, stream: S)
+    where
+        C: Codec<Data = D> + EncodeFor<C, T>,
+        S: Stream<Item = T>,
+    {
+        let mut stream = std::pin::pin!(stream);
+
+        while let Some(item) = stream.next().await {
+            if !self.send(codec.encode(item)).await {
+                break;
+            }
+        }
+    }
+}
+
+type ProducerFn<D> = Box<dyn Fn(D, StreamSink<D>) -> AsyncResult<()> + Send + Sync>;
+
+/// Handle of a function producing its results incrementally.
+///
+/// Every call runs the producer in its own task, feeding a bounded channel so a producer can't
+/// get more than the capacity ahead of its consumer. Dropping the returned stream cancels the
+/// producer, even while it's waiting for its next item.
+pub struct StreamHandle<D: YapsData> {
+    id: String,
+    capacity: usize,
+    spawner: Arc<dyn Spawner>,
+    tracker: Option<Arc<TaskTracker>>,
+    producer: ProducerFn<D>,
+}
+
+impl<D: YapsData> std::fmt::Debug for StreamHandle<D> {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("StreamHandle")
+            .field("id", &self.id)
+            .field("capacity", &self.capacity)
+            .field("spawner", &self.spawner)
+            .field("tracker", &self.tracker)
+            .finish()
+    }
+}
+
+impl<D: YapsData> StreamHandle<D> {
+    /// Creates a handle spawning the producers on `spawner`
+    pub fn new<F>(id: impl Into<String>, spawner: Arc<dyn Spawner>, producer: F) -> Self
+    where
+        F: Fn(D, StreamSink<D>) -> AsyncResult<()> + Send + Sync + 'static,
+    {
+        Self {
+            id: id.into(),
+            capacity: DEFAULT_STREAM_CAPACITY,
+            spawner,
+            tracker: None,
+            producer: Box::new(producer),
+        }
+    }
+
+    /// Sets how many items the producer can get ahead of the consumer (at least one)
+    pub fn with_capacity(mut self, capacity: usize) -> Self {
+        self.capacity = capacity.max(1);
+        self
+    }
+
+    /// Tracks the producers in `tracker`, so they're joined when the plugin is shut down
+    pub fn with_tracker(mut self, tracker: Arc<TaskTracker>) -> Self {
+        self.tracker = Some(tracker);
+        self
+    }
+}
+
+impl<D: YapsData> FuncHandle<D> for StreamHandle<D> {
+    async fn call(&self, _args: D) -> Result<D> {
+        Err(Error::Streaming(self.id.clone()))
+    }
+
+    async fn call_stream(&self, args: D) -> Result<DataStream<D>> {
+        Ok(spawn_producer(
+            &self.id,
+            self.capacity,
+            self.spawner.as_ref(),
+            self.tracker.as_deref(),
+            |sink| (self.producer)(args, sink),
+        ))
+    }
+}
+
+/// Runs a producer in its own task, returning the stream of the items it sends.
+///
+/// An error returned by the producer is sent as the last item. The task is tracked in `tracker`
+/// if there's one, named after the function `id`.
+pub(crate) fn spawn_producer<D: YapsData>(
+    id: &str,
+    capacity: usize,
+    spawner: &dyn Spawner,
+    tracker: Option<&TaskTracker>,
+    producer: impl FnOnce(StreamSink<D>) -> AsyncResult<()>,
+) -> DataStream<D> {
+    let (tx, mut rx) = mpsc::channel(capacity);
+    let producer = producer(StreamSink { tx: tx.clone() });
+
+    let task = spawner::spawn(spawner, async move {
+        // Stops producing as soon as the consumer is gone
+        match select(producer, tx.closed().boxed()).await {
+            Either::Left((Err(e), _)) => {
+                let _ = tx.send(Err(e)).await;
+            }
+            Either::Left((Ok(()), _)) | Either::Right(_) => {}
+        }
+        Ok(())
+    });
+
+    if let Some(tracker) = tracker {
+        tracker.track(format!("{id} producer"), task);
+    }
+
+    stream::poll_fn(move |cx| rx.poll_recv(cx)).boxed()
+}
@@ -1,5 +1,27 @@
-use yaps_codecs::JsonCodec;
-use yaps_core::{FuncProvider as _, Result, codec::Codec as _, local_hub::LocalHub};
+use std::{
+    sync::{
+        Arc,
+        atomic::{AtomicUsize, Ordering},
+    },
+    time::Duration,
+};
+
+use yaps_codecs::{JsonCodec, JsonData};
+use yaps_core::{
+    DynFuncHandle, Error, FuncHandle, FuncMetadata, FuncProvider, Plugin, Result, SingleProvider,
+    balanced_handle::BalanceStrategy,
+    callback::Callback,
+    codec::Codec as _,
+    futures::StreamExt,
+    introspection::{Binding, HubInfo},
+    local_hub::LocalHub,
+    sharded_handle::ShardKey,
+    shared_handle::SharedHandle,
+    shared_hub::SharedHub,
+    shutdown::Unfinished,
+    spawner::{RuntimeConfig, RuntimeSpawner, Spawner},
+    tokio::sync::{Mutex, oneshot},
+};
 use yaps_macros::yaps_plugin;
 
 #[yaps_plugin]
@@ -57,34 +79,2219 @@ mod multiplier {
     }
 }
 
-#[tokio::test]
-async fn single_provider_test() -> Result<()> {
-    let mut hub = LocalHub::new();
+#[yaps_plugin]
+mod lazy_multiplier {
+    use yaps_core::Result;
 
-    let adder = adder::AdderWrapper::new(adder::Adder::default(), JsonCodec);
-    let multiplier =
-        multiplier::MultiplierWrapper::new(multiplier::Multiplier::default(), JsonCodec);
+    #[derive(Default)]
+    pub struct LazyMultiplier;
 
-    hub.add_provider(adder).await?;
-    hub.add_plugin(multiplier).await?;
+    #[yaps_extern(namespace = "Adder", lazy)]
+    impl LazyMultiplier {
+        async fn add(&self, a: i32, b: i32) -> i32;
+    }
 
-    let func = hub.get_func("mult").await?;
+    impl LazyMultiplier {
+        #[yaps_export(id = "lazy_mult")]
+        async fn mult(&self, a: i32, b: i32) -> Result<i32> {
+            let mut sum = 0;
+            for _ in 0..b {
+                sum = self.add(sum, a).await?;
+            }
+            Ok(sum)
+        }
+    }
+}
 
-    let codec = JsonCodec;
-    let data = codec.encode((12, 3))?;
-    let result = func.call(data).await?;
-    let result: Result<i32> = codec.decode(result)?;
+#[yaps_plugin]
+mod calculator {
+    use yaps_core::Result;
 
-    assert_eq!(result, Ok(36));
+    #[derive(Default)]
+    pub struct Calculator;
 
-    let func = hub.get_func("div").await?;
+    #[yaps_extern(optional)]
+    impl Calculator {
+        #[yaps_extern(namespace = "Adder")]
+        async fn add(&self, a: i32, b: i32) -> i32;
 
-    let codec = JsonCodec;
-    let data = codec.encode((13, 3))?;
-    let result = func.call(data).await?;
-    let result: Result<i32> = codec.decode(result)?;
+        #[yaps_extern(namespace = "Subber", lazy)]
+        async fn sub(&self, a: i32, b: i32) -> i32;
+    }
 
-    assert_eq!(result, Ok(4));
+    impl Calculator {
+        #[yaps_export(id = "calc")]
+        async fn calc(&self, a: i32, b: i32) -> Result<(Option<i32>, Option<i32>)> {
+            Ok((self.add(a, b).await?, self.sub(a, b).await?))
+        }
+
+        #[yaps_export(id = "calc_has_add")]
+        async fn has_add(&self) -> bool {
+            self.has_extern("Adder::add").await
+        }
+
+        #[yaps_export(id = "calc_has_sub")]
+        async fn has_sub(&self) -> bool {
+            self.has_extern("Subber::sub").await
+        }
+    }
+}
+
+#[yaps_plugin]
+mod fallback_calculator {
+    use yaps_core::Result;
+
+    #[derive(Default)]
+    pub struct FallbackCalculator;
+
+    #[yaps_extern(namespace = "Adder")]
+    impl FallbackCalculator {
+        async fn add(&self, a: i32, b: i32) -> i32 {
+            -(a + b)
+        }
+
+        #[yaps_extern(namespace = "Flaky", id = "get", fallback_on(HandlerInvalidated))]
+        async fn flaky(&self, x: i32) -> i32 {
+            x * 10
+        }
+
+        #[yaps_extern(namespace = "Flaky", id = "strict_get")]
+        async fn strict_flaky(&self, _x: i32) -> i32 {
+            0
+        }
+    }
+
+    impl FallbackCalculator {
+        #[yaps_export(id = "fallback_add")]
+        async fn fallback_add(&self, a: i32, b: i32) -> Result<i32> {
+            self.add(a, b).await
+        }
+
+        #[yaps_export(id = "fallback_flaky")]
+        async fn fallback_flaky(&self, x: i32) -> Result<(i32, Result<i32>)> {
+            Ok((self.flaky(x).await?, self.strict_flaky(x).await))
+        }
+    }
+}
+
+#[yaps_plugin]
+mod reloadable {
+    use std::sync::atomic::{AtomicUsize, Ordering};
+
+    #[derive(Default)]
+    pub struct Reloadable {
+        reloads: AtomicUsize,
+    }
+
+    impl Reloadable {
+        #[yaps_export(id = "on_reload")]
+        fn on_reload(&self) -> usize {
+            self.reloads.fetch_add(1, Ordering::SeqCst) + 1
+        }
+    }
+}
+
+#[yaps_plugin]
+mod reloader {
+    use yaps_core::Result;
+
+    #[derive(Default)]
+    pub struct Reloader;
+
+    #[yaps_extern(broadcast)]
+    impl Reloader {
+        async fn on_reload(&self) -> Vec<Result<usize>>;
+    }
+
+    impl Reloader {
+        #[yaps_export(id = "reload_all")]
+        async fn reload_all(&self) -> Result<Vec<Result<usize>>> {
+            self.on_reload().await
+        }
+    }
+}
+
+#[yaps_plugin]
+mod cache_shard {
+    #[derive(Default)]
+    pub struct CacheShard {
+        shard: usize,
+    }
+
+    impl CacheShard {
+        pub fn new(shard: usize) -> Self {
+            Self {
+                shard,
+                ..Default::default()
+            }
+        }
+    }
+
+    #[yaps_export(namespace = "Cache")]
+    impl CacheShard {
+        fn get(&self, user_id: u64) -> (usize, u64) {
+            (self.shard, user_id)
+        }
+    }
+}
+
+#[yaps_plugin]
+mod cache_client {
+    use yaps_core::Result;
+
+    #[derive(Default)]
+    pub struct CacheClient;
+
+    // The shard key is merged into the extern's own arguments
+    #[yaps_extern(namespace = "Cache", shard_key = "user_id")]
+    impl CacheClient {
+        #[yaps_extern(id = "get")]
+        async fn get(&self, user_id: u64) -> (usize, u64);
+    }
+
+    impl CacheClient {
+        #[yaps_export(id = "lookup")]
+        async fn lookup(&self, user_id: u64) -> Result<usize> {
+            let (shard, _) = self.get(user_id).await?;
+            Ok(shard)
+        }
+    }
+}
+
+#[yaps_plugin]
+mod config_store {
+    use yaps_core::Result;
+
+    #[derive(Default)]
+    pub struct ConfigStore;
+
+    impl ConfigStore {
+        #[yaps_publish(topic = "config_changed")]
+        fn config_changed(&self, key: String, value: i32);
+
+        #[yaps_export(id = "set_config")]
+        fn set_config(&self, key: String, value: i32) -> Result<()> {
+            self.config_changed(key, value)
+        }
+    }
+}
+
+#[yaps_plugin]
+mod config_watcher {
+    use yaps_core::tokio::sync::{Mutex, Notify};
+    use yaps_core::{Error, Result};
+
+    #[derive(Default)]
+    pub struct ConfigWatcher {
+        pub changes: Mutex<Vec<(String, i32)>>,
+        pub notify: Notify,
+    }
+
+    impl ConfigWatcher {
+        #[yaps_subscribe(topic = "config_changed")]
+        async fn on_config_changed(&self, key: String, value: i32) -> Result<()> {
+            if value < 0 {
+                return Err(Error::Custom(format!("{key} can't be negative")));
+            }
+
+            self.changes.lock().await.push((key, value));
+            self.notify.notify_one();
+            Ok(())
+        }
+    }
+}
+
+#[yaps_plugin]
+mod log_source {
+    use yaps_core::futures::{Stream, StreamExt, stream};
+    use yaps_core::tokio::sync::{Mutex, oneshot};
+
+    #[derive(Default)]
+    pub struct LogSource {
+        lines: Vec<String>,
+        cancelled: Mutex<Option<oneshot::Sender<()>>>,
+    }
+
+    impl LogSource {
+        pub fn new(lines: Vec<String>, cancelled: oneshot::Sender<()>) -> Self {
+            Self {
+                lines,
+                cancelled: Mutex::new(Some(cancelled)),
+                ..Default::default()
+            }
+        }
+    }
+
+    #[yaps_export]
+    impl LogSource {
+        fn tail(&self, count: usize) -> impl Stream<Item = String> {
+            stream::iter(self.lines.iter().rev().take(count).rev().cloned())
+        }
+
+        async fn follow(&self) -> impl Stream<Item = String> {
+            // Dropped with the stream, which never ends on its own
+            let guard = self.cancelled.lock().await.take();
+
+            stream::iter(self.lines.clone())
+                .chain(stream::pending())
+                .map(move |line| {
+                    let _guard = &guard;
+                    line
+                })
+        }
+    }
+}
+
+#[yaps_plugin]
+mod log_reader {
+    use yaps_core::Result;
+    use yaps_core::futures::TryStreamExt;
+
+    #[derive(Default)]
+    pub struct LogReader;
+
+    #[yaps_extern]
+    impl LogReader {
+        async fn tail(
+            &self,
+            count: usize,
+        ) -> impl yaps_core::futures::Stream<Item = Result<String>>;
+    }
+
+    impl LogReader {
+        #[yaps_export(id = "read_tail")]
+        async fn read_tail(&self, count: usize) -> Result<Vec<String>> {
+            self.tail(count).await?.try_collect().await
+        }
+    }
+}
+
+#[yaps_plugin]
+mod uploader {
+    use yaps_core::Result;
+    use yaps_core::futures::{Stream, StreamExt};
+
+    #[derive(Default)]
+    pub struct Uploader;
+
+    #[yaps_export]
+    impl Uploader {
+        async fn upload(&self, chunks: impl Stream<Item = Result<String>>) -> usize {
+            chunks
+                .fold(0, |len, chunk| async move {
+                    len + chunk.map(|c| c.len()).unwrap_or_default()
+                })
+                .await
+        }
+
+        fn shout(&self, lines: impl Stream<Item = Result<String>>) -> impl Stream<Item = String> {
+            lines.map(|line| line.unwrap_or_default().to_uppercase())
+        }
+    }
+}
+
+#[yaps_plugin]
+mod upload_client {
+    use yaps_core::Result;
+    use yaps_core::futures::{StreamExt, TryStreamExt, stream};
+
+    #[derive(Default)]
+    pub struct UploadClient;
+
+    #[yaps_extern]
+    impl UploadClient {
+        async fn upload(
+            &self,
+            chunks: impl yaps_core::futures::Stream<Item = String>,
+        ) -> impl yaps_core::futures::Stream<Item = Result<usize>>;
+
+        async fn shout(
+            &self,
+            lines: impl yaps_core::futures::Stream<Item = String>,
+        ) -> impl yaps_core::futures::Stream<Item = Result<String>>;
+    }
+
+    impl UploadClient {
+        #[yaps_export(id = "upload_lines")]
+        async fn upload_lines(&self, lines: Vec<String>) -> Result<(Vec<usize>, Vec<String>)> {
+            let uploaded = self.upload(stream::iter(lines.clone()).boxed()).await?;
+            let shouted = self.shout(stream::iter(lines).boxed()).await?;
+
+            Ok((uploaded.try_collect().await?, shouted.try_collect().await?))
+        }
+    }
+}
+
+#[yaps_plugin]
+mod notifier {
+    use yaps_core::callback::Callback;
+    use yaps_core::tokio::sync::Mutex;
+
+    #[derive(Default)]
+    pub struct Notifier {
+        listeners: Mutex<Vec<Callback<(String,), ()>>>,
+    }
+
+    #[yaps_export]
+    impl Notifier {
+        async fn register_listener(&self, cb: Callback<(String,), ()>) {
+            self.listeners.lock().await.push(cb);
+        }
+
+        /// Returns how many listeners were notified
+        async fn notify(&self, message: String) -> usize {
+            let mut notified = 0;
+            for listener in self.listeners.lock().await.iter() {
+                if listener.call((message.clone(),)).await.is_ok() {
+                    notified += 1;
+                }
+            }
+            notified
+        }
+
+        async fn forget_listeners(&self) {
+            self.listeners.lock().await.clear();
+        }
+    }
+}
+
+#[yaps_plugin]
+mod listener {
+    use std::sync::Arc;
+    use yaps_core::Result;
+    use yaps_core::actor_handle::AsyncResult;
+    use yaps_core::callback::Callback;
+    use yaps_core::tokio::sync::Mutex;
+
+    #[derive(Default)]
+    pub struct Listener {
+        pub messages: Arc<Mutex<Vec<String>>>,
+        pub callback: Mutex<Option<Callback<(String,), ()>>>,
+    }
+
+    #[yaps_extern]
+    impl Listener {
+        async fn register_listener(&self, cb: Callback<(String,), ()>);
+    }
+
+    impl Listener {
+        #[yaps_export(id = "listen")]
+        async fn listen(&self) -> Result<()> {
+            let messages = self.messages.clone();
+            let callback = Callback::new(move |(message,): (String,)| -> AsyncResult<()> {
+                let messages = messages.clone();
+                Box::pin(async move {
+                    messages.lock().await.push(message);
+                    Ok(())
+                })
+            })?;
+
+            // Kept so the callback stays alive
+            self.callback.lock().await.replace(callback.clone());
+            self.register_listener(callback).await
+        }
+
+        #[yaps_export(id = "stop_listening")]
+        async fn stop_listening(&self) -> bool {
+            let callback = self.callback.lock().await.take();
+            callback.is_some_and(|callback| callback.is_alive())
+        }
+    }
+}
+
+#[yaps_plugin]
+mod database {
+    use std::sync::{Arc, Mutex};
+    use yaps_core::Result;
+
+    #[derive(Default)]
+    pub struct Database {
+        log: Arc<Mutex<Vec<String>>>,
+    }
+
+    impl Database {
+        pub fn new(log: Arc<Mutex<Vec<String>>>) -> Self {
+            Self {
+                log,
+                ..Default::default()
+            }
+        }
+
+        fn record(&self, event: &str) {
+            self.log.lock().unwrap().push(format!("database {event}"));
+        }
+
+        #[yaps_export(id = "query")]
+        fn query(&self, key: String) -> String {
+            format!("value of {key}")
+        }
+
+        #[yaps_init]
+        fn init(&self) {
+            self.record("init");
+        }
+
+        #[yaps_ready]
+        async fn ready(&self) -> Result<()> {
+            self.record("ready");
+            Ok(())
+        }
+
+        #[yaps_shutdown]
+        fn shutdown(&self) {
+            self.record("shutdown");
+        }
+    }
+}
+
+#[yaps_plugin]
+mod service {
+    use std::sync::{Arc, Mutex};
+    use yaps_core::{Error, Result};
+
+    #[derive(Default)]
+    pub struct Service {
+        log: Arc<Mutex<Vec<String>>>,
+        fail_init: bool,
+    }
+
+    impl Service {
+        pub fn new(log: Arc<Mutex<Vec<String>>>, fail_init: bool) -> Self {
+            Self {
+                log,
+                fail_init,
+                ..Default::default()
+            }
+        }
+
+        #[yaps_extern]
+        async fn query(&self, key: String) -> String;
+
+        #[yaps_init]
+        fn init(&self) -> Result<()> {
+            if self.fail_init {
+                return Err(Error::PluginNotInitialized("config".to_string()));
+            }
+
+            self.log.lock().unwrap().push("service init".to_string());
+            Ok(())
+        }
+
+        // Externs are bound by the time the plugin is ready
+        #[yaps_ready]
+        async fn ready(&self) -> Result<()> {
+            let value = self.query("config".to_string()).await?;
+            self.log
+                .lock()
+                .unwrap()
+                .push(format!("service ready: {value}"));
+            Ok(())
+        }
+
+        #[yaps_shutdown]
+        fn shutdown(&self) {
+            self.log
+                .lock()
+                .unwrap()
+                .push("service shutdown".to_string());
+        }
+    }
+}
+
+#[yaps_plugin]
+mod sleeper {
+    use std::time::Duration;
+    use yaps_core::tokio::sync::{Mutex, oneshot};
+
+    #[derive(Default)]
+    pub struct Sleeper {
+        started: Mutex<Option<oneshot::Sender<()>>>,
+    }
+
+    impl Sleeper {
+        /// Sends on `started` once the first call is running
+        pub fn new(started: oneshot::Sender<()>) -> Self {
+            Self {
+                started: Mutex::new(Some(started)),
+                ..Default::default()
+            }
+        }
+
+        #[yaps_export(id = "sleep")]
+        async fn sleep(&self, millis: u64) -> u64 {
+            if let Some(started) = self.started.lock().await.take() {
+                let _ = started.send(());
+            }
+
+            yaps_core::tokio::time::sleep(Duration::from_millis(millis)).await;
+            millis
+        }
+    }
+}
+
+#[yaps_plugin]
+mod counter {
+    #[derive(Default)]
+    pub struct Counter {
+        pub count: u64,
+        pub calls: u64,
+    }
+
+    #[yaps_export]
+    impl Counter {
+        fn increment(&mut self, by: u64) -> u64 {
+            self.count += by;
+            self.calls += 1;
+            self.count
+        }
+
+        fn reset(&mut self) -> u64 {
+            self.calls += 1;
+            std::mem::take(&mut self.count)
+        }
+
+        fn count(&self) -> u64 {
+            self.count
+        }
+    }
+}
+
+#[yaps_plugin(blocking_threads = 2)]
+mod cruncher {
+    use std::{
+        sync::{Condvar, Mutex},
+        time::Duration,
+    };
+
+    static ARRIVED: Mutex<usize> = Mutex::new(0);
+    static MET: Condvar = Condvar::new();
+
+    #[derive(Default)]
+    pub struct Cruncher {
+        pub crunched: u64,
+    }
+
+    #[yaps_export(blocking)]
+    impl Cruncher {
+        fn crunch(&self, millis: u64) -> u64 {
+            std::thread::sleep(Duration::from_millis(millis));
+            millis
+        }
+
+        // Waits for another call to be waiting here at the same time, giving up after a while
+        fn meet(&self) -> bool {
+            let mut arrived = ARRIVED.lock().unwrap();
+            *arrived += 1;
+            MET.notify_all();
+            let (arrived, _) = MET
+                .wait_timeout_while(arrived, Duration::from_secs(5), |arrived| *arrived < 2)
+                .unwrap();
+            *arrived >= 2
+        }
+
+        fn record(&mut self, millis: u64) -> u64 {
+            std::thread::sleep(Duration::from_millis(millis));
+            self.crunched += millis;
+            self.crunched
+        }
+    }
+}
+
+#[yaps_plugin]
+mod thread_reporter {
+    #[derive(Default)]
+    pub struct ThreadReporter;
+
+    #[yaps_export]
+    impl ThreadReporter {
+        fn thread_name(&self) -> Option<String> {
+            std::thread::current().name().map(str::to_string)
+        }
+
+        #[yaps_export(serial)]
+        fn serial_thread_name(&self) -> Option<String> {
+            std::thread::current().name().map(str::to_string)
+        }
+
+        #[yaps_export(blocking)]
+        fn blocking_thread_name(&self) -> Option<String> {
+            std::thread::current().name().map(str::to_string)
+        }
+    }
+}
+
+#[yaps_plugin]
+mod hog {
+    use std::time::Duration;
+
+    #[derive(Default)]
+    pub struct Hog;
+
+    #[yaps_export]
+    impl Hog {
+        async fn hog(&self, millis: u64) -> Option<String> {
+            // Blocks its executor instead of yielding
+            std::thread::sleep(Duration::from_millis(millis));
+            std::thread::current().name().map(str::to_string)
+        }
+    }
+}
+
+#[yaps_plugin]
+mod ping {
+    use yaps_core::Result;
+
+    #[derive(Default)]
+    pub struct Ping;
+
+    #[yaps_extern]
+    impl Ping {
+        async fn pong(&self, n: u32) -> Result<u32>;
+    }
+
+    impl Ping {
+        #[yaps_export]
+        async fn ping(&self, n: u32) -> Result<u32> {
+            if n == 0 {
+                return Ok(0);
+            }
+            Ok(self.pong(n - 1).await?? + 1)
+        }
+    }
+}
+
+#[yaps_plugin]
+mod pong {
+    use yaps_core::Result;
+
+    #[derive(Default)]
+    pub struct Pong;
+
+    #[yaps_extern]
+    impl Pong {
+        async fn ping(&self, n: u32) -> Result<u32>;
+    }
+
+    impl Pong {
+        #[yaps_export]
+        async fn pong(&self, n: u32) -> Result<u32> {
+            Ok(self.ping(n).await?? + 1)
+        }
+    }
+}
+
+#[yaps_plugin]
+mod ledger {
+    use yaps_core::Result;
+
+    #[derive(Default)]
+    pub struct Ledger {
+        pub entries: u64,
+    }
+
+    #[yaps_extern]
+    impl Ledger {
+        async fn audit(&self) -> Result<u64>;
+    }
+
+    #[yaps_export]
+    impl Ledger {
+        fn record(&mut self) -> u64 {
+            self.entries += 1;
+            self.entries
+        }
+
+        // Holds a read lock on the ledger while the auditor runs
+        async fn reconcile(&self) -> Result<u64> {
+            self.audit().await?
+        }
+
+        // Holds the write lock while it awaits
+        async fn deposit(&mut self, amount: u64) -> u64 {
+            yaps_core::tokio::task::yield_now().await;
+            self.entries += amount;
+            self.entries
+        }
+
+        async fn settle(&mut self) -> Result<u64> {
+            self.audit().await?
+        }
+    }
+}
+
+#[yaps_plugin]
+mod auditor {
+    use yaps_core::Result;
+
+    #[derive(Default)]
+    pub struct Auditor;
+
+    #[yaps_extern]
+    impl Auditor {
+        async fn record(&self) -> u64;
+    }
+
+    #[yaps_export]
+    impl Auditor {
+        async fn audit(&self) -> Result<u64> {
+            self.record().await
+        }
+    }
+}
+
+#[tokio::test]
+async fn single_provider_test() -> Result<()> {
+    let mut hub = LocalHub::new();
+
+    let adder = adder::AdderWrapper::new(adder::Adder::default(), JsonCodec);
+    let multiplier =
+        multiplier::MultiplierWrapper::new(multiplier::Multiplier::default(), JsonCodec);
+
+    hub.add_provider(adder).await?;
+    hub.add_plugin(multiplier).await?;
+
+    let func = hub.get_func("mult").await?;
+
+    let codec = JsonCodec;
+    let data = codec.encode((12, 3))?;
+    let result = func.call(data).await?;
+    let result: Result<i32> = codec.decode(result)?;
+
+    assert_eq!(result, Ok(36));
+
+    let func = hub.get_func("div").await?;
+
+    let codec = JsonCodec;
+    let data = codec.encode((13, 3))?;
+    let result = func.call(data).await?;
+    let result: Result<i32> = codec.decode(result)?;
+
+    assert_eq!(result, Ok(4));
+
+    Ok(())
+}
+
+#[tokio::test]
+async fn introspection_test() -> Result<()> {
+    let mut hub = LocalHub::new();
+
+    let multiplier =
+        multiplier::MultiplierWrapper::new(multiplier::Multiplier::default(), JsonCodec);
+    let adder = adder::AdderWrapper::new(adder::Adder::default(), JsonCodec);
+
+    hub.add_plugin(multiplier).await?;
+    hub.add_provider(adder).await?;
+
+    let info = hub.introspect();
+
+    let names: Vec<_> = info.plugins.iter().map(|p| p.name.as_str()).collect();
+    assert_eq!(names, ["Multiplier", "Adder"]);
+
+    let provided: Vec<_> = info.plugins[1].provides.iter().map(|f| &f.id).collect();
+    assert_eq!(provided, ["Adder::add", "Subber::sub"]);
+
+    assert_eq!(
+        info.bindings,
+        [
+            Binding {
+                consumer: 0,
+                func: "Adder::add".to_string(),
+                provider: Some(1),
+            },
+            Binding {
+                consumer: 0,
+                func: "Subber::sub".to_string(),
+                provider: Some(1),
+            },
+        ]
+    );
+
+    let json = serde_json::to_string(&info).expect("HubInfo should serialize");
+    let decoded: HubInfo = serde_json::from_str(&json).expect("HubInfo should deserialize");
+    assert_eq!(decoded, info);
+
+    assert!(format!("{hub:?}").contains("Multiplier"));
+
+    Ok(())
+}
+
+struct BrokenProvider;
+
+impl FuncProvider<JsonData> for BrokenProvider {
+    async fn provided_funcs(&self) -> Result<Vec<FuncMetadata>> {
+        Ok(["Adder::add", "Subber::sub"]
+            .map(|id| FuncMetadata { id: id.to_string() })
+            .to_vec())
+    }
+
+    async fn get_func(&self, id: &str) -> Result<Box<dyn DynFuncHandle<JsonData>>> {
+        Err(Error::FunctionNotInitialized(id.to_string()))
+    }
+}
+
+#[tokio::test]
+async fn add_plugins_test() -> Result<()> {
+    let mut hub = LocalHub::new().with_connect_concurrency(2);
+
+    // Registered together, so the order doesn't matter
+    hub.add_plugins([
+        multiplier::MultiplierWrapper::new(multiplier::Multiplier::default(), JsonCodec)
+            as Arc<dyn Plugin<JsonData>>,
+        adder::AdderWrapper::new(adder::Adder::default(), JsonCodec),
+    ])
+    .await?;
+
+    let func = hub.get_func("mult").await?;
+    let result: Result<i32> = func.call_with_codec(&JsonCodec, (4, 5)).await?;
+    assert_eq!(result, Ok(20));
+
+    Ok(())
+}
+
+#[tokio::test]
+async fn failed_registration_test() -> Result<()> {
+    let log = Arc::new(std::sync::Mutex::new(Vec::new()));
+    let mut hub = LocalHub::new();
+
+    hub.add_plugin(multiplier::MultiplierWrapper::new(
+        multiplier::Multiplier::default(),
+        JsonCodec,
+    ))
+    .await?;
+
+    let result = hub
+        .add_plugins([
+            adder::AdderWrapper::new(adder::Adder::default(), JsonCodec)
+                as Arc<dyn Plugin<JsonData>>,
+            service::ServiceWrapper::new(service::Service::new(log, true), JsonCodec),
+        ])
+        .await;
+    assert!(result.is_err());
+
+    // The registered consumer wasn't bound to the plugins that failed to register
+    let mult = hub.get_func("mult").await?;
+    let result: Result<i32> = mult.call_with_codec(&JsonCodec, (4, 5)).await?;
+    assert_eq!(
+        result,
+        Err(Error::FunctionNotInitialized("Adder::add".to_string()))
+    );
+
+    hub.add_plugin(adder::AdderWrapper::new(adder::Adder::default(), JsonCodec))
+        .await?;
+    let result: Result<i32> = mult.call_with_codec(&JsonCodec, (4, 5)).await?;
+    assert_eq!(result, Ok(20));
+
+    Ok(())
+}
+
+#[tokio::test]
+async fn connect_errors_are_aggregated() -> Result<()> {
+    let mut hub = LocalHub::new();
+    hub.add_provider(BrokenProvider).await?;
+
+    let multiplier =
+        multiplier::MultiplierWrapper::new(multiplier::Multiplier::default(), JsonCodec);
+    let err = hub.add_plugin(multiplier).await.unwrap_err();
+
+    let Error::Plugin { plugin, error } = err else {
+        panic!("Expected a plugin error, got {err:?}");
+    };
+    assert_eq!(plugin, "Multiplier");
+
+    let Error::Multiple(mut errors) = *error else {
+        panic!("Expected multiple errors, got {error:?}");
+    };
+    errors.sort_by_key(|e| e.to_string());
+    assert_eq!(
+        errors,
+        [
+            Error::FunctionNotInitialized("Adder::add".to_string()),
+            Error::FunctionNotInitialized("Subber::sub".to_string()),
+        ]
+    );
+
+    assert!(hub.introspect().plugins.len() == 1);
+
+    Ok(())
+}
+
+struct GatedProvider<P> {
+    gate: Mutex<Option<oneshot::Receiver<()>>>,
+    inner: P,
+}
+
+impl<P: FuncProvider<JsonData>> FuncProvider<JsonData> for GatedProvider<P> {
+    async fn provided_funcs(&self) -> Result<Vec<FuncMetadata>> {
+        let gate = self.gate.lock().await.take();
+        if let Some(gate) = gate {
+            gate.await.expect("gate sender dropped");
+        }
+        self.inner.provided_funcs().await
+    }
+
+    async fn get_func(&self, id: &str) -> Result<Box<dyn DynFuncHandle<JsonData>>> {
+        self.inner.get_func(id).await
+    }
+}
+
+#[tokio::test]
+async fn shared_hub_test() -> Result<()> {
+    let hub = Arc::new(SharedHub::new());
+
+    hub.add_provider(adder::AdderWrapper::new(adder::Adder::default(), JsonCodec))
+        .await?;
+
+    let (open_gate, gate) = oneshot::channel();
+    let gated = GatedProvider {
+        gate: Mutex::new(Some(gate)),
+        inner: SingleProvider::new("gated".to_string(), Ok),
+    };
+
+    let registration = tokio::spawn({
+        let hub = hub.clone();
+        async move { hub.add_provider(gated).await }
+    });
+    tokio::task::yield_now().await;
+
+    // The registration is stuck, but lookups are still served
+    let add = hub.get_func("Adder::add").await?;
+    let result: i32 = add.call_with_codec(&JsonCodec, (1, 2)).await?;
+    assert_eq!(result, 3);
+    assert!(matches!(
+        hub.get_func("gated").await,
+        Err(Error::FunctionNotFound(_))
+    ));
+
+    open_gate
+        .send(())
+        .expect("registration task should be waiting");
+    registration.await.expect("registration task panicked")?;
+
+    hub.get_func("gated").await?;
+
+    // Plugins can be added through the shared reference at any time
+    hub.add_plugin(multiplier::MultiplierWrapper::new(
+        multiplier::Multiplier::default(),
+        JsonCodec,
+    ))
+    .await?;
+
+    let mult = hub.get_func("mult").await?;
+    let result: Result<i32> = mult.call_with_codec(&JsonCodec, (3, 3)).await?;
+    assert_eq!(result, Ok(9));
+
+    // It's shut down through the shared reference as well
+    let report = hub.shutdown(Duration::from_secs(5)).await;
+    assert!(report.is_clean(), "{report:?}");
+    assert!(matches!(
+        mult.call_with_codec::<_, _, Result<i32>>(&JsonCodec, (3, 3))
+            .await,
+        Err(Error::ShutDown(_))
+    ));
+
+    Ok(())
+}
+
+struct CountingProvider<P> {
+    resolved: Arc<AtomicUsize>,
+    inner: P,
+}
+
+impl<P: FuncProvider<JsonData>> FuncProvider<JsonData> for CountingProvider<P> {
+    async fn provided_funcs(&self) -> Result<Vec<FuncMetadata>> {
+        self.inner.provided_funcs().await
+    }
+
+    async fn get_func(&self, id: &str) -> Result<Box<dyn DynFuncHandle<JsonData>>> {
+        self.resolved.fetch_add(1, Ordering::SeqCst);
+        self.inner.get_func(id).await
+    }
+}
+
+#[tokio::test]
+async fn lazy_extern_test() -> Result<()> {
+    let mut hub = LocalHub::new();
+
+    hub.add_plugin(lazy_multiplier::LazyMultiplierWrapper::new(
+        lazy_multiplier::LazyMultiplier::default(),
+        JsonCodec,
+    ))
+    .await?;
+
+    let resolved = Arc::new(AtomicUsize::new(0));
+    hub.add_provider(CountingProvider {
+        resolved: resolved.clone(),
+        inner: adder::AdderWrapper::new(adder::Adder::default(), JsonCodec),
+    })
+    .await?;
+
+    // Nothing is resolved until the extern gets called
+    assert_eq!(resolved.load(Ordering::SeqCst), 0);
+    assert_eq!(hub.introspect().bindings[0].provider, None);
+
+    let mult = hub.get_func("lazy_mult").await?;
+    let result: Result<i32> = mult.call_with_codec(&JsonCodec, (6, 7)).await?;
+    assert_eq!(result, Ok(42));
+
+    // The handle is resolved once and cached afterwards
+    assert_eq!(resolved.load(Ordering::SeqCst), 1);
+    assert_eq!(hub.introspect().bindings[0].provider, Some(1));
+
+    Ok(())
+}
+
+#[tokio::test]
+async fn optional_extern_test() -> Result<()> {
+    let mut hub = LocalHub::new();
+
+    hub.add_plugin(calculator::CalculatorWrapper::new(
+        calculator::Calculator::default(),
+        JsonCodec,
+    ))
+    .await?;
+
+    let calc = hub.get_func("calc").await?;
+    let has_add = hub.get_func("calc_has_add").await?;
+
+    let result: Result<(Option<i32>, Option<i32>)> =
+        calc.call_with_codec(&JsonCodec, (5, 3)).await?;
+    assert_eq!(result, Ok((None, None)));
+    assert!(
+        !has_add
+            .call_with_codec::<_, _, bool>(&JsonCodec, ())
+            .await?
+    );
+
+    hub.add_provider(adder::AdderWrapper::new(adder::Adder::default(), JsonCodec))
+        .await?;
+
+    // Checking a lazy extern doesn't resolve it
+    let has_sub = hub.get_func("calc_has_sub").await?;
+    assert!(
+        has_sub
+            .call_with_codec::<_, _, bool>(&JsonCodec, ())
+            .await?
+    );
+    assert_eq!(hub.introspect().bindings[1].provider, None);
+
+    let result: Result<(Option<i32>, Option<i32>)> =
+        calc.call_with_codec(&JsonCodec, (5, 3)).await?;
+    assert_eq!(result, Ok((Some(8), Some(2))));
+    assert!(
+        has_add
+            .call_with_codec::<_, _, bool>(&JsonCodec, ())
+            .await?
+    );
+
+    Ok(())
+}
+
+#[tokio::test]
+async fn fallback_extern_test() -> Result<()> {
+    let mut hub = LocalHub::new();
+
+    hub.add_plugin(fallback_calculator::FallbackCalculatorWrapper::new(
+        fallback_calculator::FallbackCalculator::default(),
+        JsonCodec,
+    ))
+    .await?;
+
+    let add = hub.get_func("fallback_add").await?;
+
+    // Nothing provides the function yet, so the fallback body runs
+    let result: Result<i32> = add.call_with_codec(&JsonCodec, (2, 3)).await?;
+    assert_eq!(result, Ok(-5));
+
+    hub.add_provider(adder::AdderWrapper::new(adder::Adder::default(), JsonCodec))
+        .await?;
+
+    let result: Result<i32> = add.call_with_codec(&JsonCodec, (2, 3)).await?;
+    assert_eq!(result, Ok(5));
+
+    hub.add_provider(SingleProvider::new("Flaky::get".to_string(), |_| {
+        Err(Error::HandlerInvalidated)
+    }))
+    .await?;
+    hub.add_provider(SingleProvider::new("Flaky::strict_get".to_string(), |_| {
+        Err(Error::HandlerInvalidated)
+    }))
+    .await?;
+
+    // Only the configured errors trigger the fallback
+    let flaky = hub.get_func("fallback_flaky").await?;
+    let result: Result<(i32, Result<i32>)> = flaky.call_with_codec(&JsonCodec, (4,)).await?;
+    assert_eq!(result, Ok((40, Err(Error::HandlerInvalidated))));
+
+    Ok(())
+}
+
+#[tokio::test]
+async fn broadcast_test() -> Result<()> {
+    let mut hub = LocalHub::new();
+
+    hub.add_provider(reloadable::ReloadableWrapper::new(
+        reloadable::Reloadable::default(),
+        JsonCodec,
+    ))
+    .await?;
+    hub.add_plugin(reloader::ReloaderWrapper::new(
+        reloader::Reloader::default(),
+        JsonCodec,
+    ))
+    .await?;
+
+    // Providers registered after the consumer are added to its broadcast extern
+    hub.add_provider(SingleProvider::new("on_reload".to_string(), |_| {
+        Err(Error::HandlerInvalidated)
+    }))
+    .await?;
+
+    let reload_all = hub.get_func("reload_all").await?;
+    let result: Result<Vec<Result<usize>>> = reload_all.call_with_codec(&JsonCodec, ()).await?;
+    assert_eq!(result, Ok(vec![Ok(1), Err(Error::HandlerInvalidated)]));
+
+    let broadcast = hub.get_broadcast("on_reload").await?;
+    assert_eq!(broadcast.len(), 2);
+
+    let results: Vec<Result<usize>> = broadcast.call_all_with_codec(&JsonCodec, ()).await?;
+    assert_eq!(results, vec![Ok(2), Err(Error::HandlerInvalidated)]);
+
+    assert!(hub.get_broadcast("on_shutdown").await?.is_empty());
+
+    Ok(())
+}
+
+#[tokio::test]
+async fn balanced_handle_test() -> Result<()> {
+    let mut hub = LocalHub::new();
+
+    hub.add_provider(SingleProvider::new("on_reload".to_string(), |_| {
+        Err(Error::HandlerInvalidated)
+    }))
+    .await?;
+    for _ in 0..2 {
+        hub.add_provider(reloadable::ReloadableWrapper::new(
+            reloadable::Reloadable::default(),
+            JsonCodec,
+        ))
+        .await?;
+    }
+
+    let balanced = hub
+        .get_balanced("on_reload", BalanceStrategy::RoundRobin)
+        .await?;
+    assert_eq!(balanced.len(), 3);
+
+    // The invalidated provider is dropped, the call that hit it isn't retried
+    let result: Result<usize> = balanced.call_with_codec(&JsonCodec, ()).await;
+    assert_eq!(result, Err(Error::HandlerInvalidated));
+
+    let mut results = Vec::new();
+    for _ in 0..3 {
+        let result: usize = balanced.call_with_codec(&JsonCodec, ()).await?;
+        results.push(result);
+    }
+    assert_eq!(results, vec![1, 1, 2]);
+    assert_eq!(balanced.len(), 2);
+
+    for strategy in [BalanceStrategy::LeastOutstanding, BalanceStrategy::Random] {
+        let balanced = hub.get_balanced("on_reload", strategy).await?;
+
+        // Only the invalidated provider fails, and only until it's out of rotation
+        let mut failures = 0;
+        for _ in 0..4 {
+            let result: Result<usize> = balanced.call_with_codec(&JsonCodec, ()).await;
+            match result {
+                Ok(_) => {}
+                Err(Error::HandlerInvalidated) => failures += 1,
+                Err(e) => return Err(e),
+            }
+        }
+        assert!(failures <= 1);
+    }
+
+    assert_eq!(
+        hub.get_balanced("on_shutdown", BalanceStrategy::RoundRobin)
+            .await
+            .err(),
+        Some(Error::FunctionNotFound("on_shutdown".to_string()))
+    );
+
+    Ok(())
+}
+
+#[tokio::test]
+async fn sharded_handle_test() -> Result<()> {
+    let mut hub = LocalHub::new();
+
+    hub.add_plugin(cache_client::CacheClientWrapper::new(
+        cache_client::CacheClient::default(),
+        JsonCodec,
+    ))
+    .await?;
+    for shard in 0..3 {
+        hub.add_provider(cache_shard::CacheShardWrapper::with_name(
+            cache_shard::CacheShard::new(shard),
+            JsonCodec,
+            format!("shard-{shard}"),
+        ))
+        .await?;
+    }
+
+    let sharded = hub
+        .get_sharded("Cache::get")
+        .await?
+        .with_key(Arc::new(JsonCodec), |(user_id,): &(u64,)| *user_id);
+    let lookup = hub.get_func("lookup").await?;
+
+    let mut owners = Vec::new();
+    for user_id in 0..64u64 {
+        let owner = sharded.shard_for(ShardKey::new(&user_id)).unwrap();
+
+        // The key is extracted from the arguments, or taken from the extern's shard_key argument
+        let (shard, _): (usize, u64) = sharded.call_with_codec(&JsonCodec, (user_id,)).await?;
+        assert_eq!(format!("shard-{shard}"), owner);
+        let result: Result<usize> = lookup.call_with_codec(&JsonCodec, (user_id,)).await?;
+        assert_eq!(
+            result.map(|shard| format!("shard-{shard}")),
+            Ok(owner.clone())
+        );
+
+        owners.push(owner);
+    }
+    assert!((0..3).all(|shard| owners.contains(&format!("shard-{shard}"))));
+
+    // Shards are identified by name, so the order they were added in doesn't matter
+    let mut reversed = LocalHub::new();
+    for shard in (0..3).rev() {
+        reversed
+            .add_provider(cache_shard::CacheShardWrapper::with_name(
+                cache_shard::CacheShard::new(shard),
+                JsonCodec,
+                format!("shard-{shard}"),
+            ))
+            .await?;
+    }
+    let reversed = reversed.get_sharded("Cache::get").await?;
+    for (user_id, owner) in owners.iter().enumerate() {
+        assert_eq!(
+            reversed
+                .shard_for(ShardKey::new(&(user_id as u64)))
+                .as_ref(),
+            Some(owner)
+        );
+    }
+
+    // A new shard only takes keys over from the existing ones
+    hub.add_provider(cache_shard::CacheShardWrapper::with_name(
+        cache_shard::CacheShard::new(3),
+        JsonCodec,
+        "shard-3",
+    ))
+    .await?;
+    for (user_id, owner) in owners.into_iter().enumerate() {
+        let result: Result<usize> = lookup
+            .call_with_codec(&JsonCodec, (user_id as u64,))
+            .await?;
+        let shard = format!("shard-{}", result?);
+        assert!(shard == owner || shard == "shard-3");
+    }
+
+    let unkeyed = hub.get_sharded("Cache::get").await?;
+    let result: Result<(usize, u64)> = unkeyed.call_with_codec(&JsonCodec, (0u64,)).await;
+    assert_eq!(
+        result,
+        Err(Error::ShardKeyMissing("Cache::get".to_string()))
+    );
+
+    Ok(())
+}
+
+#[tokio::test]
+async fn event_bus_test() -> Result<()> {
+    let mut hub = LocalHub::new();
+
+    let watcher = config_watcher::ConfigWatcherWrapper::new(
+        config_watcher::ConfigWatcher::default(),
+        JsonCodec,
+    );
+    hub.add_plugin(watcher.clone()).await?;
+    // Providers get the bus too, to publish on
+    hub.add_provider(config_store::ConfigStoreWrapper::new(
+        config_store::ConfigStore::default(),
+        JsonCodec,
+    ))
+    .await?;
+
+    assert_eq!(hub.events().topics(), vec!["config_changed".to_string()]);
+
+    let mut subscription = hub.events().subscribe("config_changed");
+
+    let set_config = hub.get_func("set_config").await?;
+    let result: Result<()> = set_config
+        .call_with_codec(&JsonCodec, ("answer".to_string(), 42))
+        .await?;
+    assert_eq!(result, Ok(()));
+
+    watcher.inner.notify.notified().await;
+    assert_eq!(
+        *watcher.inner.changes.lock().await,
+        vec![("answer".to_string(), 42)]
+    );
+
+    let payload: (String, i32) = JsonCodec.decode(subscription.recv().await?)?;
+    assert_eq!(payload, ("answer".to_string(), 42));
+
+    // Handler errors don't end the subscription, they're reported when shutting down
+    let result: Result<()> = set_config
+        .call_with_codec(&JsonCodec, ("answer".to_string(), -1))
+        .await?;
+    assert_eq!(result, Ok(()));
+    let result: Result<()> = set_config
+        .call_with_codec(&JsonCodec, ("answer".to_string(), 43))
+        .await?;
+    assert_eq!(result, Ok(()));
+
+    watcher.inner.notify.notified().await;
+    assert_eq!(watcher.inner.changes.lock().await.len(), 2);
+    // Plugins without mutable state are read through the same accessor
+    assert_eq!(watcher.state().await.changes.lock().await.len(), 2);
+
+    let report = hub.shutdown(Duration::from_secs(5)).await;
+    assert_eq!(
+        report.errors,
+        vec![Error::Plugin {
+            plugin: "ConfigWatcher".to_string(),
+            error: Box::new(Error::Custom("answer can't be negative".to_string())),
+        }]
+    );
+    assert!(report.unfinished.is_empty());
+
+    Ok(())
+}
+
+#[tokio::test]
+async fn event_bus_lag_test() -> Result<()> {
+    let hub = LocalHub::<JsonData>::new();
+    let events = hub.events();
+
+    assert_eq!(events.publish_with_codec(&JsonCodec, "tick", 0)?, 0);
+
+    // The capacity also applies to the existing subscriptions
+    let mut subscription = events.subscribe("tick");
+    let _hub = hub.with_event_capacity(1);
+    assert_eq!(events.capacity(), 1);
+    for tick in 1..=3 {
+        assert_eq!(events.publish_with_codec(&JsonCodec, "tick", tick)?, 1);
+    }
+
+    // The oldest events are skipped once the subscription falls behind
+    assert_eq!(
+        subscription.recv().await.err(),
+        Some(Error::EventsLagged {
+            topic: "tick".to_string(),
+            skipped: 2,
+        })
+    );
+    let tick: i32 = JsonCodec.decode(subscription.recv().await?)?;
+    assert_eq!(tick, 3);
+
+    Ok(())
+}
+
+#[tokio::test]
+async fn streaming_test() -> Result<()> {
+    let mut hub = LocalHub::new();
+
+    let lines = ["a", "b", "c"].map(String::from).to_vec();
+    let (cancelled_tx, cancelled_rx) = oneshot::channel();
+
+    hub.add_plugin(log_source::LogSourceWrapper::new(
+        log_source::LogSource::new(lines, cancelled_tx),
+        JsonCodec,
+    ))
+    .await?;
+    hub.add_plugin(log_reader::LogReaderWrapper::new(
+        log_reader::LogReader::default(),
+        JsonCodec,
+    ))
+    .await?;
+
+    let read_tail = hub.get_func("read_tail").await?;
+    let result: Result<Vec<String>> = read_tail.call_with_codec(&JsonCodec, (2,)).await?;
+    assert_eq!(result, Ok(vec!["b".to_string(), "c".to_string()]));
+
+    let tail = hub.get_func("tail").await?;
+    let result: Result<Vec<String>> = tail.call_with_codec(&JsonCodec, (2,)).await;
+    assert_eq!(result, Err(Error::Streaming("tail".to_string())));
+
+    // Dropping the stream cancels the producer, even while it waits for more items
+    let follow = hub.get_func("follow").await?;
+    let mut lines = follow
+        .call_stream_with_codec::<_, _, String>(Arc::new(JsonCodec), ())
+        .await?;
+    assert_eq!(lines.next().await, Some(Ok("a".to_string())));
+    drop(lines);
+    assert!(cancelled_rx.await.is_err());
+
+    let balanced = hub
+        .get_balanced("tail", BalanceStrategy::RoundRobin)
+        .await?;
+    let lines = balanced
+        .call_stream_with_codec::<_, _, String>(Arc::new(JsonCodec), (1,))
+        .await?;
+    assert_eq!(lines.collect::<Vec<_>>().await, vec![Ok("c".to_string())]);
+
+    let broadcast = hub.get_broadcast("tail").await?;
+    for lines in broadcast
+        .call_stream_all_with_codec::<_, _, String>(Arc::new(JsonCodec), (1,))
+        .await?
+    {
+        assert_eq!(lines?.collect::<Vec<_>>().await, vec![Ok("c".to_string())]);
+    }
+
+    Ok(())
+}
+
+#[tokio::test]
+async fn channel_test() -> Result<()> {
+    let mut hub = LocalHub::new();
+
+    hub.add_plugin(uploader::UploaderWrapper::new(
+        uploader::Uploader::default(),
+        JsonCodec,
+    ))
+    .await?;
+    hub.add_plugin(upload_client::UploadClientWrapper::new(
+        upload_client::UploadClient::default(),
+        JsonCodec,
+    ))
+    .await?;
+    hub.add_provider(adder::AdderWrapper::new(adder::Adder::default(), JsonCodec))
+        .await?;
+
+    let upload_lines = hub.get_func("upload_lines").await?;
+    let result: Result<(Vec<usize>, Vec<String>)> = upload_lines
+        .call_with_codec(&JsonCodec, (vec!["ab".to_string(), "cde".to_string()],))
+        .await?;
+    assert_eq!(
+        result,
+        Ok((vec![5], vec!["AB".to_string(), "CDE".to_string()]))
+    );
+
+    // Outputs arrive while the session is still open
+    let shout = hub.get_func("shout").await?;
+    let (tx, rx) = yaps_core::futures::channel::mpsc::unbounded();
+    let mut outputs = shout
+        .open_channel_with_codec::<_, String, String>(Arc::new(JsonCodec), rx.boxed())
+        .await?;
+    for line in ["hello", "world"] {
+        tx.unbounded_send(line.to_string()).unwrap();
+        assert_eq!(outputs.next().await, Some(Ok(line.to_uppercase())));
+    }
+    drop(tx);
+    assert_eq!(outputs.next().await, None);
+
+    let result: Result<String> = shout.call_with_codec(&JsonCodec, ()).await;
+    assert_eq!(result, Err(Error::Channel("shout".to_string())));
+
+    let add = hub.get_func("Adder::add").await?;
+    let result = add
+        .open_channel_with_codec::<_, i32, i32>(
+            Arc::new(JsonCodec),
+            yaps_core::futures::stream::empty().boxed(),
+        )
+        .await
+        .err();
+    assert_eq!(result, Some(Error::NotAChannel("Adder::add".to_string())));
+
+    // Channels can be opened through the handles spreading calls over several providers
+    hub.add_provider(uploader::UploaderWrapper::new(
+        uploader::Uploader::default(),
+        JsonCodec,
+    ))
+    .await?;
+    let lines = || yaps_core::futures::stream::iter(["a".to_string()]).boxed();
+
+    let balanced = hub
+        .get_balanced("shout", BalanceStrategy::RoundRobin)
+        .await?;
+    let outputs = balanced
+        .open_channel_with_codec::<_, String, String>(Arc::new(JsonCodec), lines())
+        .await?;
+    assert_eq!(outputs.collect::<Vec<_>>().await, vec![Ok("A".to_string())]);
+
+    let sharded = hub
+        .get_sharded("shout")
+        .await?
+        .with_key(Arc::new(JsonCodec), |line: &String| line.clone());
+    let outputs = sharded
+        .open_channel_with_codec::<_, String, String>(Arc::new(JsonCodec), lines())
+        .await?;
+    assert_eq!(outputs.collect::<Vec<_>>().await, vec![Ok("A".to_string())]);
+
+    let broadcast = hub.get_broadcast("shout").await?;
+    let channels = broadcast
+        .open_channel_all_with(|| {
+            let input = JsonCodec.encode("b".to_string())?;
+            Ok(yaps_core::futures::stream::iter([Ok(input)]).boxed())
+        })
+        .await?;
+    assert_eq!(channels.len(), 2);
+    for outputs in channels {
+        let outputs: Vec<Result<String>> = outputs?
+            .map(|output| JsonCodec.decode(output?))
+            .collect()
+            .await;
+        assert_eq!(outputs, vec![Ok("B".to_string())]);
+    }
+
+    Ok(())
+}
+
+#[tokio::test]
+async fn callback_test() -> Result<()> {
+    let mut hub = LocalHub::new();
+
+    hub.add_plugin(notifier::NotifierWrapper::new(
+        notifier::Notifier::default(),
+        JsonCodec,
+    ))
+    .await?;
+    let listener = listener::ListenerWrapper::new(listener::Listener::default(), JsonCodec);
+    hub.add_plugin(listener.clone()).await?;
+
+    let listen = hub.get_func("listen").await?;
+    let notify = hub.get_func("notify").await?;
+    let stop_listening = hub.get_func("stop_listening").await?;
+    let forget_listeners = hub.get_func("forget_listeners").await?;
+
+    let result: Result<()> = listen.call_with_codec(&JsonCodec, ()).await?;
+    assert_eq!(result, Ok(()));
+
+    let notified: usize = notify
+        .call_with_codec(&JsonCodec, ("hello".to_string(),))
+        .await?;
+    assert_eq!(notified, 1);
+    assert_eq!(
+        *listener.inner.messages.lock().await,
+        vec!["hello".to_string()]
+    );
+
+    // The listener dropping its callback ends it for the notifier
+    let alive: bool = stop_listening.call_with_codec(&JsonCodec, ()).await?;
+    assert!(alive);
+    let notified: usize = notify
+        .call_with_codec(&JsonCodec, ("ignored".to_string(),))
+        .await?;
+    assert_eq!(notified, 0);
+
+    // And the notifier dropping its copy ends it for the listener
+    let result: Result<()> = listen.call_with_codec(&JsonCodec, ()).await?;
+    assert_eq!(result, Ok(()));
+    let () = forget_listeners.call_with_codec(&JsonCodec, ()).await?;
+    let alive: bool = stop_listening.call_with_codec(&JsonCodec, ()).await?;
+    assert!(!alive);
+
+    // Only the tasks of registered plugins can create callbacks
+    let outside = Callback::<(String,), ()>::new(|_| Box::pin(async { Ok(()) }));
+    assert!(matches!(outside, Err(Error::CallbacksUnavailable)));
+
+    Ok(())
+}
+
+#[tokio::test]
+async fn lifecycle_hooks_test() -> Result<()> {
+    let log = Arc::new(std::sync::Mutex::new(Vec::new()));
+    let mut hub = LocalHub::new();
+
+    // Registered consumer first, the hooks still run in dependency order
+    hub.add_plugins([
+        service::ServiceWrapper::new(service::Service::new(log.clone(), false), JsonCodec)
+            as Arc<dyn Plugin<JsonData>>,
+        database::DatabaseWrapper::new(database::Database::new(log.clone()), JsonCodec),
+    ])
+    .await?;
+
+    assert_eq!(
+        *log.lock().unwrap(),
+        vec![
+            "database init",
+            "service init",
+            "database ready",
+            "service ready: value of config",
+        ]
+    );
+
+    // A failing hook aborts the registration, shutting down what was initialized
+    log.lock().unwrap().clear();
+    let mut hub = LocalHub::new();
+    let result = hub
+        .add_plugins([
+            service::ServiceWrapper::new(service::Service::new(log.clone(), true), JsonCodec)
+                as Arc<dyn Plugin<JsonData>>,
+            database::DatabaseWrapper::new(database::Database::new(log.clone()), JsonCodec),
+        ])
+        .await;
+
+    assert_eq!(
+        result,
+        Err(Error::Plugin {
+            plugin: "Service".to_string(),
+            error: Box::new(Error::PluginNotInitialized("config".to_string())),
+        })
+    );
+    assert_eq!(
+        *log.lock().unwrap(),
+        vec!["database init", "database shutdown"]
+    );
+    assert!(hub.introspect().plugins.is_empty());
+
+    // Consumers registered on their own run their hooks and are shut down as well
+    log.lock().unwrap().clear();
+    let mut hub = LocalHub::new();
+    hub.add_provider(database::DatabaseWrapper::new(
+        database::Database::new(log.clone()),
+        JsonCodec,
+    ))
+    .await?;
+    hub.add_consumer(service::ServiceWrapper::new(
+        service::Service::new(log.clone(), false),
+        JsonCodec,
+    ))
+    .await?;
+    let report = hub.shutdown(Duration::from_secs(1)).await;
+
+    assert!(report.is_clean());
+    assert_eq!(
+        *log.lock().unwrap(),
+        vec![
+            "database init",
+            "database ready",
+            "service init",
+            "service ready: value of config",
+            "service shutdown",
+            "database shutdown",
+        ]
+    );
+
+    Ok(())
+}
+
+#[tokio::test]
+async fn shutdown_test() -> Result<()> {
+    let log = Arc::new(std::sync::Mutex::new(Vec::new()));
+    let mut hub = LocalHub::new();
+
+    hub.add_plugins([
+        service::ServiceWrapper::new(service::Service::new(log.clone(), false), JsonCodec)
+            as Arc<dyn Plugin<JsonData>>,
+        database::DatabaseWrapper::new(database::Database::new(log.clone()), JsonCodec),
+    ])
+    .await?;
+    let (started, sleeping) = oneshot::channel();
+    hub.add_plugin(sleeper::SleeperWrapper::new(
+        sleeper::Sleeper::new(started),
+        JsonCodec,
+    ))
+    .await?;
+
+    let query = hub.get_func("query").await?;
+    let sleep = hub.get_func("sleep").await?;
+
+    // Calls in flight are drained before shutting down
+    let in_flight = yaps_core::tokio::spawn(async move {
+        sleep.call_with_codec::<_, _, u64>(&JsonCodec, (20,)).await
+    });
+    sleeping.await.unwrap();
+
+    log.lock().unwrap().clear();
+    let report = hub.shutdown(Duration::from_secs(5)).await;
+
+    assert!(report.is_clean(), "{report:?}");
+    assert_eq!(in_flight.await.unwrap(), Ok(20));
+    assert_eq!(
+        *log.lock().unwrap(),
+        vec!["service shutdown", "database shutdown"]
+    );
+
+    // New calls are refused
+    let result = query
+        .call_with_codec::<_, _, String>(&JsonCodec, ("config".to_string(),))
+        .await;
+    assert_eq!(result, Err(Error::ShutDown("query".to_string())));
+
+    // What doesn't finish in time is reported
+    let mut hub = LocalHub::new();
+    let (started, sleeping) = oneshot::channel();
+    hub.add_plugin(sleeper::SleeperWrapper::new(
+        sleeper::Sleeper::new(started),
+        JsonCodec,
+    ))
+    .await?;
+
+    let sleep = hub.get_func("sleep").await?;
+    let in_flight = yaps_core::tokio::spawn(async move {
+        sleep
+            .call_with_codec::<_, _, u64>(&JsonCodec, (10_000,))
+            .await
+    });
+    sleeping.await.unwrap();
+
+    let report = hub.shutdown(Duration::from_millis(20)).await;
+
+    assert_eq!(
+        report.unfinished,
+        vec![
+            Unfinished::Calls {
+                plugin: "Sleeper".to_string(),
+                in_flight: 1,
+            },
+            Unfinished::Task {
+                plugin: "Sleeper".to_string(),
+                task: "sleep".to_string(),
+            },
+        ]
+    );
+    assert!(report.errors.is_empty());
+
+    // The aborted actor can't answer anymore
+    assert_eq!(in_flight.await.unwrap(), Err(Error::HandlerInvalidated));
+
+    // Producers of streams still open are joined as well
+    let mut hub = LocalHub::new();
+    let (cancelled, on_cancel) = oneshot::channel();
+    hub.add_provider(log_source::LogSourceWrapper::new(
+        log_source::LogSource::new(vec!["first".to_string()], cancelled),
+        JsonCodec,
+    ))
+    .await?;
+
+    let follow = hub.get_func("follow").await?;
+    let mut lines = follow
+        .call_stream_with_codec::<_, _, String>(Arc::new(JsonCodec), ())
+        .await?;
+    assert_eq!(lines.next().await, Some(Ok("first".to_string())));
+
+    let report = hub.shutdown(Duration::from_millis(20)).await;
+
+    assert_eq!(
+        report.unfinished,
+        vec![
+            Unfinished::Calls {
+                plugin: "LogSource".to_string(),
+                in_flight: 1,
+            },
+            Unfinished::Task {
+                plugin: "LogSource".to_string(),
+                task: "follow producer".to_string(),
+            },
+        ]
+    );
+    // Aborting the producer dropped the stream it was forwarding
+    assert!(on_cancel.await.is_err());
+
+    Ok(())
+}
+
+#[tokio::test]
+async fn mutable_export_test() -> Result<()> {
+    let mut hub = LocalHub::new();
+    let counter = counter::CounterWrapper::new(counter::Counter::default(), JsonCodec);
+    hub.add_plugin(counter.clone()).await?;
+
+    let increment = Arc::new(hub.get_func("increment").await?);
+    let count = hub.get_func("count").await?;
+
+    let increments = (1..=10).map(|by| {
+        let increment = increment.clone();
+        yaps_core::tokio::spawn(async move {
+            increment
+                .call_with_codec::<_, _, u64>(&JsonCodec, (by,))
+                .await
+        })
+    });
+    for result in yaps_core::futures::future::join_all(increments).await {
+        result.unwrap()?;
+    }
+
+    let total: u64 = count.call_with_codec(&JsonCodec, ()).await?;
+    assert_eq!(total, 55);
+
+    let reset = hub.get_func("reset").await?;
+    let previous: u64 = reset.call_with_codec(&JsonCodec, ()).await?;
+    assert_eq!(previous, 55);
+
+    let state = counter.state().await;
+    assert_eq!((state.count, state.calls), (0, 11));
+
+    Ok(())
+}
+
+#[tokio::test]
+async fn blocking_export_test() -> Result<()> {
+    use yaps_core::futures::future::{Either, join_all, select};
+    use yaps_core::tokio::time::{Instant, sleep};
+
+    let mut hub = LocalHub::new();
+    let cruncher = cruncher::CruncherWrapper::new(cruncher::Cruncher::default(), JsonCodec);
+    hub.add_plugin(cruncher.clone()).await?;
+
+    // The single worker thread of the test runtime keeps running while the export blocks
+    let crunch = hub.get_func("crunch").await?;
+    let crunching = crunch.call_with_codec::<_, _, u64>(&JsonCodec, (100,));
+    match select(crunching, Box::pin(sleep(Duration::from_millis(10)))).await {
+        Either::Left(_) => panic!("the worker was blocked by the export"),
+        Either::Right((_, crunching)) => assert_eq!(crunching.await, Ok(100)),
+    }
+
+    // Calls to the same handle run concurrently on the plugin's two threads, or they would give
+    // up waiting for each other
+    let meet = hub.get_func("meet").await?;
+    let met = join_all((0..2).map(|_| meet.call_with_codec::<_, _, bool>(&JsonCodec, ()))).await;
+    assert_eq!(met, [Ok(true), Ok(true)]);
+
+    // No more than two of them run at a time
+    let start = Instant::now();
+    let results =
+        join_all((0..4).map(|_| crunch.call_with_codec::<_, _, u64>(&JsonCodec, (50,)))).await;
+    assert!(start.elapsed() >= Duration::from_millis(100));
+    assert!(results.into_iter().all(|result| result == Ok(50)));
+
+    let record = hub.get_func("record").await?;
+    let crunched: u64 = record.call_with_codec(&JsonCodec, (5,)).await?;
+    assert_eq!(crunched, 5);
+    assert_eq!(cruncher.state().await.crunched, 5);
+
+    Ok(())
+}
+
+#[test]
+fn blocking_call_test() -> Result<()> {
+    let mut hub = LocalHub::new();
+    let runtime = hub.runtime()?;
+    runtime
+        .block_on(hub.add_plugin(adder::AdderWrapper::new(adder::Adder::default(), JsonCodec)))?;
+
+    let add = hub.get_func_blocking("Adder::add")?;
+    let sum: i32 = add.call_blocking_with_codec(&JsonCodec, (1, 2))?;
+    assert_eq!(sum, 3);
+
+    // Blocking on a call from async code could deadlock the runtime
+    let result =
+        runtime.block_on(async { add.call_blocking_with_codec::<_, _, i32>(&JsonCodec, (1, 2)) });
+    assert_eq!(result, Err(Error::InsideRuntime));
+
+    let result = runtime.block_on(async { hub.get_func_blocking("Adder::add").map(|_| ()) });
+    assert_eq!(result, Err(Error::InsideRuntime));
+
+    // The shared hub keeps the runtime and offers the same facade
+    let hub = hub.into_shared();
+    hub.runtime()?
+        .block_on(hub.add_plugin(multiplier::MultiplierWrapper::new(
+            multiplier::Multiplier::default(),
+            JsonCodec,
+        )))?;
+
+    let mult = hub.get_func_blocking("mult")?;
+    let product: Result<i32> = mult.call_blocking_with_codec(&JsonCodec, (3, 4))?;
+    assert_eq!(product, Ok(12));
+    assert_eq!(add.call_blocking_with_codec(&JsonCodec, (2, 2)), Ok(4));
+
+    let result = runtime.block_on(async { hub.get_func_blocking("mult").map(|_| ()) });
+    assert_eq!(result, Err(Error::InsideRuntime));
+
+    Ok(())
+}
+
+#[tokio::test]
+async fn spawner_test() -> Result<()> {
+    let hub_runtime =
+        RuntimeSpawner::new(RuntimeConfig::new("yaps-hub-test").with_worker_threads(1))?;
+    let mut hub = LocalHub::new().with_spawner(Arc::new(hub_runtime));
+    hub.add_plugin(thread_reporter::ThreadReporterWrapper::new(
+        thread_reporter::ThreadReporter::default(),
+        JsonCodec,
+    ))
+    .await?;
+
+    let thread_name = hub.get_func("thread_name").await?;
+    let name: Option<String> = thread_name.call_with_codec(&JsonCodec, ()).await?;
+    assert_eq!(name.as_deref(), Some("yaps-hub-test"));
+
+    // Blocking exports run on the blocking threads of the same runtime
+    let blocking_thread_name = hub.get_func("blocking_thread_name").await?;
+    let name: Option<String> = blocking_thread_name.call_with_codec(&JsonCodec, ()).await?;
+    assert_eq!(name.as_deref(), Some("yaps-hub-test"));
+
+    // A spawner given to the wrapper wins over the hub's
+    let plugin_runtime = RuntimeSpawner::new(RuntimeConfig::new("yaps-plugin-test"))?;
+    let spawner: Arc<dyn Spawner> = Arc::new(plugin_runtime.handle().clone());
+    let mut hub = LocalHub::new().with_spawner(Arc::new(RuntimeSpawner::new(RuntimeConfig::new(
+        "yaps-hub-test",
+    ))?));
+    hub.add_plugin(thread_reporter::ThreadReporterWrapper::with_spawner(
+        thread_reporter::ThreadReporter::default(),
+        JsonCodec,
+        spawner,
+    ))
+    .await?;
+
+    let thread_name = hub.get_func("thread_name").await?;
+    let name: Option<String> = thread_name.call_with_codec(&JsonCodec, ()).await?;
+    assert_eq!(name.as_deref(), Some("yaps-plugin-test"));
+
+    Ok(())
+}
+
+#[tokio::test]
+async fn plugin_runtime_test() -> Result<()> {
+    let mut hub = LocalHub::new()
+        .with_plugin_runtime("Hog", RuntimeConfig::new("yaps-hog").with_worker_threads(1));
+    hub.add_plugin(hog::HogWrapper::new(hog::Hog::default(), JsonCodec))
+        .await?;
+    hub.add_plugin(adder::AdderWrapper::new(adder::Adder::default(), JsonCodec))
+        .await?;
+
+    let hog = hub.get_func("hog").await?;
+    let hogging = yaps_core::tokio::spawn(async move {
+        hog.call_with_codec::<_, _, Option<String>>(&JsonCodec, (300,))
+            .await
+    });
+
+    // The test's single thread is still free while the hog blocks its own runtime
+    let add = hub.get_func("Adder::add").await?;
+    let sum: i32 = yaps_core::tokio::time::timeout(
+        Duration::from_millis(100),
+        add.call_with_codec(&JsonCodec, (1, 2)),
+    )
+    .await
+    .expect("the adder shouldn't wait for the hog")?;
+    assert_eq!(sum, 3);
+
+    let name = hogging.await.expect("the call shouldn't panic")?;
+    assert_eq!(name.as_deref(), Some("yaps-hog"));
+
+    // A plugin given a spawner of its own keeps it, no runtime is started for it
+    let plugin_runtime = RuntimeSpawner::new(RuntimeConfig::new("yaps-plugin-test"))?;
+    let mut hub = LocalHub::new().with_plugin_runtime(
+        "ThreadReporter",
+        RuntimeConfig::new("yaps-unused").with_worker_threads(1),
+    );
+    hub.add_plugin(thread_reporter::ThreadReporterWrapper::with_spawner(
+        thread_reporter::ThreadReporter::default(),
+        JsonCodec,
+        Arc::new(plugin_runtime),
+    ))
+    .await?;
+
+    let thread_name = hub.get_func("thread_name").await?;
+    let name: Option<String> = thread_name.call_with_codec(&JsonCodec, ()).await?;
+    assert_eq!(name.as_deref(), Some("yaps-plugin-test"));
+
+    Ok(())
+}
+
+#[tokio::test]
+async fn reentrancy_test() -> Result<()> {
+    let mut hub = LocalHub::new();
+    hub.add_plugins([
+        ping::PingWrapper::new(ping::Ping::default(), JsonCodec) as Arc<dyn Plugin<JsonData>>,
+        pong::PongWrapper::new(pong::Pong::default(), JsonCodec),
+    ])
+    .await?;
+
+    // Every consumer resolves its own handles, so going back and forth once is fine
+    let ping = hub.get_func("ping").await?;
+    let result: Result<u32> = ping.call_with_codec(&JsonCodec, (1,)).await?;
+    assert_eq!(result, Ok(2));
+
+    // ping(2) -> pong(1) -> ping(1) -> pong(0), through the pong handle still waiting on ping(1)
+    let result: Result<u32> = ping.call_with_codec(&JsonCodec, (2,)).await?;
+    assert_eq!(
+        result,
+        Err(Error::Reentrancy {
+            chain: ["ping", "pong", "ping", "pong"]
+                .map(str::to_string)
+                .to_vec(),
+        })
+    );
+
+    // The actors weren't left waiting on each other
+    let result: Result<u32> = ping.call_with_codec(&JsonCodec, (0,)).await?;
+    assert_eq!(result, Ok(0));
+
+    // Neither can be shut down before the other, which is reported
+    drop(ping);
+    let report = hub.shutdown(Duration::from_secs(5)).await;
+    assert!(report.is_clean(), "{report:?}");
+    assert_eq!(
+        report.cycles,
+        vec![vec!["Ping".to_string(), "Pong".to_string()]]
+    );
+
+    Ok(())
+}
+
+#[tokio::test]
+async fn locked_reentrancy_test() -> Result<()> {
+    let mut hub = LocalHub::new();
+    let ledger = ledger::LedgerWrapper::new(ledger::Ledger::default(), JsonCodec);
+    hub.add_plugins([
+        ledger.clone() as Arc<dyn Plugin<JsonData>>,
+        auditor::AuditorWrapper::new(auditor::Auditor::default(), JsonCodec),
+    ])
+    .await?;
+
+    // reconcile holds the ledger's lock, so coming back to record would wait for it forever
+    let reconcile = hub.get_func("reconcile").await?;
+    let result: Result<u64> = yaps_core::tokio::time::timeout(
+        Duration::from_secs(5),
+        reconcile.call_with_codec(&JsonCodec, ()),
+    )
+    .await
+    .expect("the call shouldn't wait for the lock")?;
+    assert_eq!(
+        result,
+        Err(Error::Reentrancy {
+            chain: ["reconcile", "audit", "record"]
+                .map(str::to_string)
+                .to_vec(),
+        })
+    );
+
+    // The lock was released
+    let record = hub.get_func("record").await?;
+    let entries: u64 = record.call_with_codec(&JsonCodec, ()).await?;
+    assert_eq!(entries, 1);
+    assert_eq!(ledger.state().await.entries, 1);
+
+    // Same with the write lock of an async &mut self export
+    let settle = hub.get_func("settle").await?;
+    let result: Result<u64> = yaps_core::tokio::time::timeout(
+        Duration::from_secs(5),
+        settle.call_with_codec(&JsonCodec, ()),
+    )
+    .await
+    .expect("the call shouldn't wait for the lock")?;
+    assert_eq!(
+        result,
+        Err(Error::Reentrancy {
+            chain: ["settle", "audit", "record"].map(str::to_string).to_vec(),
+        })
+    );
+
+    let deposit = hub.get_func("deposit").await?;
+    let entries: u64 = deposit.call_with_codec(&JsonCodec, (2,)).await?;
+    assert_eq!(entries, 3);
+    assert_eq!(ledger.state().await.entries, 3);
+
+    Ok(())
+}
+
+/// Calls each function from a thread outside the runtime, returning the name of the thread
+/// each of them ran on
+async fn thread_names_from_caller(
+    funcs: Vec<Box<dyn DynFuncHandle<JsonData>>>,
+) -> Result<Vec<Option<String>>> {
+    let (tx, rx) = oneshot::channel();
+    std::thread::Builder::new()
+        .name("yaps-caller".to_string())
+        .spawn(move || {
+            let names = yaps_core::futures::executor::block_on(async {
+                let mut names = Vec::new();
+                for func in funcs {
+                    names.push(func.call_with_codec(&JsonCodec, ()).await?);
+                }
+                Ok(names)
+            });
+            let _ = tx.send(names);
+        })
+        .expect("the caller thread should start");
+
+    rx.await.expect("the caller thread shouldn't panic")
+}
+
+#[tokio::test]
+async fn direct_call_test() -> Result<()> {
+    let mut hub = LocalHub::new();
+    hub.add_plugin(thread_reporter::ThreadReporterWrapper::new(
+        thread_reporter::ThreadReporter::default(),
+        JsonCodec,
+    ))
+    .await?;
+
+    let direct = hub.get_func("thread_name").await?;
+    let serial = hub.get_func("serial_thread_name").await?;
+
+    let names = thread_names_from_caller(vec![direct, serial]).await?;
+    assert_eq!(names[0].as_deref(), Some("yaps-caller"));
+    assert_eq!(names[1].as_deref(), Some("direct_call_test"));
+
+    // A plugin with a spawner runs every export there, the inline ones included
+    let spawner = RuntimeSpawner::new(RuntimeConfig::new("yaps-direct-test"))?;
+    let mut hub = LocalHub::new().with_spawner(Arc::new(spawner));
+    hub.add_plugin(thread_reporter::ThreadReporterWrapper::new(
+        thread_reporter::ThreadReporter::default(),
+        JsonCodec,
+    ))
+    .await?;
+
+    let direct = hub.get_func("thread_name").await?;
+    let names = thread_names_from_caller(vec![direct]).await?;
+    assert_eq!(names[0].as_deref(), Some("yaps-direct-test"));
+
+    Ok(())
+}
+
+#[tokio::test]
+async fn shared_handle_test() -> Result<()> {
+    let mut hub = LocalHub::new();
+    hub.add_plugin(adder::AdderWrapper::new(adder::Adder::default(), JsonCodec))
+        .await?;
+
+    // One resolved function handed to many tasks
+    let add = hub.get_shared_func("Adder::add").await?;
+    let tasks = (0..8).map(|i| {
+        let add = add.clone();
+        yaps_core::tokio::spawn(async move {
+            add.call_with_codec::<_, _, i32>(&JsonCodec, (i, i)).await
+        })
+    });
+    let sums: Vec<i32> = yaps_core::futures::future::join_all(tasks)
+        .await
+        .into_iter()
+        .map(|task| task.expect("the task shouldn't panic"))
+        .collect::<Result<_>>()?;
+    assert_eq!(sums, (0..8).map(|i| i * 2).collect::<Vec<_>>());
+
+    // Boxed handles still work and convert both ways
+    let add = SharedHandle::from(hub.get_func("Adder::add").await?).into_boxed();
+    let sum: i32 = add.call_with_codec(&JsonCodec, (1, 2)).await?;
+    assert_eq!(sum, 3);
+
+    Ok(())
+}
+
+#[tokio::test]
+async fn provider_index_test() -> Result<()> {
+    let mut hub = LocalHub::new();
+    hub.add_plugin(adder::AdderWrapper::new(adder::Adder::default(), JsonCodec))
+        .await?;
+    hub.add_provider(SingleProvider::new("Adder::add".to_string(), Ok))
+        .await?;
+
+    // Ids resolve to their providers in registration order
+    let add = hub.get_func("Adder::add").await?;
+    let sum: i32 = add.call_with_codec(&JsonCodec, (1, 2)).await?;
+    assert_eq!(sum, 3);
+    assert_eq!(hub.get_all_funcs("Adder::add").await?.len(), 2);
+    assert_eq!(hub.get_all_funcs("Subber::sub").await?.len(), 1);
+
+    assert!(matches!(
+        hub.get_func("Adder::mul").await,
+        Err(Error::FunctionNotFound(id)) if id == "Adder::mul"
+    ));
 
     Ok(())
 }
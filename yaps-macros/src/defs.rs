@@ -32,11 +32,19 @@ macro_rules! define_const_token_streams {
 define_const_token_streams! {
 
     Vec = { ::std::vec::Vec };
+    String = { ::std::string::String };
+    Option = { ::std::option::Option };
     Box = { ::std::boxed::Box };
     Arc = { ::std::sync::Arc };
     Weak = { ::std::sync::Weak };
-    OnceCell = { ::tokio::sync::OnceCell };
+    OnceCell = { ::yaps_core::tokio::sync::OnceCell };
+    OnceLock = { ::std::sync::OnceLock };
+    Deref = { ::std::ops::Deref };
+    RwLock = { ::yaps_core::tokio::sync::RwLock };
     async_trait = { ::yaps_core::async_trait::async_trait };
+    join_all = { ::yaps_core::futures::future::join_all };
+    BoxStream = { ::yaps_core::futures::stream::BoxStream };
+    StreamExt = { ::yaps_core::futures::StreamExt };
 
     Result = { ::yaps_core::Result };
     Error = { ::yaps_core::Error };
@@ -47,11 +55,29 @@ define_const_token_streams! {
 
     FuncProvider = { ::yaps_core::FuncProvider };
     FuncConsumer = { ::yaps_core::FuncConsumer };
-    FuncHandle = { ::yaps_core::FuncHandle };
+    DynFuncHandle = { ::yaps_core::DynFuncHandle };
+    DynFuncProvider = { ::yaps_core::DynFuncProvider };
+    SharedHandle = { ::yaps_core::shared_handle::SharedHandle };
     FuncMetadata = { ::yaps_core::FuncMetadata };
 
     ActorHandle = { ::yaps_core::actor_handle::ActorHandle };
+    DirectHandle = { ::yaps_core::direct_handle::DirectHandle };
+    LazyHandle = { ::yaps_core::lazy_handle::LazyHandle };
+    BroadcastHandle = { ::yaps_core::broadcast_handle::BroadcastHandle };
+    ShardedHandle = { ::yaps_core::sharded_handle::ShardedHandle };
+    ShardKey = { ::yaps_core::sharded_handle::ShardKey };
     AsyncResult = { ::yaps_core::actor_handle::AsyncResult };
+    StreamHandle = { ::yaps_core::stream_handle::StreamHandle };
+    ChannelHandle = { ::yaps_core::channel_handle::ChannelHandle };
+    EventBus = { ::yaps_core::event_bus::EventBus };
+    BlockingPool = { ::yaps_core::blocking::BlockingPool };
+    PooledHandle = { ::yaps_core::blocking::PooledHandle };
+    TaskTracker = { ::yaps_core::shutdown::TaskTracker };
+    TrackedHandle = { ::yaps_core::shutdown::TrackedHandle };
+    Spawner = { ::yaps_core::spawner::Spawner };
+    SpawnerCell = { ::yaps_core::spawner::SpawnerCell };
+    CallbackTable = { ::yaps_core::callback::CallbackTable };
+    CallbackScope = { ::yaps_core::callback::CallbackScope };
 
     YapsData = { ::yaps_core::YapsData };
 
@@ -3,7 +3,8 @@ use proc_macro_error::abort;
 use proc_macro2::TokenStream;
 use quote::{ToTokens, quote};
 use syn::{
-    Attribute, FnArg, Ident, Meta, Pat, Signature, Token, Type, parse_quote, punctuated::Punctuated,
+    Attribute, FnArg, GenericArgument, Ident, Meta, Pat, PathArguments, Signature, Token, Type,
+    TypeParamBound, parse_quote, punctuated::Punctuated,
 };
 
 #[derive(Debug, Clone)]
@@ -86,3 +87,73 @@ pub fn parse_darling_attr<A: FromMeta + Default>(attr: &Attribute) -> A {
         _ => abort!(attr, "Invalid attribute usage"),
     }
 }
+
+/// Extracts `T` from `impl Stream<Item = T>`
+pub fn stream_item_type(ty: &Type) -> Option<&Type> {
+    let Type::ImplTrait(impl_trait) = ty else {
+        return None;
+    };
+
+    impl_trait.bounds.iter().find_map(|bound| {
+        let TypeParamBound::Trait(bound) = bound else {
+            return None;
+        };
+
+        let segment = bound.path.segments.last()?;
+        if segment.ident != "Stream" {
+            return None;
+        }
+
+        let PathArguments::AngleBracketed(args) = &segment.arguments else {
+            return None;
+        };
+
+        args.args.iter().find_map(|arg| match arg {
+            GenericArgument::AssocType(assoc) if assoc.ident == "Item" => Some(&assoc.ty),
+            _ => None,
+        })
+    })
+}
+
+/// Returns the first type argument of `ty` if its last path segment is `ident`, e.g. `T` from `Vec<T>`
+pub fn first_generic<'a>(ty: &'a Type, ident: &str) -> Option<&'a Type> {
+    let Type::Path(path) = ty else {
+        return None;
+    };
+
+    let segment = path.path.segments.last()?;
+    if segment.ident != ident {
+        return None;
+    }
+
+    let PathArguments::AngleBracketed(args) = &segment.arguments else {
+        return None;
+    };
+
+    args.args.iter().find_map(|arg| match arg {
+        GenericArgument::Type(ty) => Some(ty),
+        _ => None,
+    })
+}
+
+/// Extracts `T` from `Result<T>`
+pub fn result_item_type(ty: &Type) -> Option<Type> {
+    first_generic(ty, "Result").cloned()
+}
+
+/// Finds the item type of the `impl Stream<Item = T>` argument of a channel function
+pub fn channel_input_type(sig: &Signature) -> Option<&Type> {
+    let input = sig.inputs.iter().find_map(|input| match input {
+        FnArg::Typed(t) => stream_item_type(&t.ty),
+        _ => None,
+    })?;
+
+    if FunctionArgs::from(sig).0.len() != 1 {
+        abort!(
+            sig.inputs,
+            "Channel functions take the input stream as their only argument"
+        );
+    }
+
+    Some(input)
+}
@@ -1,13 +1,23 @@
+use proc_macro2::TokenStream;
 use quote::quote;
-use syn::{Arm, Expr, ItemImpl, LitStr, parse_quote};
+use syn::{Arm, Expr, Ident, ItemImpl, LitStr, parse_quote};
 
-use super::wrapper::{extern_field_name, generate_codec_export_bounds};
+use super::wrapper::{
+    borrow_inner, borrow_inner_blocking, extern_field_name, generate_codec_event_bounds,
+    generate_codec_export_bounds, inner_field, lock_state,
+};
 use crate::{defs::*, utils};
 
-use super::{yaps_export::ExportFunc, yaps_extern::ExternFunc, yaps_plugin_macro::YapsPluginInfo};
+use super::{
+    yaps_event::SubscribeFunc,
+    yaps_export::ExportFunc,
+    yaps_extern::ExternFunc,
+    yaps_lifecycle::{Hook, LifecycleFunc},
+    yaps_plugin_macro::YapsPluginInfo,
+};
 
-fn generate_func_metadata(export_func: &ExportFunc) -> Expr {
-    let id_str = LitStr::new(&export_func.id, export_func.ident.span());
+fn generate_func_metadata(id: &str, ident: &Ident) -> Expr {
+    let id_str = LitStr::new(id, ident.span());
 
     parse_quote! {
         #FuncMetadata {
@@ -16,10 +26,94 @@ fn generate_func_metadata(export_func: &ExportFunc) -> Expr {
     }
 }
 
-fn generate_provider_match_arm(export_func: &ExportFunc) -> Arm {
+/// Sync exports run inline on the caller's task, unless they're serial or the plugin has
+/// mutable state, which would have to be locked from the caller's task.
+///
+/// The arm is skipped at lookup when the plugin has a spawner, given to its wrapper or by the
+/// hub, so all its code runs where the spawner puts it. Its calls then go through the actor.
+fn generate_direct_match_arm(info: &YapsPluginInfo, export_func: &ExportFunc) -> Option<Arm> {
+    if export_func.is_async
+        || export_func.is_blocking
+        || export_func.is_serial
+        || export_func.is_stream
+        || export_func.channel_input.is_some()
+        || info.has_mutable_state()
+    {
+        return None;
+    }
+
     let ident = &export_func.ident;
     let id_str = LitStr::new(&export_func.id, export_func.ident.span());
 
+    let arg_types = utils::punctuated_into_tuple(export_func.args.to_types());
+    let arg_idents_tuple = utils::punctuated_into_tuple(export_func.args.to_idents());
+    let arg_idents = export_func.args.to_idents();
+    let ret_type = &export_func.ret_ty;
+
+    // Decoded in the plugin's scope as well, so callbacks can be received
+    Some(parse_quote! {
+        #id_str if !self.spawner.is_set() => {
+            let codec = self.codec.clone();
+            let scope = self.callback_scope();
+            let handle = #DirectHandle::new(move |args: D| -> #Result<D> {
+                scope.clone().sync_scope(|| {
+                    let inner = inner.upgrade().ok_or(#Error::HandlerInvalidated)?;
+
+                    let #arg_idents_tuple: #arg_types = codec.decode(args)?;
+                    let result: #ret_type = inner.#ident(#arg_idents);
+                    codec.encode(result)
+                })
+            });
+            Ok(#Box::new(#TrackedHandle::new(#id_str, self.tasks.clone(), handle)))
+        }
+    })
+}
+
+/// Blocking exports run on the plugin's pool from the caller's task, so calls to the same
+/// export run concurrently up to the pool's number of threads, on its spawner's blocking threads
+fn generate_blocking_match_arm(info: &YapsPluginInfo, export_func: &ExportFunc) -> Arm {
+    let ident = &export_func.ident;
+    let inner = borrow_inner_blocking(info, quote! { inner }, export_func.is_mut);
+    let lock_state = lock_state(info);
+    let id_str = LitStr::new(&export_func.id, export_func.ident.span());
+
+    let arg_types = utils::punctuated_into_tuple(export_func.args.to_types());
+    let arg_idents_tuple = utils::punctuated_into_tuple(export_func.args.to_idents());
+    let arg_idents = export_func.args.to_idents();
+    let ret_type = &export_func.ret_ty;
+
+    // The scoped spawner runs the calls in the plugin's scope, so callbacks can be received
+    parse_quote! {
+        #id_str => {
+            let codec = self.codec.clone();
+            let handle = #PooledHandle::new(
+                #id_str,
+                self.blocking.clone(),
+                self.callback_scope().spawner(),
+                move |args: D| -> #Result<D> {
+                    let inner = inner.upgrade().ok_or(#Error::HandlerInvalidated)?;
+
+                    let #arg_idents_tuple: #arg_types = codec.decode(args)?;
+                    let result: #ret_type = #inner.#ident(#arg_idents);
+                    codec.encode(result)
+                },
+            )
+            #lock_state;
+            Ok(#Box::new(#TrackedHandle::new(#id_str, self.tasks.clone(), handle)))
+        }
+    }
+}
+
+fn generate_provider_match_arm(info: &YapsPluginInfo, export_func: &ExportFunc) -> Arm {
+    if export_func.is_blocking {
+        return generate_blocking_match_arm(info, export_func);
+    }
+
+    let ident = &export_func.ident;
+    let inner = borrow_inner(info, quote! { inner }, export_func.is_mut);
+    let lock_state = lock_state(info);
+    let id_str = LitStr::new(&export_func.id, export_func.ident.span());
+
     let arg_types = utils::punctuated_into_tuple(export_func.args.to_types());
     let arg_idents_tuple = utils::punctuated_into_tuple(export_func.args.to_idents());
     let arg_idents = export_func.args.to_idents();
@@ -32,52 +126,201 @@ fn generate_provider_match_arm(export_func: &ExportFunc) -> Arm {
         quote! {}
     };
 
+    if let Some(input_ty) = &export_func.channel_input {
+        // Client-streaming exports return a single output
+        let forward = if export_func.is_stream {
+            quote! { sink.forward_with_codec(codec.as_ref(), output).await; }
+        } else {
+            quote! { sink.send(codec.encode(output)).await; }
+        };
+
+        return parse_quote! {
+            #id_str => {
+                let codec = self.codec.clone();
+                let handle = #ChannelHandle::new(#id_str, self.callback_scope().spawner(), move |inputs, sink| -> #AsyncResult<()> {
+                    let inner = inner.clone();
+                    let codec = codec.clone();
+                    #Box::pin(async move {
+                        let inner = inner.upgrade().ok_or(#Error::HandlerInvalidated)?;
+
+                        let input_codec = codec.clone();
+                        let #arg_idents = #StreamExt::map(
+                            inputs,
+                            move |input| -> #Result<#input_ty> { input_codec.decode(input?) },
+                        );
+                        let output = #inner.#ident(#arg_idents) #await_call;
+                        #forward
+                        Ok(())
+                    })
+                })
+                .with_tracker(self.tasks.clone());
+                Ok(#Box::new(#TrackedHandle::new(#id_str, self.tasks.clone(), handle)))
+            }
+        };
+    }
+
+    if export_func.is_stream {
+        return parse_quote! {
+            #id_str => {
+                let codec = self.codec.clone();
+                let handle = #StreamHandle::new(#id_str, self.callback_scope().spawner(), move |args, sink| -> #AsyncResult<()> {
+                    let inner = inner.clone();
+                    let codec = codec.clone();
+                    #Box::pin(async move {
+                        let inner = inner.upgrade().ok_or(#Error::HandlerInvalidated)?;
+
+                        let #arg_idents_tuple: #arg_types = codec.decode(args)?;
+                        let stream = #inner.#ident(#arg_idents) #await_call;
+                        sink.forward_with_codec(codec.as_ref(), stream).await;
+                        Ok(())
+                    })
+                })
+                .with_tracker(self.tasks.clone());
+                Ok(#Box::new(#TrackedHandle::new(#id_str, self.tasks.clone(), handle)))
+            }
+        };
+    }
+
     parse_quote! {
         #id_str => {
-            // TODO: handle the join handle
-            let (handle, _) = #ActorHandle::spawn_with_codec(
-                move |args| -> #AsyncResult<#ret_type> {
+            let (handle, task) = #ActorHandle::spawn_with_codec_on(
+                move |args| {
                     let inner = inner.clone();
-                    #Box::pin(async move {
+                    async move {
                         let inner = inner.upgrade().ok_or(#Error::HandlerInvalidated)?;
 
                         // This will change in the macro
                         let #arg_idents_tuple: #arg_types = args;
-                        Ok(inner.#ident(#arg_idents) #await_call)
-                    })
+                        #Result::<#ret_type>::Ok(#inner.#ident(#arg_idents) #await_call)
+                    }
                 },
                 self.codec.clone(),
+                self.callback_scope().spawner().as_ref(),
+                self.tasks.closed(),
             )?;
-            Ok(#Box::new(handle))
+            let handle = handle.with_name(#id_str) #lock_state;
+            self.tasks.track(#id_str, task);
+            Ok(#Box::new(#TrackedHandle::new(#id_str, self.tasks.clone(), handle)))
         }
     }
 }
 
+fn generate_hook_call(info: &YapsPluginInfo, lifecycle_func: &LifecycleFunc) -> TokenStream {
+    let ident = &lifecycle_func.ident;
+    let inner_field = inner_field(info);
+    let inner = borrow_inner(info, quote! { self.#inner_field }, false);
+
+    let await_call = if lifecycle_func.is_async {
+        quote! { .await }
+    } else {
+        quote! {}
+    };
+
+    let check_result = if lifecycle_func.fallible {
+        quote! { ? }
+    } else {
+        quote! {}
+    };
+
+    quote! { #inner.#ident() #await_call #check_result; }
+}
+
+/// Overrides the hook's default implementation if the plugin declared any function for it
+fn generate_hook_impl(info: &YapsPluginInfo, hook: Hook) -> Option<TokenStream> {
+    let calls: Vec<_> = info
+        .lifecycle_funcs
+        .iter()
+        .filter(|func| func.hook == hook)
+        .map(|func| generate_hook_call(info, func))
+        .collect();
+
+    if calls.is_empty() {
+        return None;
+    }
+
+    let method = match hook {
+        Hook::Init => quote! { on_init },
+        Hook::Ready => quote! { on_ready },
+        Hook::Shutdown => quote! { on_shutdown },
+    };
+
+    Some(quote! {
+        async fn #method(&self) -> #Result<()> {
+            self.callback_scope()
+                .scope(async {
+                    #( #calls )*
+                    Ok(())
+                })
+                .await
+        }
+    })
+}
+
 pub(crate) fn generate_provider_impl(info: &YapsPluginInfo) -> ItemImpl {
     let codec_export_bounds = generate_codec_export_bounds(info);
+    let codec_event_bounds = generate_codec_event_bounds(info);
+    let attach_events = generate_attach_events(info);
     let wrapper_ident = &info.wrapper_ident;
+    let inner_field = inner_field(info);
 
-    let func_metadatas = info.export_funcs.iter().map(generate_func_metadata);
-    let func_arms = info.export_funcs.iter().map(generate_provider_match_arm);
+    let plugin_str = LitStr::new(&info.plugin_name, info.struct_ident.span());
+    let func_metadatas = info
+        .export_funcs
+        .iter()
+        .map(|func| generate_func_metadata(&func.id, &func.ident));
+    let func_arms = info.export_funcs.iter().flat_map(|func| {
+        let direct_arm = generate_direct_match_arm(info, func);
+        direct_arm
+            .into_iter()
+            .chain([generate_provider_match_arm(info, func)])
+    });
+    let hook_impls = [Hook::Init, Hook::Ready, Hook::Shutdown]
+        .into_iter()
+        .filter_map(|hook| generate_hook_impl(info, hook));
 
     parse_quote! {
-        #[#async_trait]
         impl<D, C> #FuncProvider<D> for #wrapper_ident<D, C>
         where
             D: #YapsData,
-            C: #Codec<Data = D> #codec_export_bounds + 'static,
+            C: #Codec<Data = D> #codec_export_bounds #codec_event_bounds + 'static,
         {
+            fn name(&self) -> #String {
+                self.name.get().map_or(#plugin_str, |name| name.as_str()).to_string()
+            }
+
             async fn provided_funcs(&self) -> #Result<#Vec<#FuncMetadata>> {
                 Ok(#Vec::from([ #( #func_metadatas ),* ]))
             }
 
-            async fn get_func(&self, id: &str) -> #Result<#Box<dyn #FuncHandle<D>>> {
-                let inner = #Arc::downgrade(&self.inner);
+            async fn get_func(&self, id: &str) -> #Result<#Box<dyn #DynFuncHandle<D>>> {
+                let inner = #Arc::downgrade(&self.#inner_field);
                 match id {
                     #( #func_arms, )*
                     _ => Err(#Error::FunctionNotFound(id.to_string())),
                 }
             }
+
+            #( #hook_impls )*
+
+            fn task_tracker(&self) -> #Option<#Arc<#TaskTracker>> {
+                Some(self.tasks.clone())
+            }
+
+            fn attach_spawner(&self, spawner: &#Arc<dyn #Spawner>) {
+                self.spawner.set(spawner.clone());
+            }
+
+            fn has_spawner(&self) -> bool {
+                self.spawner.is_set()
+            }
+
+            fn attach_events(&self, events: &#Arc<#EventBus<D>>) {
+                #attach_events
+            }
+
+            fn attach_callbacks(&self, callbacks: &#Arc<#CallbackTable>) {
+                let _ = self.callbacks.set(callbacks.clone());
+            }
         }
     }
 }
@@ -86,9 +329,39 @@ fn generate_consumer_match_arm(extern_func: &ExternFunc) -> Arm {
     let id_str = LitStr::new(&extern_func.id, extern_func.ident.span());
     let extern_field = extern_field_name(&extern_func.ident);
 
+    if extern_func.broadcast {
+        return parse_quote! {
+            #id_str => {
+                let func_handles = provider.get_all_shared_funcs(#id_str).await?;
+                self.#extern_field.extend(func_handles);
+            }
+        };
+    }
+
+    if extern_func.shard_key.is_some() {
+        return parse_quote! {
+            #id_str => {
+                let func_handles = provider.get_all_named_funcs(#id_str).await?;
+                self.#extern_field.extend(func_handles);
+            }
+        };
+    }
+
+    if extern_func.lazy {
+        // Only resolved here if there's no provider to resolve it from later on
+        return parse_quote! {
+            #id_str => {
+                if !self.#extern_field.is_attached() {
+                    let func_handle = provider.get_shared_func(#id_str).await?;
+                    self.#extern_field.set(func_handle);
+                }
+            }
+        };
+    }
+
     parse_quote! {
         #id_str => {
-            let func_handle = provider.get_func(#id_str).await?;
+            let func_handle = provider.get_shared_func(#id_str).await?;
             match self.#extern_field.set(func_handle) {
                 Ok(()) => {}
                 Err(_) => {} // TODO: function set already, maybe log this
@@ -97,22 +370,160 @@ fn generate_consumer_match_arm(extern_func: &ExternFunc) -> Arm {
     }
 }
 
+fn generate_subscription(info: &YapsPluginInfo, subscribe_func: &SubscribeFunc) -> TokenStream {
+    let ident = &subscribe_func.ident;
+    let inner_field = inner_field(info);
+    let inner = borrow_inner(info, quote! { inner }, false);
+    let topic_str = LitStr::new(&subscribe_func.topic, subscribe_func.ident.span());
+    let task_str = LitStr::new(
+        &format!("subscription to {}", subscribe_func.topic),
+        subscribe_func.ident.span(),
+    );
+
+    let arg_types = utils::punctuated_into_tuple(subscribe_func.args.to_types());
+    let arg_idents_tuple = utils::punctuated_into_tuple(subscribe_func.args.to_idents());
+    let arg_idents = subscribe_func.args.to_idents();
+
+    let await_call = if subscribe_func.is_async {
+        quote! { .await }
+    } else {
+        quote! {}
+    };
+
+    // Errors of fallible handlers are reported when the subscription is joined
+    let call = if subscribe_func.fallible {
+        quote! { #inner.#ident(#arg_idents) #await_call .map(|_| ()) }
+    } else {
+        quote! {
+            #inner.#ident(#arg_idents) #await_call;
+            Ok(())
+        }
+    };
+
+    quote! {
+        {
+            let inner = #Arc::downgrade(&self.#inner_field);
+
+            let task = events.subscribe(#topic_str).spawn_with_codec_on(
+                move |args| -> #AsyncResult<()> {
+                    let inner = inner.clone();
+                    #Box::pin(async move {
+                        let inner = inner.upgrade().ok_or(#Error::HandlerInvalidated)?;
+
+                        let #arg_idents_tuple: #arg_types = args;
+                        #call
+                    })
+                },
+                self.codec.clone(),
+                self.callback_scope().spawner().as_ref(),
+                // Ends the subscription once the plugin is shut down
+                self.tasks.closed(),
+            );
+            self.tasks.track(#task_str, task);
+        }
+    }
+}
+
+/// Body of `attach_events`, generated for both the provider and the consumer
+fn generate_attach_events(info: &YapsPluginInfo) -> TokenStream {
+    let subscriptions = info
+        .subscribe_funcs
+        .iter()
+        .map(|func| generate_subscription(info, func));
+
+    quote! {
+        // Only the first bus is kept, subscribing again would deliver events twice
+        if self.events.set(events.clone()).is_err() {
+            return;
+        }
+
+        #( #subscriptions )*
+    }
+}
+
 pub(crate) fn generate_consumer_impl(info: &YapsPluginInfo) -> ItemImpl {
+    let codec_event_bounds = generate_codec_event_bounds(info);
     let extern_arms = info.extern_funcs.iter().map(generate_consumer_match_arm);
+    let attach_events = generate_attach_events(info);
     let wrapper_ident = &info.wrapper_ident;
 
+    let lazy_fields = info
+        .extern_funcs
+        .iter()
+        .filter(|func| func.lazy)
+        .map(|func| extern_field_name(&func.ident));
+
+    // Consumers registered on their own still run their hooks
+    let hook_impls = [Hook::Init, Hook::Ready, Hook::Shutdown]
+        .into_iter()
+        .filter_map(|hook| generate_hook_impl(info, hook));
+
+    let plugin_str = LitStr::new(&info.plugin_name, info.struct_ident.span());
+    let func_metadatas = info
+        .extern_funcs
+        .iter()
+        .map(|func| generate_func_metadata(&func.id, &func.ident));
+
     parse_quote! {
-        #[#async_trait]
-        impl<D: #YapsData, C: #Codec<Data = D>> #FuncConsumer<D> for #wrapper_ident<D, C> {
-            async fn connect(&self, provider: &dyn #FuncProvider<D>) -> #Result<()> {
-                for func in provider.provided_funcs().await? {
-                    match func.id.as_str() {
+        impl<D, C> #FuncConsumer<D> for #wrapper_ident<D, C>
+        where
+            D: #YapsData,
+            C: #Codec<Data = D> #codec_event_bounds + 'static,
+        {
+            fn name(&self) -> #String {
+                self.name.get().map_or(#plugin_str, |name| name.as_str()).to_string()
+            }
+
+            async fn consumed_funcs(&self) -> #Result<#Vec<#FuncMetadata>> {
+                Ok(#Vec::from([ #( #func_metadatas ),* ]))
+            }
+
+            fn attach(&self, provider: #Arc<dyn #DynFuncProvider<D>>) {
+                #( self.#lazy_fields.attach(provider.clone()); )*
+            }
+
+            #( #hook_impls )*
+
+            fn task_tracker(&self) -> #Option<#Arc<#TaskTracker>> {
+                Some(self.tasks.clone())
+            }
+
+            fn attach_spawner(&self, spawner: &#Arc<dyn #Spawner>) {
+                self.spawner.set(spawner.clone());
+            }
+
+            fn has_spawner(&self) -> bool {
+                self.spawner.is_set()
+            }
+
+            fn attach_events(&self, events: &#Arc<#EventBus<D>>) {
+                #attach_events
+            }
+
+            fn attach_callbacks(&self, callbacks: &#Arc<#CallbackTable>) {
+                let _ = self.callbacks.set(callbacks.clone());
+            }
+
+            async fn connect(&self, provider: &dyn #DynFuncProvider<D>) -> #Result<()> {
+                let funcs = provider.provided_funcs().await?;
+
+                // Hubs list an id once for each plugin providing it
+                let mut ids: #Vec<&str> = funcs.iter().map(|func| func.id.as_str()).collect();
+                ids.sort_unstable();
+                ids.dedup();
+
+                // Every extern is resolved concurrently, failures are reported together
+                let results = #join_all(ids.into_iter().map(|id| async move {
+                    match id {
                         #( #extern_arms, )*
-                        _ => continue,
+                        _ => {}
                     }
-                }
 
-                Ok(())
+                    #Result::<()>::Ok(())
+                }))
+                .await;
+
+                #Error::aggregate(results.into_iter().filter_map(#Result::err))
             }
         }
     }
@@ -1,17 +1,46 @@
+use proc_macro2::TokenStream;
+use quote::quote;
 use syn::{ImplItemFn, ItemImpl, ItemTrait, LitStr, parse_quote};
 
 use crate::defs::*;
 
-use super::{yaps_extern::ExternFunc, yaps_plugin_macro::YapsPluginInfo};
+use super::{yaps_event::PublishFunc, yaps_extern::ExternFunc, yaps_plugin_macro::YapsPluginInfo};
+
+/// Explains how the signature of an extern declared with `impl Stream` was rewritten
+fn stream_doc(func: &ExternFunc) -> TokenStream {
+    let doc = match func.channel_input {
+        Some(_) => {
+            "Declared with `impl Stream`, the inputs are taken and the outputs returned as \
+             `BoxStream<'static, _>` so the extern can be called through a trait object."
+        }
+        None if func.stream => {
+            "Declared as returning `impl Stream`, the outputs are returned as \
+             `BoxStream<'static, _>` so the extern can be called through a trait object."
+        }
+        None => return quote! {},
+    };
+
+    quote! { #[doc = #doc] }
+}
 
 pub(crate) fn generate_extern_trait(info: &YapsPluginInfo) -> ItemTrait {
+    let vis = &info.struct_vis;
     let ident = &info.extern_funcs_trait;
-    let trait_items = info.extern_funcs.iter().map(|func| &func.sig);
+    let trait_items = info.extern_funcs.iter().map(|func| {
+        let doc = stream_doc(func);
+        let sig = &func.sig;
+        quote! { #doc #sig }
+    });
+    let publish_items = info.publish_funcs.iter().map(|func| &func.sig);
 
+    // Boxed since plugins keep their wrapper as a `dyn` of this trait
     parse_quote! {
         #[#async_trait]
-        trait #ident: Send + Sync {
+        #vis trait #ident: Send + Sync {
             #( #trait_items; )*
+            #( #publish_items; )*
+
+            async fn has_extern(&self, id: &str) -> bool;
         }
     }
 }
@@ -22,21 +51,44 @@ fn generate_extern_trait_inner_fn(func: &ExternFunc, info: &YapsPluginInfo) -> I
     let args = &func.args.to_idents();
 
     let plugin_str = LitStr::new(&info.plugin_name, info.struct_ident.span());
+    let doc = stream_doc(func);
 
     parse_quote! {
+        #doc
         pub #sig {
             let extern_funcs = self
                 .extern_funcs
                 .get()
-                .ok_or(#Error::PluginNotInitialized(#plugin_str.to_string()))?
+                .ok_or_else(|| #Error::PluginNotInitialized(#plugin_str.to_string()))?
                 .upgrade()
-                .ok_or(#Error::PluginWrapperDropped(#plugin_str.to_string()))?;
+                .ok_or_else(|| #Error::PluginWrapperDropped(#plugin_str.to_string()))?;
 
             extern_funcs.#ident(#args).await
         }
     }
 }
 
+fn generate_publish_inner_fn(func: &PublishFunc, info: &YapsPluginInfo) -> ImplItemFn {
+    let sig = &func.sig;
+    let ident = &func.ident;
+    let args = &func.args.to_idents();
+
+    let plugin_str = LitStr::new(&info.plugin_name, info.struct_ident.span());
+
+    parse_quote! {
+        pub #sig {
+            let extern_funcs = self
+                .extern_funcs
+                .get()
+                .ok_or_else(|| #Error::PluginNotInitialized(#plugin_str.to_string()))?
+                .upgrade()
+                .ok_or_else(|| #Error::PluginWrapperDropped(#plugin_str.to_string()))?;
+
+            extern_funcs.#ident(#args)
+        }
+    }
+}
+
 pub(crate) fn generate_extern_funcs_inner_impl(info: &YapsPluginInfo) -> ItemImpl {
     let (impl_generics, ty_generics, where_generics) = info.struct_generics.split_for_impl();
     let ty_ident = &info.struct_ident;
@@ -45,10 +97,28 @@ pub(crate) fn generate_extern_funcs_inner_impl(info: &YapsPluginInfo) -> ItemImp
         .extern_funcs
         .iter()
         .map(|func| generate_extern_trait_inner_fn(func, info));
+    let publish_items = info
+        .publish_funcs
+        .iter()
+        .map(|func| generate_publish_inner_fn(func, info));
 
     parse_quote! {
         impl<#impl_generics> #ty_ident #ty_generics #where_generics {
             #( #items )*
+            #( #publish_items )*
+
+            /// Checks whether the extern with the given id is bound to a provider
+            pub async fn has_extern(&self, id: &str) -> bool {
+                let extern_funcs = self
+                    .extern_funcs
+                    .get()
+                    .and_then(|extern_funcs| extern_funcs.upgrade());
+
+                match extern_funcs {
+                    Some(extern_funcs) => extern_funcs.has_extern(id).await,
+                    None => false,
+                }
+            }
         }
     }
 }
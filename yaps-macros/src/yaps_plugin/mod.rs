@@ -3,8 +3,10 @@ mod yaps_plugin_macro;
 mod yaps_impl;
 mod yaps_struct;
 
+mod yaps_event;
 mod yaps_export;
 mod yaps_extern;
+mod yaps_lifecycle;
 
 mod extern_trait;
 mod wrapper;
@@ -1,8 +1,8 @@
 use proc_macro2::TokenStream;
-use quote::{format_ident, quote};
+use quote::{ToTokens, format_ident, quote};
 use syn::{Ident, ImplItemFn, ItemImpl, ItemStruct, LitStr, parse_quote};
 
-use super::{yaps_extern::ExternFunc, yaps_plugin_macro::YapsPluginInfo};
+use super::{yaps_event::PublishFunc, yaps_extern::ExternFunc, yaps_plugin_macro::YapsPluginInfo};
 use crate::{defs::*, utils};
 
 fn wrapper_name(struct_name: &Ident) -> Ident {
@@ -13,12 +13,102 @@ pub fn extern_field_name(func_name: &Ident) -> Ident {
     format_ident!("extern_{}", func_name)
 }
 
+fn extern_field_type(func: &ExternFunc) -> TokenStream {
+    if func.broadcast {
+        quote! { #BroadcastHandle<D> }
+    } else if func.shard_key.is_some() {
+        quote! { #ShardedHandle<D> }
+    } else if func.lazy {
+        quote! { #LazyHandle<D> }
+    } else {
+        quote! { #OnceCell<#SharedHandle<D>> }
+    }
+}
+
+fn extern_field_init(func: &ExternFunc) -> TokenStream {
+    let id_str = LitStr::new(&func.id, func.ident.span());
+
+    if func.broadcast {
+        quote! { #BroadcastHandle::new(#id_str) }
+    } else if func.shard_key.is_some() {
+        quote! { #ShardedHandle::new(#id_str) }
+    } else if func.lazy {
+        quote! { #LazyHandle::new(#id_str) }
+    } else {
+        quote! { #OnceCell::new() }
+    }
+}
+
+/// Field the wrapper keeps the plugin struct in.
+///
+/// Plugins with `&mut self` exports keep it behind a lock in the private `state` field instead of
+/// the public `inner` one, the wrapper's `state()` method reads it for both.
+pub(crate) fn inner_field(info: &YapsPluginInfo) -> Ident {
+    if info.has_mutable_state() {
+        format_ident!("state")
+    } else {
+        format_ident!("inner")
+    }
+}
+
+/// Expression borrowing the plugin struct from `inner`, locking it if it has mutable state
+pub(crate) fn borrow_inner(
+    info: &YapsPluginInfo,
+    inner: TokenStream,
+    mutable: bool,
+) -> TokenStream {
+    if !info.has_mutable_state() {
+        inner
+    } else if mutable {
+        quote! { #inner.write().await }
+    } else {
+        quote! { #inner.read().await }
+    }
+}
+
+/// Builder call marking a handle as locking the plugin's state, if it has mutable state.
+///
+/// A call chain coming back to the plugin while an async export holds the lock then fails
+/// instead of waiting for the lock forever.
+pub(crate) fn lock_state(info: &YapsPluginInfo) -> TokenStream {
+    if info.has_mutable_state() {
+        quote! { .with_lock(&self.state) }
+    } else {
+        quote! {}
+    }
+}
+
+/// Like [`borrow_inner`], from a blocking thread
+pub(crate) fn borrow_inner_blocking(
+    info: &YapsPluginInfo,
+    inner: TokenStream,
+    mutable: bool,
+) -> TokenStream {
+    if !info.has_mutable_state() {
+        inner
+    } else if mutable {
+        quote! { #inner.blocking_write() }
+    } else {
+        quote! { #inner.blocking_read() }
+    }
+}
+
+fn blocking_pool_init(info: &YapsPluginInfo) -> TokenStream {
+    match info.blocking_threads {
+        Some(threads) => quote! { #BlockingPool::new(#threads) },
+        None => quote! { #BlockingPool::default() },
+    }
+}
+
 pub(crate) fn generate_codec_export_bounds(info: &YapsPluginInfo) -> TokenStream {
+    // Channel inputs are decoded one by one
     let in_types = info
         .export_funcs
         .iter()
-        .map(|func| func.args.to_types())
-        .map(utils::punctuated_into_tuple);
+        .map(|func| match &func.channel_input {
+            Some(input_ty) => input_ty.to_token_stream(),
+            None => utils::punctuated_into_tuple(func.args.to_types()),
+        });
     let out_types = info.export_funcs.iter().map(|func| &func.ret_ty);
 
     let decode_for = DecodeFor;
@@ -31,11 +121,19 @@ pub(crate) fn generate_codec_export_bounds(info: &YapsPluginInfo) -> TokenStream
 }
 
 pub(crate) fn generate_codec_extern_bounds(info: &YapsPluginInfo) -> TokenStream {
+    // Published events are encoded like extern arguments
     let in_types = info
         .extern_funcs
         .iter()
-        .map(|func| func.args.to_types())
-        .map(utils::punctuated_into_tuple);
+        .map(|func| match &func.channel_input {
+            Some(input_ty) => input_ty.to_token_stream(),
+            None => utils::punctuated_into_tuple(func.args.to_types()),
+        })
+        .chain(
+            info.publish_funcs
+                .iter()
+                .map(|func| utils::punctuated_into_tuple(func.args.to_types())),
+        );
     let out_types = info.extern_funcs.iter().map(|func| &func.ret_ty);
 
     let decode_for = DecodeFor;
@@ -47,6 +145,20 @@ pub(crate) fn generate_codec_extern_bounds(info: &YapsPluginInfo) -> TokenStream
     }
 }
 
+pub(crate) fn generate_codec_event_bounds(info: &YapsPluginInfo) -> TokenStream {
+    let event_types = info
+        .subscribe_funcs
+        .iter()
+        .map(|func| func.args.to_types())
+        .map(utils::punctuated_into_tuple);
+
+    let decode_for = DecodeFor;
+
+    quote! {
+        #( + #decode_for<C, #event_types> )*
+    }
+}
+
 pub(crate) fn generate_wrapper_struct(info: &mut YapsPluginInfo) -> ItemStruct {
     let struct_ident = &info.struct_ident;
     let wrapper_ident = wrapper_name(&info.struct_ident);
@@ -56,17 +168,26 @@ pub(crate) fn generate_wrapper_struct(info: &mut YapsPluginInfo) -> ItemStruct {
         .extern_funcs
         .iter()
         .map(|func| extern_field_name(&func.ident));
+    let extern_types = info.extern_funcs.iter().map(extern_field_type);
 
-    let once_cell = OnceCell;
-    let box_tok = Box;
-    let func_handle = FuncHandle;
+    let inner_field = if info.has_mutable_state() {
+        quote! { state: #Arc<#RwLock<#struct_ident>> }
+    } else {
+        quote! { pub inner: #Arc<#struct_ident> }
+    };
 
     parse_quote! {
         pub struct #wrapper_ident<D: #YapsData, C: #Codec<Data = D>> {
-            pub inner: #Arc<#struct_ident>,
+            #inner_field,
             codec: #Arc<C>,
+            name: #OnceLock<#String>,
+            events: #OnceLock<#Arc<#EventBus<D>>>,
+            callbacks: #OnceLock<#Arc<#CallbackTable>>,
+            tasks: #Arc<#TaskTracker>,
+            blocking: #Arc<#BlockingPool>,
+            spawner: #SpawnerCell,
 
-            #( #extern_fields: #once_cell<#box_tok<dyn #func_handle<D>>>, )*
+            #( #extern_fields: #extern_types, )*
         }
     }
 }
@@ -78,12 +199,22 @@ pub(crate) fn generate_wrapper_impl(info: &YapsPluginInfo) -> ItemImpl {
     let wrapper_ident = &info.wrapper_ident;
     let struct_ident = &info.struct_ident;
 
+    let inner_field = inner_field(info);
+    let (wrap_inner, inner_struct) = if info.has_mutable_state() {
+        (
+            quote! { #RwLock::new(inner) },
+            quote! { new.state.try_read().expect("the lock isn't shared yet") },
+        )
+    } else {
+        (quote! { inner }, quote! { new.inner })
+    };
+
     let extern_fields = info
         .extern_funcs
         .iter()
         .map(|func| extern_field_name(&func.ident));
-
-    let once_cell = OnceCell;
+    let extern_inits = info.extern_funcs.iter().map(extern_field_init);
+    let blocking_pool = blocking_pool_init(info);
 
     parse_quote! {
         impl<C, D> #wrapper_ident<D, C>
@@ -96,39 +227,264 @@ pub(crate) fn generate_wrapper_impl(info: &YapsPluginInfo) -> ItemImpl {
         {
             pub fn new(inner: #struct_ident, codec: C) -> #Arc<Self> {
                 let new = #Arc::new(Self {
-                    inner: #Arc::new(inner),
+                    #inner_field: #Arc::new(#wrap_inner),
                     codec: #Arc::new(codec),
+                    name: #OnceLock::new(),
+                    events: #OnceLock::new(),
+                    callbacks: #OnceLock::new(),
+                    tasks: #Arc::new(#TaskTracker::new()),
+                    blocking: #Arc::new(#blocking_pool),
+                    spawner: #SpawnerCell::new(),
 
-                    #( #extern_fields: #once_cell::new(), )*
+                    #( #extern_fields: #extern_inits, )*
                 });
 
                 let weak = #Arc::downgrade(&new);
 
-                new.inner
+                #inner_struct
                     .extern_funcs
                     .set(weak)
                     .expect("wrapping a plugin twice is not allowed");
 
                 new
             }
+
+            /// Wraps the plugin with its tasks spawned on `spawner` instead of the hub's
+            pub fn with_spawner(
+                inner: #struct_ident,
+                codec: C,
+                spawner: #Arc<dyn #Spawner>,
+            ) -> #Arc<Self> {
+                let new = Self::new(inner, codec);
+                new.spawner.set(spawner);
+                new
+            }
+
+            /// Wraps the plugin under its own name instead of the struct's, e.g. to tell shards apart
+            pub fn with_name(inner: #struct_ident, codec: C, name: impl Into<#String>) -> #Arc<Self> {
+                let new = Self::new(inner, codec);
+                new.name.set(name.into()).expect("the name isn't set yet");
+                new
+            }
         }
     }
 }
 
+/// Helpers that don't depend on the codec, usable from every generated impl
+pub(crate) fn generate_wrapper_scope_impl(info: &YapsPluginInfo) -> ItemImpl {
+    let wrapper_ident = &info.wrapper_ident;
+    let struct_ident = &info.struct_ident;
+
+    let state = if info.has_mutable_state() {
+        quote! { self.state.read().await }
+    } else {
+        quote! { self.inner.as_ref() }
+    };
+
+    parse_quote! {
+        impl<D: #YapsData, C: #Codec<Data = D>> #wrapper_ident<D, C> {
+            /// Reads the plugin struct, whether it's kept behind a lock or not.
+            ///
+            /// Plugins with `&mut self` exports lock it for reading, waiting for the one running.
+            pub async fn state(&self) -> impl #Deref<Target = #struct_ident> + '_ {
+                #state
+            }
+
+            /// Scope the plugin's tasks run in, e.g. to create callbacks from tasks it spawns
+            pub fn callback_scope(&self) -> #CallbackScope {
+                #CallbackScope::new(
+                    self.callbacks.get().cloned(),
+                    self.spawner.get(),
+                    self.tasks.clone(),
+                )
+            }
+        }
+    }
+}
+
+/// Expression evaluating to the extern's handle if it's available
+fn extern_handle_expr(func: &ExternFunc) -> TokenStream {
+    let field_name = extern_field_name(&func.ident);
+
+    if func.lazy {
+        quote! { self.#field_name.get().await? }
+    } else {
+        quote! { self.#field_name.get() }
+    }
+}
+
 fn generate_wrapper_extern_func_impl(func: &ExternFunc) -> ImplItemFn {
     let sig = &func.sig;
     let field_name = extern_field_name(&func.ident);
     let id_str = LitStr::new(&func.id, func.ident.span());
-    let arg_idents = utils::punctuated_into_tuple(func.args.to_idents());
+    let arg_idents = func.args.to_idents();
+    let arg_tuple = utils::punctuated_into_tuple(arg_idents.clone());
+
+    if func.broadcast {
+        return parse_quote! {
+            #sig {
+                self.#field_name
+                    .call_all_with_codec(self.codec.as_ref(), #arg_tuple)
+                    .await
+            }
+        };
+    }
+
+    if let Some(shard_key) = &func.shard_key {
+        return parse_quote! {
+            #sig {
+                let key = #ShardKey::new(&#shard_key);
+
+                self.#field_name
+                    .call_with_key_codec(self.codec.as_ref(), key, #arg_tuple)
+                    .await
+            }
+        };
+    }
+
+    if func.stream {
+        let func_handle = if func.lazy {
+            quote! { self.#field_name }
+        } else {
+            quote! {
+                self.#field_name
+                    .get()
+                    .ok_or_else(|| #Error::FunctionNotInitialized(#id_str.to_string()))?
+            }
+        };
+
+        let call = match &func.channel_input {
+            Some(_) => quote! { open_channel_with_codec(self.codec.clone(), #arg_idents) },
+            None => quote! { call_stream_with_codec(self.codec.clone(), #arg_tuple) },
+        };
+
+        return parse_quote! {
+            #sig {
+                #func_handle.#call.await
+            }
+        };
+    }
+
+    if func.optional || func.fallback.is_some() {
+        let handle = extern_handle_expr(func);
+
+        let wrap_result = if func.optional {
+            quote! { Some }
+        } else {
+            quote! {}
+        };
+
+        let map_result = func.optional.then(|| quote! { .map(Some) });
+
+        let fallback_call = func.fallback.as_ref().map(|fallback| {
+            quote! { Ok(#wrap_result(self.inner.#fallback(#arg_idents).await)) }
+        });
+
+        let missing = match &fallback_call {
+            Some(fallback_call) => fallback_call.clone(),
+            None => quote! { Ok(None) },
+        };
+
+        // The arguments have to be kept around if the provider's errors can trigger the fallback
+        let (call_args, error_fallback) = if func.fallback_on.is_empty() {
+            (arg_tuple, quote! {})
+        } else {
+            let cloned_args = func
+                .args
+                .0
+                .iter()
+                .map(|(ident, _)| quote! { #ident.clone() });
+            let patterns = func
+                .fallback_on
+                .iter()
+                .map(|variant| quote! { #Error::#variant { .. } });
+
+            (
+                quote! { ( #( #cloned_args, )* ) },
+                quote! {
+                    if matches!(result, Err( #( #patterns )|* )) {
+                        return #fallback_call;
+                    }
+                },
+            )
+        };
+
+        return parse_quote! {
+            #sig {
+                let func = match #handle {
+                    Some(func) => func,
+                    None => return #missing,
+                };
+
+                let result = func.call_with_codec(self.codec.as_ref(), #call_args).await;
+                #error_fallback
+
+                result #map_result
+            }
+        };
+    }
+
+    if func.lazy {
+        return parse_quote! {
+            #sig {
+                self.#field_name
+                    .call_with_codec(self.codec.as_ref(), #arg_tuple)
+                    .await
+            }
+        };
+    }
 
     parse_quote! {
         #sig {
             let func = self
                 .#field_name
                 .get()
-                .ok_or(#Error::FunctionNotInitialized(#id_str.to_string()))?;
+                .ok_or_else(|| #Error::FunctionNotInitialized(#id_str.to_string()))?;
 
-            func.call_with_codec(self.codec.as_ref(), #arg_idents).await
+            func.call_with_codec(self.codec.as_ref(), #arg_tuple).await
+        }
+    }
+}
+
+fn generate_wrapper_publish_impl(func: &PublishFunc) -> ImplItemFn {
+    let sig = &func.sig;
+    let topic_str = LitStr::new(&func.topic, func.ident.span());
+    let arg_tuple = utils::punctuated_into_tuple(func.args.to_idents());
+
+    // Nothing can be subscribed before the plugin is registered, so there's no one to notify
+    parse_quote! {
+        #sig {
+            match self.events.get() {
+                Some(events) => {
+                    events.publish_with_codec(self.codec.as_ref(), #topic_str, #arg_tuple)?;
+                    Ok(())
+                }
+                None => Ok(()),
+            }
+        }
+    }
+}
+
+fn generate_wrapper_has_extern_impl(info: &YapsPluginInfo) -> ImplItemFn {
+    let arms = info.extern_funcs.iter().map(|func| {
+        let id_str = LitStr::new(&func.id, func.ident.span());
+        let field_name = extern_field_name(&func.ident);
+
+        if func.broadcast || func.shard_key.is_some() {
+            quote! { #id_str => !self.#field_name.is_empty() }
+        } else if func.lazy {
+            quote! { #id_str => self.#field_name.is_provided().await }
+        } else {
+            quote! { #id_str => self.#field_name.get().is_some() }
+        }
+    });
+
+    parse_quote! {
+        async fn has_extern(&self, id: &str) -> bool {
+            match id {
+                #( #arms, )*
+                _ => false,
+            }
         }
     }
 }
@@ -143,15 +499,20 @@ pub(crate) fn generate_wrapper_extern_funcs_impl(info: &YapsPluginInfo) -> ItemI
         .extern_funcs
         .iter()
         .map(generate_wrapper_extern_func_impl);
+    let publish_impls = info.publish_funcs.iter().map(generate_wrapper_publish_impl);
+    let has_extern_impl = generate_wrapper_has_extern_impl(info);
 
     parse_quote! {
         #[#async_trait]
         impl<D, C> #extern_funcs_trait for #wrapper_ident<D, C>
         where
             D: #YapsData,
-            C: #Codec<Data = D> #codec_extern_bounds,
+            C: #Codec<Data = D> #codec_extern_bounds + 'static,
         {
             #( #extern_funcs_impls )*
+            #( #publish_impls )*
+
+            #has_extern_impl
         }
     }
 }
@@ -0,0 +1,178 @@
+use darling::FromMeta;
+use proc_macro_error::abort;
+use proc_macro2::TokenStream;
+use quote::ToTokens;
+use syn::{Ident, ImplItem, ItemImpl, ReturnType, Signature, TraitItemFn, parse_quote, parse2};
+
+use crate::{
+    defs::*,
+    utils::{self, FunctionArgs, parse_darling_attr},
+};
+
+use super::{
+    yaps_export::EXPORT_ATTR,
+    yaps_extern::{EXTERN_ATTR, check_reserved_name},
+};
+
+pub const PUBLISH_ATTR: &str = "yaps_publish";
+pub const SUBSCRIBE_ATTR: &str = "yaps_subscribe";
+
+#[derive(Debug, FromMeta, Default, Clone)]
+struct EventFuncArgs {
+    topic: Option<String>,
+}
+
+#[derive(Debug)]
+pub(crate) struct PublishFunc {
+    pub ident: Ident,
+    // Encoded for each subscription, so they have to be `Clone`
+    pub args: FunctionArgs,
+
+    // Declared signature, returning a Result
+    pub sig: Signature,
+
+    pub topic: String,
+}
+
+#[derive(Debug)]
+pub(crate) struct SubscribeFunc {
+    pub is_async: bool,
+    // Returns a `Result`, its errors are reported when the subscription ends
+    pub fallible: bool,
+    pub ident: Ident,
+    pub args: FunctionArgs,
+
+    pub topic: String,
+}
+
+/// Checks whether the item is handled by [`process_event_funcs`] and must be skipped otherwise
+pub(crate) fn is_event_item(item: &ImplItem) -> bool {
+    let attrs = match item {
+        ImplItem::Fn(f) => f.attrs.clone(),
+        ImplItem::Verbatim(ts) => match parse2::<TraitItemFn>(ts.clone()) {
+            Ok(f) => f.attrs,
+            Err(_) => return false,
+        },
+        _ => return false,
+    };
+
+    utils::get_attr(&attrs, PUBLISH_ATTR).is_some()
+        || utils::get_attr(&attrs, SUBSCRIBE_ATTR).is_some()
+}
+
+pub(crate) fn process_event_funcs(item: &mut ItemImpl) -> (Vec<PublishFunc>, Vec<SubscribeFunc>) {
+    for name in [PUBLISH_ATTR, SUBSCRIBE_ATTR] {
+        if let Some(attr) = utils::get_attr(&item.attrs, name) {
+            abort!(attr, "{} has to be set on each function", name)
+        }
+    }
+
+    let mut publish_funcs = Vec::new();
+    let mut subscribe_funcs = Vec::new();
+
+    for item in item.items.iter_mut() {
+        let token_stream = match item {
+            ImplItem::Fn(f) => f.to_token_stream(),
+            ImplItem::Verbatim(ts) => ts.clone(),
+            _ => continue,
+        };
+
+        let Ok(mut func) = parse2::<TraitItemFn>(token_stream) else {
+            continue;
+        };
+
+        if let Some(attr) = utils::pop_attr(&mut func.attrs, PUBLISH_ATTR) {
+            publish_funcs.push(process_publish_fn(&func, parse_darling_attr(&attr)));
+
+            // The declaration is implemented through the extern trait
+            *item = ImplItem::Verbatim(TokenStream::new());
+        } else if let Some(attr) = utils::pop_attr(&mut func.attrs, SUBSCRIBE_ATTR) {
+            subscribe_funcs.push(process_subscribe_fn(&func, parse_darling_attr(&attr)));
+
+            if let ImplItem::Fn(f) = item {
+                f.attrs.retain(|attr| !attr.path().is_ident(SUBSCRIBE_ATTR));
+            }
+        }
+    }
+
+    (publish_funcs, subscribe_funcs)
+}
+
+fn check_receiver(func: &TraitItemFn, kind: &str) {
+    for name in [EXPORT_ATTR, EXTERN_ATTR] {
+        if let Some(attr) = utils::get_attr(&func.attrs, name) {
+            abort!(attr, "{} function can't use {}", kind, name);
+        }
+    }
+
+    match func.sig.receiver() {
+        Some(r) => {
+            if r.reference.is_none() || r.mutability.is_some() {
+                abort!(r, "{} func must take &self", kind)
+            }
+        }
+        None => abort!(func.sig, "{} func must take &self", kind),
+    };
+}
+
+fn topic(args: EventFuncArgs, func: &TraitItemFn) -> String {
+    args.topic.unwrap_or(func.sig.ident.to_string())
+}
+
+fn process_publish_fn(func: &TraitItemFn, args: EventFuncArgs) -> PublishFunc {
+    check_receiver(func, "Publish");
+    check_reserved_name(&func.sig);
+
+    if func.default.is_some() {
+        abort!(
+            func.sig,
+            "Publish funcs are generated, they can't have a body"
+        );
+    }
+
+    if func.sig.asyncness.is_some() {
+        abort!(
+            func.sig,
+            "Publishing doesn't wait for subscribers, declare publish funcs without async"
+        );
+    }
+
+    if let ReturnType::Type(_, ty) = &func.sig.output {
+        abort!(ty, "Publish funcs don't return anything");
+    }
+
+    let mut sig = func.sig.clone();
+    sig.output = parse_quote! { -> #Result<()> };
+
+    PublishFunc {
+        ident: func.sig.ident.clone(),
+        args: FunctionArgs::from(&func.sig),
+        sig,
+        topic: topic(args, func),
+    }
+}
+
+fn process_subscribe_fn(func: &TraitItemFn, args: EventFuncArgs) -> SubscribeFunc {
+    check_receiver(func, "Subscribe");
+
+    if func.default.is_none() {
+        abort!(func.sig, "Subscribe funcs handle events, they need a body");
+    }
+
+    let fallible = match &func.sig.output {
+        ReturnType::Default => false,
+        ReturnType::Type(_, ty) if utils::result_item_type(ty).is_some() => true,
+        ReturnType::Type(_, ty) => abort!(
+            ty,
+            "Subscribe funcs return nothing or a Result, e.g. Result<()>"
+        ),
+    };
+
+    SubscribeFunc {
+        is_async: func.sig.asyncness.is_some(),
+        fallible,
+        ident: func.sig.ident.clone(),
+        args: FunctionArgs::from(&func.sig),
+        topic: topic(args, func),
+    }
+}
@@ -4,7 +4,9 @@ use syn::{Ident, ImplItem, ImplItemFn, ItemImpl, ReturnType, Type, parse_quote};
 
 use crate::{
     utils::{self, FunctionArgs, parse_darling_attr},
-    yaps_plugin::yaps_extern::EXTERN_ATTR,
+    yaps_plugin::{
+        yaps_event::is_event_item, yaps_extern::EXTERN_ATTR, yaps_lifecycle::is_lifecycle_item,
+    },
 };
 
 pub const EXPORT_ATTR: &str = "yaps_export";
@@ -13,15 +15,28 @@ pub const EXPORT_ATTR: &str = "yaps_export";
 struct ExportFuncArgs {
     id: Option<String>,
     namespace: Option<String>,
+    blocking: Option<bool>,
+    serial: Option<bool>,
 }
 
 #[derive(Debug)]
 pub(crate) struct ExportFunc {
     pub is_async: bool,
+    // Takes &mut self, the plugin struct is then kept behind a lock
+    pub is_mut: bool,
+    // Runs on the plugin's blocking pool instead of an actor, calls run concurrently
+    pub is_blocking: bool,
+    // Keeps the calls of sync exports on the actor, one at a time, instead of running them inline
+    pub is_serial: bool,
     pub ident: Ident,
     pub args: FunctionArgs,
     pub ret_ty: Type,
 
+    // Returns `impl Stream<Item = ret_ty>`, each item is sent separately
+    pub is_stream: bool,
+    // Takes `impl Stream<Item = Result<channel_input>>`, opened as a channel by consumers
+    pub channel_input: Option<Type>,
+
     pub id: String,
 }
 
@@ -34,6 +49,10 @@ pub(crate) fn process_export_funcs(item: &mut ItemImpl) -> Vec<ExportFunc> {
 }
 
 fn process_item(item: &mut ImplItem, outer_args: &Option<ExportFuncArgs>) -> Option<ExportFunc> {
+    if is_event_item(item) || is_lifecycle_item(item) {
+        return None;
+    }
+
     let item = match item {
         ImplItem::Fn(f) => f,
         _ => return None,
@@ -53,13 +72,14 @@ fn process_fn_item(item: &ImplItemFn, args: ExportFuncArgs) -> ExportFunc {
         abort!(attr, "Export function can't be extern");
     }
 
-    match item.sig.receiver() {
+    let is_mut = match item.sig.receiver() {
         Some(r) => {
-            if r.reference.is_none() || r.mutability.is_some() {
-                abort!(r, "Export func must take &self");
+            if r.reference.is_none() {
+                abort!(r, "Export func must take &self or &mut self");
             }
+            r.mutability.is_some()
         }
-        None => abort!(item.sig, "Export func must take &self"),
+        None => abort!(item.sig, "Export func must take &self or &mut self"),
     };
 
     let ret_ty = match &item.sig.output {
@@ -67,6 +87,36 @@ fn process_fn_item(item: &ImplItemFn, args: ExportFuncArgs) -> ExportFunc {
         _ => parse_quote! {()},
     };
 
+    let stream_item = utils::stream_item_type(&ret_ty).cloned();
+    let is_stream = stream_item.is_some();
+    let ret_ty = stream_item.unwrap_or(ret_ty);
+
+    let channel_input = utils::channel_input_type(&item.sig).map(|input_ty| {
+        utils::result_item_type(input_ty).unwrap_or_else(|| {
+            abort!(
+                input_ty,
+                "Channel inputs may fail to decode, e.g. impl Stream<Item = Result<T>>"
+            )
+        })
+    });
+
+    let is_blocking = args.blocking.unwrap_or(false);
+    if is_blocking && (item.sig.asyncness.is_some() || is_stream || channel_input.is_some()) {
+        abort!(
+            item.sig,
+            "Blocking exports have to be synchronous and return a single value"
+        );
+    }
+
+    // Async exports keep the write lock while they await, calls coming back to the plugin in
+    // the meantime fail with a reentrancy error. Streams would keep it until they end instead.
+    if is_mut && (is_stream || channel_input.is_some()) {
+        abort!(
+            item.sig,
+            "Streaming and channel exports can't take &mut self, the plugin would stay locked until their stream ends"
+        );
+    }
+
     let mut id = args.id.unwrap_or(item.sig.ident.to_string());
 
     if let Some(namespace) = args.namespace {
@@ -75,9 +125,14 @@ fn process_fn_item(item: &ImplItemFn, args: ExportFuncArgs) -> ExportFunc {
 
     ExportFunc {
         is_async: item.sig.asyncness.is_some(),
+        is_mut,
+        is_blocking,
+        is_serial: args.serial.unwrap_or(false),
         ident: item.sig.ident.clone(),
         args: FunctionArgs::from(&item.sig),
         ret_ty,
+        is_stream,
+        channel_input,
         id,
     }
 }
@@ -95,6 +150,7 @@ fn get_outer_args(item: &mut ItemImpl) -> Option<ExportFuncArgs> {
         abort!(outer_attrs, "{} on impl block cannot set id", EXPORT_ATTR)
     }
 
+    #[allow(clippy::collapsible_if)]
     if let Some(ref mut namespace) = outer_args.namespace {
         if namespace == "auto" {
             *namespace = get_impl_type_string(item);
@@ -141,5 +197,8 @@ fn merge_args(
         }
     };
 
+    args.blocking = args.blocking.or(outer_args.blocking);
+    args.serial = args.serial.or(outer_args.serial);
+
     Some(args)
 }
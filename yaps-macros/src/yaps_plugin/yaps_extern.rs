@@ -1,7 +1,9 @@
+use darling::util::PathList;
 use proc_macro2::TokenStream;
-use quote::ToTokens;
+use quote::{ToTokens, format_ident};
 use syn::{
-    Ident, ImplItem, ItemImpl, ReturnType, Signature, TraitItemFn, Type, parse_quote, parse2,
+    FnArg, Ident, ImplItem, ItemImpl, Path, ReturnType, Signature, TraitItemFn, Type, parse_quote,
+    parse2,
 };
 
 use crate::{defs::*, utils::parse_darling_attr};
@@ -11,14 +13,34 @@ use proc_macro_error::abort;
 
 use crate::utils::{self, FunctionArgs};
 
-use super::yaps_export::EXPORT_ATTR;
+use super::{
+    yaps_event::is_event_item, yaps_export::EXPORT_ATTR, yaps_lifecycle::is_lifecycle_item,
+};
 
 pub const EXTERN_ATTR: &str = "yaps_extern";
 
+/// Generated on the plugin struct to check whether an extern is provided, so it can't be declared
+pub const HAS_EXTERN: &str = "has_extern";
+
+pub(crate) fn check_reserved_name(sig: &Signature) {
+    if sig.ident == HAS_EXTERN {
+        abort!(
+            sig.ident,
+            "'{}' is generated to check whether an extern is provided, pick another name",
+            HAS_EXTERN
+        );
+    }
+}
+
 #[derive(Debug, FromMeta, Default, Clone)]
 struct ExternFuncArgs {
     id: Option<String>,
     namespace: Option<String>,
+    lazy: Option<bool>,
+    optional: Option<bool>,
+    broadcast: Option<bool>,
+    shard_key: Option<String>,
+    fallback_on: Option<PathList>,
 }
 
 #[derive(Debug)]
@@ -31,6 +53,33 @@ pub(crate) struct ExternFunc {
     pub sig: Signature,
 
     pub id: String,
+
+    // Resolve the function on its first call instead of when connecting
+    pub lazy: bool,
+
+    // Return None instead of failing if the function isn't provided
+    pub optional: bool,
+
+    // Call every provider of the function, `ret_ty` is then the type returned by each of them.
+    // The arguments are encoded for each provider, so they have to be `Clone`
+    pub broadcast: bool,
+
+    // Declared as returning `impl Stream<Item = Result<ret_ty>>`, rewritten to return a
+    // `BoxStream<'static, Result<ret_ty>>` in `sig`
+    pub stream: bool,
+    // Declared as taking `impl Stream<Item = channel_input>`, opening a channel with the provider.
+    // The argument is rewritten to a `BoxStream<'static, channel_input>` in `sig`
+    pub channel_input: Option<Type>,
+
+    // Argument routing the call to one of the providers of the function
+    pub shard_key: Option<Ident>,
+
+    // Plugin struct method containing the body the extern was declared with
+    pub fallback: Option<Ident>,
+    // Error variants returned by the provider that also trigger the fallback.
+    // The fallback needs the arguments after the provider was called, so they have to be `Clone`
+    // and are cloned on every call
+    pub fallback_on: Vec<Path>,
 }
 
 pub(crate) fn process_extern_funcs(item: &mut ItemImpl) -> Vec<ExternFunc> {
@@ -42,6 +91,10 @@ pub(crate) fn process_extern_funcs(item: &mut ItemImpl) -> Vec<ExternFunc> {
 }
 
 fn process_item(item: &mut ImplItem, outer_args: &Option<ExternFuncArgs>) -> Option<ExternFunc> {
+    if is_event_item(item) || is_lifecycle_item(item) {
+        return None;
+    }
+
     let token_stream = match item {
         ImplItem::Fn(f) => f.to_token_stream(),
         ImplItem::Verbatim(ts) => ts.clone(),
@@ -54,9 +107,32 @@ fn process_item(item: &mut ImplItem, outer_args: &Option<ExternFuncArgs>) -> Opt
 
     let args = merge_args(args, outer_args)?;
 
-    *item = ImplItem::Verbatim(TokenStream::new());
+    let extern_func = process_fn_item(&func, args);
+
+    *item = match (&extern_func.fallback, &func.default) {
+        // The body is kept as a plugin method, called when the extern can't be
+        (Some(fallback), Some(body)) => {
+            let mut sig = func.sig.clone();
+            sig.ident = fallback.clone();
 
-    Some(process_fn_item(&func, args))
+            let attrs = func
+                .attrs
+                .iter()
+                .filter(|attr| !attr.path().is_ident(EXTERN_ATTR));
+
+            ImplItem::Fn(parse_quote! {
+                #( #attrs )*
+                #sig #body
+            })
+        }
+        _ => ImplItem::Verbatim(TokenStream::new()),
+    };
+
+    Some(extern_func)
+}
+
+fn fallback_name(func_name: &Ident) -> Ident {
+    format_ident!("__yaps_fallback_{}", func_name)
 }
 
 fn process_fn_item(item: &TraitItemFn, args: ExternFuncArgs) -> ExternFunc {
@@ -80,6 +156,8 @@ fn process_fn_item(item: &TraitItemFn, args: ExternFuncArgs) -> ExternFunc {
         None => abort!(item.sig, "Extern func takes &self"),
     };
 
+    check_reserved_name(&item.sig);
+
     let mut sig = item.sig.clone();
 
     let ret_ty = match sig.output {
@@ -87,8 +165,100 @@ fn process_fn_item(item: &TraitItemFn, args: ExternFuncArgs) -> ExternFunc {
         ReturnType::Default => parse_quote! {()},
     };
 
-    // Wrap the return type in the signature with Result
-    sig.output = parse_quote! { -> #Result<#ret_ty> };
+    let optional = args.optional.unwrap_or(false);
+    let lazy = args.lazy.unwrap_or(false);
+    let broadcast = args.broadcast.unwrap_or(false);
+
+    let fallback = item
+        .default
+        .as_ref()
+        .map(|_| fallback_name(&item.sig.ident));
+    let fallback_on = args.fallback_on.map(|p| p.to_vec()).unwrap_or_default();
+
+    if fallback.is_none() && !fallback_on.is_empty() {
+        abort!(
+            item.sig,
+            "fallback_on requires the extern to have a fallback body"
+        );
+    }
+
+    if broadcast && (lazy || optional || fallback.is_some()) {
+        abort!(
+            item.sig,
+            "Broadcast externs can't be lazy, optional or have a fallback body"
+        );
+    }
+
+    let shard_key = args.shard_key.map(|name| {
+        if broadcast || lazy || optional || fallback.is_some() {
+            abort!(
+                item.sig,
+                "Sharded externs can't be broadcast, lazy, optional or have a fallback body"
+            );
+        }
+
+        FunctionArgs::from(&item.sig)
+            .0
+            .into_iter()
+            .map(|(ident, _)| ident)
+            .find(|ident| *ident == name)
+            .unwrap_or_else(|| abort!(item.sig, "No argument named {} to use as shard key", name))
+    });
+
+    let stream_item = utils::stream_item_type(&ret_ty).map(|item_ty| {
+        if broadcast || optional || fallback.is_some() || shard_key.is_some() {
+            abort!(
+                item.sig,
+                "Streaming externs can't be broadcast, optional, sharded or have a fallback body"
+            );
+        }
+
+        utils::result_item_type(item_ty).unwrap_or_else(|| {
+            abort!(
+                item_ty,
+                "Streaming externs yield results, e.g. impl Stream<Item = Result<T>>"
+            )
+        })
+    });
+    let stream = stream_item.is_some();
+
+    let channel_input = utils::channel_input_type(&item.sig).cloned();
+    if let Some(input_ty) = &channel_input {
+        if !stream {
+            abort!(
+                item.sig,
+                "Channel externs return their outputs, e.g. impl Stream<Item = Result<T>>"
+            );
+        }
+
+        // Trait objects can't take impl arguments, so the inputs are boxed
+        for input in sig.inputs.iter_mut() {
+            if let FnArg::Typed(t) = input {
+                *t.ty = parse_quote! { #BoxStream<'static, #input_ty> };
+            }
+        }
+    }
+
+    let ret_ty = if let Some(item_ty) = stream_item {
+        item_ty
+    } else if broadcast {
+        broadcast_item_type(&ret_ty).unwrap_or_else(|| {
+            abort!(
+                sig.output,
+                "Broadcast externs return one result per provider, e.g. Vec<Result<T>>"
+            )
+        })
+    } else {
+        ret_ty
+    };
+
+    // Wrap the return type in the signature with Result (and Option if the extern is optional)
+    sig.output = match &sig.output {
+        _ if optional => parse_quote! { -> #Result<#Option<#ret_ty>> },
+        _ if stream => parse_quote! { -> #Result<#BoxStream<'static, #Result<#ret_ty>>> },
+        ReturnType::Type(_, declared) if broadcast => parse_quote! { -> #Result<#declared> },
+        _ => parse_quote! { -> #Result<#ret_ty> },
+    };
 
     let mut id = args.id.unwrap_or(item.sig.ident.to_string());
 
@@ -102,9 +272,22 @@ fn process_fn_item(item: &TraitItemFn, args: ExternFuncArgs) -> ExternFunc {
         args: FunctionArgs::from(&item.sig),
         sig,
         ret_ty,
+        lazy,
+        optional,
+        broadcast,
+        stream,
+        channel_input,
+        shard_key,
+        fallback,
+        fallback_on,
     }
 }
 
+/// Extracts `T` from `Vec<Result<T>>`
+fn broadcast_item_type(ty: &Type) -> Option<Type> {
+    utils::result_item_type(utils::first_generic(ty, "Vec")?)
+}
+
 fn merge_args(
     args: Option<ExternFuncArgs>,
     outer_args: &Option<ExternFuncArgs>,
@@ -128,6 +311,11 @@ fn merge_args(
         }
     };
 
+    args.lazy = args.lazy.or(outer_args.lazy);
+    args.optional = args.optional.or(outer_args.optional);
+    args.broadcast = args.broadcast.or(outer_args.broadcast);
+    args.shard_key = args.shard_key.or(outer_args.shard_key.clone());
+
     Some(args)
 }
 
@@ -144,5 +332,13 @@ fn get_outer_args(item: &mut ItemImpl) -> Option<ExternFuncArgs> {
         abort!(outer_attrs, "{} on impl block cannot set id", EXTERN_ATTR)
     }
 
+    if outer_args.fallback_on.is_some() {
+        abort!(
+            outer_attrs,
+            "{} on impl block cannot set fallback_on, set it on the externs with a fallback body",
+            EXTERN_ATTR
+        )
+    }
+
     Some(outer_args)
 }
@@ -1,11 +1,20 @@
 use syn::ItemImpl;
 
 use super::{
-    yaps_export::process_export_funcs, yaps_extern::process_extern_funcs,
+    yaps_event::process_event_funcs, yaps_export::process_export_funcs,
+    yaps_extern::process_extern_funcs, yaps_lifecycle::process_lifecycle_funcs,
     yaps_plugin_macro::YapsPluginInfo,
 };
 
 pub(crate) fn process_impl(item: &mut ItemImpl, info: &mut YapsPluginInfo) {
     info.export_funcs.append(&mut process_export_funcs(item));
     info.extern_funcs.append(&mut process_extern_funcs(item));
+
+    // Event and lifecycle funcs are skipped by the above, even in export or extern impl blocks
+    let (mut publish_funcs, mut subscribe_funcs) = process_event_funcs(item);
+    info.publish_funcs.append(&mut publish_funcs);
+    info.subscribe_funcs.append(&mut subscribe_funcs);
+
+    info.lifecycle_funcs
+        .append(&mut process_lifecycle_funcs(item));
 }
@@ -0,0 +1,120 @@
+use proc_macro_error::abort;
+use syn::{Attribute, Ident, ImplItem, ImplItemFn, ItemImpl, ReturnType, parse2};
+
+use crate::utils;
+
+use super::{yaps_export::EXPORT_ATTR, yaps_extern::EXTERN_ATTR};
+
+pub const INIT_ATTR: &str = "yaps_init";
+pub const READY_ATTR: &str = "yaps_ready";
+pub const SHUTDOWN_ATTR: &str = "yaps_shutdown";
+
+const LIFECYCLE_ATTRS: [&str; 3] = [INIT_ATTR, READY_ATTR, SHUTDOWN_ATTR];
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub(crate) enum Hook {
+    Init,
+    Ready,
+    Shutdown,
+}
+
+impl Hook {
+    fn from_attr(attr: &Attribute) -> Option<Self> {
+        let path = attr.path();
+
+        if path.is_ident(INIT_ATTR) {
+            Some(Hook::Init)
+        } else if path.is_ident(READY_ATTR) {
+            Some(Hook::Ready)
+        } else if path.is_ident(SHUTDOWN_ATTR) {
+            Some(Hook::Shutdown)
+        } else {
+            None
+        }
+    }
+}
+
+#[derive(Debug)]
+pub(crate) struct LifecycleFunc {
+    pub hook: Hook,
+    pub is_async: bool,
+    pub ident: Ident,
+
+    // Declared as returning a Result, failing the hook on error
+    pub fallible: bool,
+}
+
+/// Checks whether the item is handled by [`process_lifecycle_funcs`] and must be skipped otherwise
+pub(crate) fn is_lifecycle_item(item: &ImplItem) -> bool {
+    match item {
+        ImplItem::Fn(f) => f.attrs.iter().any(|attr| Hook::from_attr(attr).is_some()),
+        ImplItem::Verbatim(ts) => parse2::<ImplItemFn>(ts.clone())
+            .is_ok_and(|f| f.attrs.iter().any(|attr| Hook::from_attr(attr).is_some())),
+        _ => false,
+    }
+}
+
+pub(crate) fn process_lifecycle_funcs(item: &mut ItemImpl) -> Vec<LifecycleFunc> {
+    for name in LIFECYCLE_ATTRS {
+        if let Some(attr) = utils::get_attr(&item.attrs, name) {
+            abort!(attr, "{} has to be set on each function", name)
+        }
+    }
+
+    item.items
+        .iter_mut()
+        .filter_map(|item| match item {
+            ImplItem::Fn(f) => process_fn_item(f),
+            _ => None,
+        })
+        .collect()
+}
+
+fn process_fn_item(func: &mut ImplItemFn) -> Option<LifecycleFunc> {
+    let mut hooks = func.attrs.iter().filter_map(Hook::from_attr);
+    let hook = hooks.next()?;
+
+    if hooks.next().is_some() {
+        abort!(
+            func.sig,
+            "A function can only be used for one lifecycle hook"
+        );
+    }
+
+    for name in [EXPORT_ATTR, EXTERN_ATTR] {
+        if let Some(attr) = utils::get_attr(&func.attrs, name) {
+            abort!(attr, "Lifecycle hooks can't use {}", name);
+        }
+    }
+
+    match func.sig.receiver() {
+        Some(r) => {
+            if r.reference.is_none() || r.mutability.is_some() {
+                abort!(r, "Lifecycle hooks must take &self")
+            }
+        }
+        None => abort!(func.sig, "Lifecycle hooks must take &self"),
+    };
+
+    if func.sig.inputs.len() > 1 {
+        abort!(func.sig.inputs, "Lifecycle hooks don't take arguments");
+    }
+
+    let fallible = match &func.sig.output {
+        ReturnType::Default => false,
+        ReturnType::Type(_, ty) if utils::result_item_type(ty).is_some() => true,
+        ReturnType::Type(_, ty) => abort!(
+            ty,
+            "Lifecycle hooks return nothing or a Result, e.g. Result<()>"
+        ),
+    };
+
+    func.attrs.retain(|attr| Hook::from_attr(attr).is_none());
+
+    Some(LifecycleFunc {
+        hook,
+        is_async: func.sig.asyncness.is_some(),
+        ident: func.sig.ident.clone(),
+        fallible,
+    })
+}
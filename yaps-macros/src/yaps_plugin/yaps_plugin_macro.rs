@@ -1,5 +1,7 @@
 use proc_macro2::Span;
-use syn::{Generics, Ident, Item, ItemImpl, ItemMod, ItemStruct, Meta, Type, parse_quote};
+use syn::{
+    Generics, Ident, Item, ItemImpl, ItemMod, ItemStruct, Meta, Type, Visibility, parse_quote,
+};
 
 use darling::FromMeta;
 use proc_macro_error::abort;
@@ -8,15 +10,18 @@ use super::{
     consumer_provider::{generate_consumer_impl, generate_provider_impl},
     extern_trait::*,
     wrapper::*,
+    yaps_event::{PublishFunc, SubscribeFunc},
     yaps_export::ExportFunc,
     yaps_extern::ExternFunc,
     yaps_impl::process_impl,
+    yaps_lifecycle::LifecycleFunc,
     yaps_struct::process_struct,
 };
 
 #[derive(FromMeta, Debug)]
 struct YapsPluginArgs {
     pub struct_name: Option<String>,
+    pub blocking_threads: Option<usize>,
 }
 
 fn get_plugin_struct<'a>(
@@ -80,7 +85,7 @@ fn get_plugin_impls<'a>(content: &'a mut [Item], struct_ident: &Ident) -> Vec<&'
 fn generate_imports() -> Item {
     parse_quote! {
         use ::yaps_core::{
-            FuncHandle as _,
+            FuncHandle as _, FuncProvider as _,
         };
     }
 }
@@ -90,13 +95,29 @@ pub(crate) struct YapsPluginInfo {
     pub struct_ident: Ident,
     pub struct_generics: Generics,
 
+    pub struct_vis: Visibility,
     pub extern_funcs_trait: Ident,
     pub wrapper_ident: Ident,
 
     pub plugin_name: String,
 
+    // Size of the pool running the blocking exports, the default one if unset
+    pub blocking_threads: Option<usize>,
+
     pub export_funcs: Vec<ExportFunc>,
     pub extern_funcs: Vec<ExternFunc>,
+
+    pub publish_funcs: Vec<PublishFunc>,
+    pub subscribe_funcs: Vec<SubscribeFunc>,
+
+    pub lifecycle_funcs: Vec<LifecycleFunc>,
+}
+
+impl YapsPluginInfo {
+    /// Whether an export mutates the plugin struct, which the wrapper then keeps behind a lock
+    pub fn has_mutable_state(&self) -> bool {
+        self.export_funcs.iter().any(|func| func.is_mut)
+    }
 }
 
 impl Default for YapsPluginInfo {
@@ -104,11 +125,16 @@ impl Default for YapsPluginInfo {
         YapsPluginInfo {
             struct_ident: Ident::new("NIL", Span::call_site()),
             struct_generics: Generics::default(),
+            struct_vis: Visibility::Inherited,
             extern_funcs_trait: Ident::new("NIL", Span::call_site()),
             wrapper_ident: Ident::new("NIL", Span::call_site()),
             plugin_name: String::from("NIL"),
+            blocking_threads: None,
             export_funcs: Vec::new(),
             extern_funcs: Vec::new(),
+            publish_funcs: Vec::new(),
+            subscribe_funcs: Vec::new(),
+            lifecycle_funcs: Vec::new(),
         }
     }
 }
@@ -124,7 +150,10 @@ pub(crate) fn process_yaps_module(module: &mut ItemMod, args_meta: &Meta) {
         None => abort!(module, "Yaps module cannot be empty"),
     };
 
-    let mut plugin_info = YapsPluginInfo::default();
+    let mut plugin_info = YapsPluginInfo {
+        blocking_threads: args.blocking_threads,
+        ..Default::default()
+    };
 
     {
         let plugin_struct = get_plugin_struct(content, args.struct_name, args_meta);
@@ -138,6 +167,21 @@ pub(crate) fn process_yaps_module(module: &mut ItemMod, args_meta: &Meta) {
         }
     }
 
+    // The wrapper runs fallbacks on a read borrow of the state. It would wait forever for the
+    // write lock of an async &mut self export calling the extern, and even under a read lock
+    // the fair lock queues it behind any writer waiting for the export to finish.
+    if plugin_info.has_mutable_state()
+        && let Some(func) = plugin_info
+            .extern_funcs
+            .iter()
+            .find(|func| func.fallback.is_some())
+    {
+        abort!(
+            func.ident,
+            "Plugins with &mut self exports can't have fallback externs, the fallback would wait for the lock held by the export calling the extern"
+        )
+    }
+
     content.insert(0, generate_imports());
 
     // TODO: add helper `InnerStruct.wrap(self) -> Wrapper` method
@@ -147,6 +191,7 @@ pub(crate) fn process_yaps_module(module: &mut ItemMod, args_meta: &Meta) {
 
     content.push(Item::Struct(generate_wrapper_struct(&mut plugin_info)));
     content.push(Item::Impl(generate_wrapper_impl(&plugin_info)));
+    content.push(Item::Impl(generate_wrapper_scope_impl(&plugin_info)));
     content.push(Item::Impl(generate_wrapper_extern_funcs_impl(&plugin_info)));
 
     content.push(Item::Impl(generate_provider_impl(&plugin_info)));
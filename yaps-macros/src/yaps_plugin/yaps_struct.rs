@@ -11,7 +11,9 @@ fn extern_funcs_trait_name(struct_ident: &Ident) -> Ident {
 
 pub(crate) fn process_struct(item: &mut ItemStruct, info: &mut YapsPluginInfo) {
     info.struct_ident = item.ident.clone();
+    info.plugin_name = item.ident.to_string();
     info.struct_generics = item.generics.clone();
+    info.struct_vis = item.vis.clone();
 
     let extern_funcs_trait = extern_funcs_trait_name(&info.struct_ident);
     info.extern_funcs_trait = extern_funcs_trait.clone();
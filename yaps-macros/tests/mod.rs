@@ -1 +1 @@
-// mod yaps_plugin;
+mod yaps_plugin;
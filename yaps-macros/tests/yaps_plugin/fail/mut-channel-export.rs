@@ -0,0 +1,22 @@
+use yaps_macros::yaps_plugin;
+
+#[yaps_plugin]
+mod counter {
+    #[derive(Default)]
+    pub struct Counter {
+        count: u64,
+    }
+
+    #[yaps_export]
+    impl Counter {
+        async fn count_all(
+            &mut self,
+            _items: impl yaps_core::futures::Stream<Item = yaps_core::Result<u64>>,
+        ) -> u64 {
+            self.count += 1;
+            self.count
+        }
+    }
+}
+
+fn main() {}
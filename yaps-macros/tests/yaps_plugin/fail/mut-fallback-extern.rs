@@ -0,0 +1,26 @@
+use yaps_macros::yaps_plugin;
+
+#[yaps_plugin]
+mod counter {
+    #[derive(Default)]
+    pub struct Counter {
+        count: i32,
+    }
+
+    #[yaps_extern(namespace = "Adder")]
+    impl Counter {
+        async fn add(&self, a: i32, b: i32) -> i32 {
+            a + b
+        }
+    }
+
+    #[yaps_export]
+    impl Counter {
+        fn increment(&mut self) -> i32 {
+            self.count += 1;
+            self.count
+        }
+    }
+}
+
+fn main() {}
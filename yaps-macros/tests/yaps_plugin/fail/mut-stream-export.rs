@@ -0,0 +1,19 @@
+use yaps_macros::yaps_plugin;
+
+#[yaps_plugin]
+mod counter {
+    #[derive(Default)]
+    pub struct Counter {
+        count: u64,
+    }
+
+    #[yaps_export]
+    impl Counter {
+        fn count_up(&mut self, steps: u64) -> impl yaps_core::futures::Stream<Item = u64> {
+            self.count += steps;
+            yaps_core::futures::stream::iter(0..self.count)
+        }
+    }
+}
+
+fn main() {}
@@ -17,3 +17,9 @@ fn trybuild_tests() {
     // TODO: Add failing cases with friendly error messages
 }
 */
+
+#[test]
+fn compile_fail_tests() {
+    let t = trybuild::TestCases::new();
+    t.compile_fail("tests/yaps_plugin/fail/*.rs");
+}